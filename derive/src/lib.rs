@@ -0,0 +1,60 @@
+//! Derive macros for `bevy-realtime`, re-exported from the main crate.
+
+use proc_macro::TokenStream;
+use quote::quote;
+use syn::{parse_macro_input, DeriveInput, LitStr, Type};
+
+/// Implements `bevy_realtime::rpc::RpcRequest`. The response type is set with
+/// `#[rpc(response = Type)]`, and the name defaults to the type's name unless set with
+/// `#[rpc(name = "...")]`.
+#[proc_macro_derive(RpcRequest, attributes(rpc))]
+pub fn derive_rpc_request(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+
+    match rpc_request(input) {
+        Ok(tokens) => tokens.into(),
+        Err(e) => e.to_compile_error().into(),
+    }
+}
+
+fn rpc_request(input: DeriveInput) -> syn::Result<proc_macro2::TokenStream> {
+    let mut response: Option<Type> = None;
+    let mut name: Option<LitStr> = None;
+
+    for attr in input
+        .attrs
+        .iter()
+        .filter(|attr| attr.path().is_ident("rpc"))
+    {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("response") {
+                response = Some(meta.value()?.parse()?);
+                Ok(())
+            } else if meta.path.is_ident("name") {
+                name = Some(meta.value()?.parse()?);
+                Ok(())
+            } else {
+                Err(meta.error("expected `response` or `name`"))
+            }
+        })?;
+    }
+
+    let ident = &input.ident;
+
+    let Some(response) = response else {
+        return Err(syn::Error::new_spanned(
+            ident,
+            "missing `#[rpc(response = Type)]`",
+        ));
+    };
+
+    let name = name.unwrap_or_else(|| LitStr::new(&ident.to_string(), ident.span()));
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+
+    Ok(quote! {
+        impl #impl_generics ::bevy_realtime::rpc::RpcRequest for #ident #ty_generics #where_clause {
+            const NAME: &'static str = #name;
+            type Response = #response;
+        }
+    })
+}
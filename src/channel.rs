@@ -4,28 +4,38 @@ use bevy::{
     prelude::In,
 };
 use bevy_crossbeam_event::CrossbeamEventSender;
-use crossbeam::channel::{unbounded, Receiver, SendError, Sender};
+use crossbeam::channel::{SendError, Sender};
 use serde_json::Value;
 use uuid::Uuid;
 
-use super::client::ClientManager;
+use super::client::{ClientManager, ReconnectFn};
 use crate::{
     message::{
         payload::{
             AccessTokenPayload, BroadcastConfig, BroadcastPayload, JoinConfig, JoinPayload,
-            Payload, PayloadStatus, PostgresChange, PostgresChangesEvent, PostgresChangesPayload,
-            PresenceConfig,
+            Payload, PostgresChange, PostgresChangesEvent, PostgresChangesPayload, PresenceConfig,
         },
         postgres_change_filter::PostgresChangeFilter,
         realtime_message::{MessageEvent, RealtimeMessage},
     },
+    outbound::{
+        bounded_channel, BoundedMessage, BoundedReceiver, BoundedSender, MessagePriority,
+        OfflineBuffer, OutboundDepth, OutboundMessage, OutboundQueue, OverflowPolicy, QueueBound,
+        SendPolicy,
+    },
     presence::PresenceCallbackEvent,
+    push::{Push, PushKind, PushReply, PushStatus, ReplyStatus},
 };
 
 use super::client::Client;
 use crate::presence::{Presence, PresenceCallback, PresenceEvent, PresenceState};
 use std::fmt::Debug;
-use std::{collections::HashMap, error::Error};
+use std::{
+    collections::HashMap,
+    error::Error,
+    sync::Arc,
+    time::{Duration, Instant},
+};
 
 #[derive(Clone)]
 struct BroadcastCallback(SystemId<In<HashMap<String, Value>>>);
@@ -43,6 +53,58 @@ pub struct PostgresChangesCallbackEvent(
     pub (SystemId<In<PostgresChangesPayload>>, PostgresChangesPayload),
 );
 
+/// Identifies a broadcast sent through [ChannelManager], see [BroadcastAck]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct BroadcastHandle(pub Uuid);
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum BroadcastAckResult {
+    Ok,
+    /// Server rejected the broadcast, with its response
+    Error(Value),
+    /// No reply within the channel's [ChannelBuilder::broadcast_ack_timeout]
+    Timeout,
+}
+
+/// Sent when the server acknowledges a broadcast, or the ack times out.
+///
+/// Only sent for channels with [BroadcastConfig::ack] enabled.
+#[derive(Event, Debug, Clone)]
+pub struct BroadcastAck {
+    /// Name of the client, see [crate::client::ClientBuilder::name]
+    pub client: Option<String>,
+    pub handle: BroadcastHandle,
+    pub result: BroadcastAckResult,
+}
+
+/// Why a channel closed
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum CloseReason {
+    /// The client left the channel
+    Leave,
+    /// The server closed the channel
+    Server,
+}
+
+/// Sent when a channel is errored or closed. `client` is the name of the channel's client, see
+/// [crate::client::ClientBuilder::name].
+#[derive(Event, Debug, Clone, PartialEq)]
+pub enum ChannelLifecycleEvent {
+    /// The channel process failed on the server. The channel rejoins automatically, see
+    /// [ChannelBuilder::rejoin_interval].
+    Errored {
+        client: Option<String>,
+        topic: String,
+    },
+    /// The channel closed. A [CloseReason::Server] close rejoins automatically like
+    /// [ChannelLifecycleEvent::Errored], a [CloseReason::Leave] close does not.
+    Closed {
+        client: Option<String>,
+        topic: String,
+        reason: CloseReason,
+    },
+}
+
 /// Channel states
 #[derive(PartialEq, Clone, Copy, Debug)]
 pub enum ChannelState {
@@ -55,18 +117,29 @@ pub enum ChannelState {
 
 #[derive(Clone)]
 pub struct ChannelManager {
+    /// Sending through this directly skips [crate::client::ClientBuilder::manager_queue_bound]
     pub tx: Sender<ChannelManagerMessage>,
+    bounded_tx: BoundedSender<ChannelManagerMessage>,
+    outbound_depth: OutboundDepth,
+    outbound_bound: Option<QueueBound>,
 }
 
 pub enum ChannelManagerMessage {
     Broadcast {
         payload: BroadcastPayload,
+        policy: Option<SendPolicy>,
+        handle: BroadcastHandle,
     },
     Subscribe,
+    Unsubscribe,
     Track {
         payload: HashMap<String, Value>,
     },
     Untrack,
+    Push {
+        message: Box<RealtimeMessage>,
+        push: Push,
+    },
     PresenceState {
         callback: SystemId<In<PresenceState>>,
     },
@@ -75,34 +148,106 @@ pub enum ChannelManagerMessage {
     },
 }
 
+impl BoundedMessage for ChannelManagerMessage {
+    fn bounded(&self) -> bool {
+        matches!(self, ChannelManagerMessage::Broadcast { .. })
+    }
+}
+
 impl ChannelManager {
+    /// Broadcast a payload, using the [SendPolicy] registered for its event with
+    /// [ChannelBuilder::broadcast_policy], or [SendPolicy::Reliable]
+    ///
+    /// Returns a handle matching the [BroadcastAck] sent if the channel has acks enabled.
+    /// Errors if the outbound queue is full and bounded with [OverflowPolicy::Reject].
     pub fn broadcast(
         &self,
         payload: BroadcastPayload,
-    ) -> Result<(), SendError<ChannelManagerMessage>> {
-        self.tx.send(ChannelManagerMessage::Broadcast { payload })
+    ) -> Result<BroadcastHandle, SendError<ChannelManagerMessage>> {
+        self.send_broadcast(payload, None)
+    }
+
+    /// Broadcast a payload with the provided [SendPolicy], overriding any registered policy
+    pub fn broadcast_with_policy(
+        &self,
+        payload: BroadcastPayload,
+        policy: SendPolicy,
+    ) -> Result<BroadcastHandle, SendError<ChannelManagerMessage>> {
+        self.send_broadcast(payload, Some(policy))
+    }
+
+    fn send_broadcast(
+        &self,
+        payload: BroadcastPayload,
+        policy: Option<SendPolicy>,
+    ) -> Result<BroadcastHandle, SendError<ChannelManagerMessage>> {
+        let handle = BroadcastHandle(Uuid::new_v4());
+
+        let message = ChannelManagerMessage::Broadcast {
+            payload,
+            policy,
+            handle,
+        };
+
+        if let Some(QueueBound {
+            capacity,
+            overflow: OverflowPolicy::Reject,
+        }) = self.outbound_bound
+        {
+            if self.outbound_depth.get() >= capacity {
+                return Err(SendError(message));
+            }
+        }
+
+        self.bounded_tx.send(message).map(|()| handle)
+    }
+
+    /// Number of messages waiting for the channel to process them
+    pub fn queue_depth(&self) -> usize {
+        self.bounded_tx.len()
     }
 
     pub fn subscribe(&self) -> Result<(), SendError<ChannelManagerMessage>> {
-        self.tx.send(ChannelManagerMessage::Subscribe)
+        self.bounded_tx.send(ChannelManagerMessage::Subscribe)
+    }
+
+    /// Leave the channel. Once the leave completes the client drops the channel, so it won't
+    /// be rejoined on reconnect; build a new channel to join the topic again.
+    pub fn unsubscribe(&self) -> Result<(), SendError<ChannelManagerMessage>> {
+        self.bounded_tx.send(ChannelManagerMessage::Unsubscribe)
     }
 
     pub fn track(
         &self,
         payload: HashMap<String, Value>,
     ) -> Result<(), SendError<ChannelManagerMessage>> {
-        self.tx.send(ChannelManagerMessage::Track { payload })
+        self.bounded_tx
+            .send(ChannelManagerMessage::Track { payload })
     }
 
     pub fn untrack(&self) -> Result<(), SendError<ChannelManagerMessage>> {
-        self.tx.send(ChannelManagerMessage::Untrack)
+        self.bounded_tx.send(ChannelManagerMessage::Untrack)
+    }
+
+    /// Send `message` on the channel as a tracked [Push], running its hooks on the reply. The
+    /// topic is set to the channel's. Label the push with [PushKind::Custom] so its [PushReply]
+    /// isn't mistaken for one of the channel's own operations.
+    pub fn push(
+        &self,
+        message: RealtimeMessage,
+        push: Push,
+    ) -> Result<(), SendError<ChannelManagerMessage>> {
+        self.bounded_tx.send(ChannelManagerMessage::Push {
+            message: Box::new(message),
+            push,
+        })
     }
 
     pub fn presence_state(
         &self,
         callback: SystemId<In<PresenceState>>,
     ) -> Result<(), SendError<ChannelManagerMessage>> {
-        self.tx
+        self.bounded_tx
             .send(ChannelManagerMessage::PresenceState { callback })
     }
 
@@ -110,7 +255,7 @@ impl ChannelManager {
         &self,
         callback: SystemId<In<ChannelState>>,
     ) -> Result<(), SendError<ChannelManagerMessage>> {
-        self.tx
+        self.bounded_tx
             .send(ChannelManagerMessage::ChannelState { callback })
     }
 }
@@ -128,15 +273,32 @@ pub struct RealtimeChannel {
     pub(crate) id: Uuid,
     postgres_changes_callbacks: HashMap<PostgresChangesEvent, Vec<PostgresChangesCallback>>,
     broadcast_callbacks: HashMap<String, Vec<BroadcastCallback>>,
+    broadcast_policies: HashMap<String, SendPolicy>,
+    offline_buffer: Option<OfflineBuffer>,
+    pending_pushes: HashMap<String, Push>,
+    join_ref: Option<String>,
+    join_timeout: Duration,
+    rejoin_interval: Arc<ReconnectFn>,
+    rejoin_attempts: usize,
+    rejoin_at: Option<Instant>,
+    /// Set once a leave completes, the client then drops the channel
+    left: bool,
+    /// Name of the client the channel was added to
+    pub(crate) client: Option<String>,
+    push_timeout: Duration,
+    broadcast_ack_timeout: Duration,
     join_payload: JoinPayload,
     presence: Presence,
     // sync bridge
-    tx: Sender<RealtimeMessage>,
-    manager_rx: Receiver<ChannelManagerMessage>,
+    tx: Sender<OutboundMessage>,
+    manager_rx: BoundedReceiver<ChannelManagerMessage>,
     presence_state_callback_event_sender: CrossbeamEventSender<PresenceStateCallbackEvent>,
     channel_state_callback_event_sender: CrossbeamEventSender<ChannelStateCallbackEvent>,
     broadcast_callback_event_sender: CrossbeamEventSender<BroadcastCallbackEvent>,
     postgres_changes_callback_event_sender: CrossbeamEventSender<PostgresChangesCallbackEvent>,
+    broadcast_ack_event_sender: CrossbeamEventSender<BroadcastAck>,
+    push_reply_event_sender: CrossbeamEventSender<PushReply>,
+    lifecycle_event_sender: CrossbeamEventSender<ChannelLifecycleEvent>,
 }
 
 // TODO channel options with broadcast + presence settings
@@ -145,10 +307,20 @@ impl RealtimeChannel {
     pub(crate) fn manager_recv(&mut self) -> Result<(), Box<dyn Error>> {
         while let Ok(message) = self.manager_rx.try_recv() {
             match message {
-                ChannelManagerMessage::Broadcast { payload } => self.broadcast(payload)?,
+                ChannelManagerMessage::Broadcast {
+                    payload,
+                    policy,
+                    handle,
+                } => self.broadcast(payload, policy, handle)?,
                 ChannelManagerMessage::Subscribe => self.subscribe()?,
+                ChannelManagerMessage::Unsubscribe => {
+                    self.unsubscribe()?;
+                }
                 ChannelManagerMessage::Track { payload } => self.track(payload)?,
                 ChannelManagerMessage::Untrack => self.untrack()?,
+                ChannelManagerMessage::Push { message, push } => {
+                    self.push(*message, push, SendPolicy::Reliable)?
+                }
                 ChannelManagerMessage::PresenceState { callback } => self
                     .presence_state_callback_event_sender
                     .send(PresenceStateCallbackEvent((
@@ -170,35 +342,108 @@ impl RealtimeChannel {
 
     /// Send a join request to the channel
     /// Does not block, for blocking behaviour use [RealtimeClient::block_until_subscribed()]
-    pub(crate) fn subscribe(&mut self) -> Result<(), SendError<RealtimeMessage>> {
+    pub(crate) fn subscribe(&mut self) -> Result<(), Box<SendError<RealtimeMessage>>> {
+        self.left = false;
+
         let join_message = RealtimeMessage {
             event: MessageEvent::PhxJoin,
             topic: self.topic.clone(),
             payload: Payload::Join(self.join_payload.clone()),
-            message_ref: Some(self.id.into()),
+            message_ref: None,
         };
 
+        let push = Push::new(PushKind::Join, self.join_timeout)
+            .receive(ReplyStatus::Ok, |channel, _| {
+                channel.connection_state = ChannelState::Joined;
+                channel.rejoin_attempts = 0;
+                channel.rejoin_at = None;
+                channel.flush_offline_buffer();
+            })
+            .receive(ReplyStatus::Error, |channel, status| {
+                debug!("Join {} failed: {:?}", channel.topic, status);
+                channel.connection_state = ChannelState::Errored;
+                channel.schedule_rejoin();
+            })
+            .receive(ReplyStatus::Timeout, |channel, _| {
+                debug!("Join {} timed out", channel.topic);
+                channel.connection_state = ChannelState::Errored;
+                channel.schedule_rejoin();
+            });
+
+        // Drop the reply tracking of any earlier join
+        if let Some(join_ref) = self.join_ref.replace(push.message_ref().into()) {
+            self.pending_pushes.remove(&join_ref);
+        }
+
         self.connection_state = ChannelState::Joining;
 
-        self.tx.send(join_message)
+        self.push(join_message, push, SendPolicy::Reliable)
+    }
+
+    /// Starts the rejoin timer, with the delay given by [ChannelBuilder::rejoin_interval]
+    pub(crate) fn schedule_rejoin(&mut self) {
+        if self.connection_state == ChannelState::Closed
+            || self.connection_state == ChannelState::Leaving
+        {
+            return;
+        }
+
+        let delay = self.rejoin_interval.0(self.rejoin_attempts);
+        self.rejoin_attempts += 1;
+        self.rejoin_at = Some(Instant::now() + delay);
+
+        debug!(
+            "Rejoining {} in {:?}, attempt {}",
+            self.topic, delay, self.rejoin_attempts
+        );
+    }
+
+    /// Rejoins once the rejoin timer has elapsed. Only run while the socket is connected.
+    pub(crate) fn run_rejoin_timer(&mut self) {
+        let Some(rejoin_at) = self.rejoin_at else {
+            return;
+        };
+
+        if Instant::now() < rejoin_at {
+            return;
+        }
+
+        self.rejoin_at = None;
+
+        if let Err(e) = self.subscribe() {
+            debug!("Rejoin {} failed: {:?}", self.topic, e.0);
+            self.schedule_rejoin();
+        }
     }
 
     /// Leave the channel
-    pub(crate) fn unsubscribe(&mut self) -> Result<ChannelState, SendError<RealtimeMessage>> {
+    pub(crate) fn unsubscribe(&mut self) -> Result<ChannelState, Box<SendError<RealtimeMessage>>> {
         if self.connection_state == ChannelState::Closed
             || self.connection_state == ChannelState::Leaving
         {
             return Ok(self.connection_state);
         }
 
+        self.rejoin_at = None;
+
         let message = RealtimeMessage {
             event: MessageEvent::PhxLeave,
             topic: self.topic.clone(),
             payload: Payload::Empty {},
-            message_ref: Some(format!("{}+leave", self.id)),
+            message_ref: None,
         };
 
-        match self.send(message) {
+        let close = |channel: &mut RealtimeChannel, _: &PushStatus| {
+            channel.close(CloseReason::Leave);
+        };
+
+        // The channel is closed locally however the server answers, as in phoenix.js
+        let push = Push::new(PushKind::Leave, self.push_timeout)
+            .receive(ReplyStatus::Ok, close)
+            .receive(ReplyStatus::Error, close)
+            .receive(ReplyStatus::Timeout, close);
+
+        match self.push(message, push, SendPolicy::Reliable) {
             Ok(()) => {
                 self.connection_state = ChannelState::Leaving;
                 Ok(self.connection_state)
@@ -207,58 +452,241 @@ impl RealtimeChannel {
         }
     }
 
+    /// True once the channel has been left, see [ChannelManager::unsubscribe]
+    pub(crate) fn has_left(&self) -> bool {
+        self.left
+    }
+
     /// Returns the current [PresenceState] of the channel
     fn presence_state(&self) -> PresenceState {
         self.presence.state.clone()
     }
 
     /// Track provided state in Realtime Presence
-    fn track(&mut self, payload: HashMap<String, Value>) -> Result<(), SendError<RealtimeMessage>> {
-        self.send(RealtimeMessage {
-            event: MessageEvent::Presence,
-            topic: self.topic.clone(),
-            payload: Payload::PresenceTrack(payload.into()),
-            message_ref: None,
-        })
+    fn track(
+        &mut self,
+        payload: HashMap<String, Value>,
+    ) -> Result<(), Box<SendError<RealtimeMessage>>> {
+        self.push(
+            RealtimeMessage {
+                event: MessageEvent::Presence,
+                topic: self.topic.clone(),
+                payload: Payload::PresenceTrack(payload.into()),
+                message_ref: None,
+            },
+            Push::new(PushKind::Track, self.push_timeout),
+            SendPolicy::Reliable,
+        )
     }
 
     /// Sends a message to stop tracking this channel's presence
-    fn untrack(&mut self) -> Result<(), SendError<RealtimeMessage>> {
-        self.send(RealtimeMessage {
-            event: MessageEvent::Untrack,
-            topic: self.topic.clone(),
-            payload: Payload::Empty {},
-            message_ref: None,
-        })
+    fn untrack(&mut self) -> Result<(), Box<SendError<RealtimeMessage>>> {
+        self.push(
+            RealtimeMessage {
+                event: MessageEvent::Untrack,
+                topic: self.topic.clone(),
+                payload: Payload::Empty {},
+                message_ref: None,
+            },
+            Push::new(PushKind::Untrack, self.push_timeout),
+            SendPolicy::Reliable,
+        )
     }
 
-    /// Send a [RealtimeMessage] on this channel
-    fn send(&mut self, message: RealtimeMessage) -> Result<(), SendError<RealtimeMessage>> {
+    /// Send a [RealtimeMessage] on this channel with the provided [SendPolicy]
+    fn send(
+        &mut self,
+        message: RealtimeMessage,
+        policy: SendPolicy,
+    ) -> Result<(), Box<SendError<RealtimeMessage>>> {
         // inject channel topic to message here
         let mut message = message.clone();
         message.topic.clone_from(&self.topic);
 
         if self.connection_state == ChannelState::Leaving {
-            return Err(SendError(message));
+            return Err(Box::new(SendError(message)));
+        }
+
+        let message = OutboundMessage::new(message, policy);
+
+        if self.connection_state != ChannelState::Joined
+            && message.priority() != MessagePriority::Control
+        {
+            if let Some(buffer) = &mut self.offline_buffer {
+                buffer.push(message);
+                return Ok(());
+            }
         }
 
-        self.tx.send(message)
+        self.tx
+            .send(message)
+            .map_err(|SendError(outbound)| Box::new(SendError(outbound.message)))
+    }
+
+    /// Called when the socket drops, before rejoining. If offline buffering is enabled, this
+    /// channel's queued messages are moved back into the buffer to be replayed after the join.
+    pub(crate) fn connection_lost(&mut self, queue: &mut OutboundQueue) {
+        // The server forgets the channel with the socket, so a pending leave is done
+        if self.connection_state == ChannelState::Leaving {
+            self.close(CloseReason::Leave);
+            return;
+        }
+
+        if self.connection_state == ChannelState::Joined {
+            self.connection_state = ChannelState::Joining;
+        }
+
+        // The client rejoins every channel once reconnected
+        self.rejoin_at = None;
+        self.rejoin_attempts = 0;
+
+        if let Some(buffer) = &mut self.offline_buffer {
+            buffer.requeue(queue.take_topic(&self.topic));
+        }
+    }
+
+    /// Sends any messages buffered while the channel wasn't joined
+    fn flush_offline_buffer(&mut self) {
+        let Some(buffer) = &mut self.offline_buffer else {
+            return;
+        };
+
+        let messages = buffer.drain();
+
+        if !messages.is_empty() {
+            debug!("Replaying {} offline messages", messages.len());
+        }
+
+        for message in messages {
+            if let Err(e) = self.tx.send(message) {
+                debug!("Failed to replay offline message: {:?}", e.0.message);
+            }
+        }
+    }
+
+    /// Sends `message` as a tracked [Push], resolved by the matching `phx_reply` or by timing
+    /// out
+    pub(crate) fn push(
+        &mut self,
+        mut message: RealtimeMessage,
+        push: Push,
+        policy: SendPolicy,
+    ) -> Result<(), Box<SendError<RealtimeMessage>>> {
+        message.message_ref = Some(push.message_ref().into());
+
+        self.send(message, policy)?;
+        self.pending_pushes.insert(push.message_ref().into(), push);
+
+        Ok(())
+    }
+
+    /// Resolves the pending push matching a `phx_reply`, if any
+    fn receive_reply(&mut self, message: &RealtimeMessage) {
+        let Some(push) = message
+            .message_ref
+            .as_ref()
+            .and_then(|message_ref| self.pending_pushes.remove(message_ref))
+        else {
+            return;
+        };
+
+        self.resolve_push(push, PushStatus::from_reply(&message.payload));
+    }
+
+    fn resolve_push(&mut self, push: Push, status: PushStatus) {
+        debug!(
+            "{:?} push on {} resolved: {:?}",
+            push.kind(),
+            self.topic,
+            status
+        );
+
+        self.push_reply_event_sender.send(PushReply {
+            client: self.client.clone(),
+            topic: self.topic.clone(),
+            kind: push.kind().clone(),
+            message_ref: push.message_ref().into(),
+            status: status.clone(),
+        });
+
+        push.resolve(self, &status);
+    }
+
+    /// Resolves pushes that haven't had a reply within their timeout
+    pub(crate) fn check_push_timeouts(&mut self) {
+        if self.pending_pushes.is_empty() {
+            return;
+        }
+
+        let now = Instant::now();
+
+        let timed_out = self
+            .pending_pushes
+            .iter()
+            .filter(|(_, push)| push.is_timed_out(now))
+            .map(|(message_ref, _)| message_ref.clone())
+            .collect::<Vec<_>>();
+
+        for message_ref in timed_out {
+            if let Some(push) = self.pending_pushes.remove(&message_ref) {
+                self.resolve_push(push, PushStatus::Timeout);
+            }
+        }
     }
 
     /// Helper function for sending broadcast messages
-    fn broadcast(&mut self, payload: BroadcastPayload) -> Result<(), SendError<RealtimeMessage>> {
-        self.send(RealtimeMessage {
+    fn broadcast(
+        &mut self,
+        payload: BroadcastPayload,
+        policy: Option<SendPolicy>,
+        handle: BroadcastHandle,
+    ) -> Result<(), Box<SendError<RealtimeMessage>>> {
+        let policy = policy
+            .or_else(|| self.broadcast_policies.get(&payload.event).copied())
+            .unwrap_or_default();
+
+        let message = RealtimeMessage {
             event: MessageEvent::Broadcast,
             topic: "".into(),
             payload: Payload::Broadcast(payload),
-            message_ref: None,
-        })
+            message_ref: Some(handle.0.to_string()),
+        };
+
+        // Without acks the server doesn't reply, so there is nothing to track
+        if !self.join_payload.config.broadcast.ack {
+            return self.send(message, policy);
+        }
+
+        let ack = move |channel: &mut RealtimeChannel, status: &PushStatus| {
+            let result = match status {
+                PushStatus::Ok(_) => BroadcastAckResult::Ok,
+                PushStatus::Error(response) => BroadcastAckResult::Error(response.clone()),
+                PushStatus::Timeout => BroadcastAckResult::Timeout,
+            };
+
+            channel.broadcast_ack_event_sender.send(BroadcastAck {
+                client: channel.client.clone(),
+                handle,
+                result,
+            });
+        };
+
+        let push = Push::with_ref(
+            PushKind::Broadcast,
+            handle.0.to_string(),
+            self.broadcast_ack_timeout,
+        )
+        .receive(ReplyStatus::Ok, ack)
+        .receive(ReplyStatus::Error, ack)
+        .receive(ReplyStatus::Timeout, ack);
+
+        self.push(message, push, policy)
     }
 
     pub(crate) fn set_auth(
         &mut self,
         access_token: String,
-    ) -> Result<(), SendError<RealtimeMessage>> {
+    ) -> Result<(), Box<SendError<RealtimeMessage>>> {
         self.join_payload.access_token.clone_from(&access_token);
 
         if self.connection_state != ChannelState::Joined {
@@ -272,20 +700,15 @@ impl RealtimeChannel {
             ..Default::default()
         };
 
-        self.send(access_token_message)
+        self.push(
+            access_token_message,
+            Push::new(PushKind::AccessToken, self.push_timeout),
+            SendPolicy::Reliable,
+        )
     }
 
     pub(crate) fn recieve(&mut self, message: RealtimeMessage) {
         match &message.payload {
-            Payload::Response(join_response) => {
-                let target_id = message.message_ref.clone().unwrap_or("".to_string());
-                if target_id != self.id.to_string() {
-                    return;
-                }
-                if join_response.status == PayloadStatus::Ok {
-                    self.connection_state = ChannelState::Joined;
-                }
-            }
             Payload::PresenceState(state) => self.presence.sync(state.clone().into()),
             Payload::PresenceDiff(raw_diff) => {
                 self.presence.sync_diff(raw_diff.clone().into());
@@ -334,24 +757,79 @@ impl RealtimeChannel {
 
         match &message.event {
             MessageEvent::PhxClose => {
-                if let Some(message_ref) = message.message_ref {
-                    if message_ref == self.id.to_string() {
-                        self.connection_state = ChannelState::Closed;
-                        debug!("Channel Closed! {:?}", self.id);
-                    }
+                if message.message_ref.is_some() && message.message_ref != self.join_ref {
+                    return;
                 }
-            }
-            MessageEvent::PhxReply => {
-                if message.message_ref.clone().unwrap_or("#NOREF".to_string())
-                    == format!("{}+leave", self.id)
-                {
-                    self.connection_state = ChannelState::Closed;
-                    debug!("Channel Closed! {:?}", self.id);
+
+                let reason = match self.connection_state {
+                    ChannelState::Leaving => CloseReason::Leave,
+                    _ => CloseReason::Server,
+                };
+
+                self.close(reason);
+
+                // Only a leave we asked for keeps the channel closed
+                if reason == CloseReason::Server {
+                    self.connection_state = ChannelState::Errored;
+                    self.schedule_rejoin();
                 }
             }
+            MessageEvent::PhxError => self.errored(),
+            MessageEvent::PhxReply => self.receive_reply(&message),
             _ => {}
         }
     }
+
+    /// Marks the channel closed, cancelling any rejoin
+    fn close(&mut self, reason: CloseReason) {
+        if self.connection_state == ChannelState::Closed {
+            return;
+        }
+
+        self.connection_state = ChannelState::Closed;
+        self.rejoin_at = None;
+        self.left = reason == CloseReason::Leave;
+
+        if let Some(join_ref) = self.join_ref.take() {
+            self.pending_pushes.remove(&join_ref);
+        }
+
+        debug!("Channel Closed! {:?} {:?}", self.id, reason);
+
+        self.lifecycle_event_sender
+            .send(ChannelLifecycleEvent::Closed {
+                client: self.client.clone(),
+                topic: self.topic.clone(),
+                reason,
+            });
+    }
+
+    /// Handles the server's channel process failing, scheduling a rejoin
+    fn errored(&mut self) {
+        if matches!(
+            self.connection_state,
+            ChannelState::Closed | ChannelState::Leaving | ChannelState::Errored
+        ) {
+            return;
+        }
+
+        debug!("Channel Errored! {:?}", self.id);
+
+        self.connection_state = ChannelState::Errored;
+
+        // The pending join won't be answered, the rejoin replaces it
+        if let Some(join_ref) = &self.join_ref {
+            self.pending_pushes.remove(join_ref);
+        }
+
+        self.lifecycle_event_sender
+            .send(ChannelLifecycleEvent::Errored {
+                client: self.client.clone(),
+                topic: self.topic.clone(),
+            });
+
+        self.schedule_rejoin();
+    }
 }
 
 impl Debug for RealtimeChannel {
@@ -376,8 +854,17 @@ pub struct ChannelBuilder {
     postgres_changes: Vec<PostgresChange>,
     cdc_callbacks: HashMap<PostgresChangesEvent, Vec<PostgresChangesCallback>>,
     broadcast_callbacks: HashMap<String, Vec<BroadcastCallback>>,
+    broadcast_policies: HashMap<String, SendPolicy>,
+    offline_buffer: Option<Duration>,
+    join_timeout: Duration,
+    rejoin_interval: Arc<ReconnectFn>,
+    push_timeout: Duration,
+    broadcast_ack_timeout: Duration,
     presence_callbacks: HashMap<PresenceEvent, Vec<PresenceCallback>>,
-    tx: Sender<RealtimeMessage>,
+    tx: Sender<OutboundMessage>,
+    outbound_depth: OutboundDepth,
+    outbound_bound: Option<QueueBound>,
+    manager_bound: Option<QueueBound>,
 }
 
 impl ChannelBuilder {
@@ -391,8 +878,17 @@ impl ChannelBuilder {
             postgres_changes: Default::default(),
             cdc_callbacks: Default::default(),
             broadcast_callbacks: Default::default(),
+            broadcast_policies: Default::default(),
+            offline_buffer: Default::default(),
+            join_timeout: Duration::from_secs(10),
+            rejoin_interval: Arc::new(ReconnectFn::new(rejoin_backoff)),
+            push_timeout: Duration::from_secs(10),
+            broadcast_ack_timeout: Duration::from_secs(10),
             presence_callbacks: Default::default(),
             tx: client.get_channel_tx(),
+            outbound_depth: client.outbound_depth.clone(),
+            outbound_bound: client.outbound_bound,
+            manager_bound: client.manager_bound,
         }
     }
 
@@ -402,6 +898,11 @@ impl ChannelBuilder {
         self
     }
 
+    /// Returns the full topic of the channel, including the `realtime:` prefix
+    pub fn get_topic(&self) -> &str {
+        &self.topic
+    }
+
     /// Set the broadcast config for this channel
     pub fn set_broadcast_config(&mut self, broadcast_config: BroadcastConfig) -> &mut Self {
         self.broadcast = broadcast_config;
@@ -479,6 +980,55 @@ impl ChannelBuilder {
         self
     }
 
+    /// Set the [SendPolicy] used for broadcasts with the provided event.
+    /// Defaults to [SendPolicy::Reliable]
+    pub fn broadcast_policy(&mut self, event: impl Into<String>, policy: SendPolicy) -> &mut Self {
+        self.broadcast_policies.insert(event.into(), policy);
+        self
+    }
+
+    /// Hold broadcasts and presence updates sent while the channel isn't joined, e.g. while
+    /// reconnecting, and send them in order once it is. Messages older than `max_age` when the
+    /// channel joins are discarded.
+    /// Default: disabled, messages are queued for the socket regardless of channel state
+    pub fn offline_buffer(&mut self, max_age: Duration) -> &mut Self {
+        self.offline_buffer = Some(max_age);
+        self
+    }
+
+    /// Set how long to wait for the server to accept a join before giving up and scheduling a
+    /// rejoin.
+    /// Default: 10 seconds
+    pub fn join_timeout(&mut self, timeout: Duration) -> &mut Self {
+        self.join_timeout = timeout;
+        self
+    }
+
+    /// Set the function providing the time between rejoin attempts after a join fails, times
+    /// out or the server errors the channel. It is passed the count of attempts so far.
+    ///
+    /// Defaults to stepped backoff of 1, 2 and 5 seconds, then every 10 seconds
+    pub fn rejoin_interval(&mut self, rejoin_interval: ReconnectFn) -> &mut Self {
+        self.rejoin_interval = Arc::new(rejoin_interval);
+        self
+    }
+
+    /// Set how long to wait for the server to reply to leaves, presence updates and
+    /// access token updates before resolving them with [PushStatus::Timeout].
+    /// Default: 10 seconds
+    pub fn push_timeout(&mut self, timeout: Duration) -> &mut Self {
+        self.push_timeout = timeout;
+        self
+    }
+
+    /// Set how long to wait for the server to ack a broadcast before sending a
+    /// [BroadcastAckResult::Timeout]. Only used if [BroadcastConfig::ack] is enabled.
+    /// Default: 10 seconds
+    pub fn broadcast_ack_timeout(&mut self, timeout: Duration) -> &mut Self {
+        self.broadcast_ack_timeout = timeout;
+        self
+    }
+
     // TODO on_message handler for sys messages
 
     /// Create the channel and pass ownership to provided [RealtimeClient], returning the channel
@@ -491,14 +1041,29 @@ impl ChannelBuilder {
         broadcast_callback_event_sender: CrossbeamEventSender<BroadcastCallbackEvent>,
         presence_callback_event_sender: CrossbeamEventSender<PresenceCallbackEvent>,
         postgres_changes_callback_event_sender: CrossbeamEventSender<PostgresChangesCallbackEvent>,
+        broadcast_ack_event_sender: CrossbeamEventSender<BroadcastAck>,
+        push_reply_event_sender: CrossbeamEventSender<PushReply>,
+        lifecycle_event_sender: CrossbeamEventSender<ChannelLifecycleEvent>,
     ) -> ChannelManager {
-        let manager_channel = unbounded();
+        let manager_channel = bounded_channel(self.manager_bound);
 
         client
             .add_channel(RealtimeChannel {
                 topic: self.topic.clone(),
                 postgres_changes_callbacks: self.cdc_callbacks.clone(),
                 broadcast_callbacks: self.broadcast_callbacks.clone(),
+                broadcast_policies: self.broadcast_policies.clone(),
+                offline_buffer: self.offline_buffer.map(OfflineBuffer::new),
+                pending_pushes: Default::default(),
+                join_ref: None,
+                join_timeout: self.join_timeout,
+                rejoin_interval: self.rejoin_interval.clone(),
+                rejoin_attempts: 0,
+                rejoin_at: None,
+                left: false,
+                client: None,
+                push_timeout: self.push_timeout,
+                broadcast_ack_timeout: self.broadcast_ack_timeout,
                 tx: self.tx.clone(),
                 manager_rx: manager_channel.1,
                 connection_state: ChannelState::Closed,
@@ -519,11 +1084,473 @@ impl ChannelBuilder {
                 channel_state_callback_event_sender,
                 broadcast_callback_event_sender,
                 postgres_changes_callback_event_sender,
+                broadcast_ack_event_sender,
+                push_reply_event_sender,
+                lifecycle_event_sender,
             })
             .unwrap();
 
         ChannelManager {
-            tx: manager_channel.0,
+            tx: manager_channel.0.unbounded(),
+            bounded_tx: manager_channel.0,
+            outbound_depth: self.outbound_depth.clone(),
+            outbound_bound: self.outbound_bound,
+        }
+    }
+}
+
+fn rejoin_backoff(attempts: usize) -> Duration {
+    let times: Vec<u64> = vec![1, 2, 5, 10];
+
+    Duration::from_secs(times[attempts.min(times.len() - 1)])
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::prelude::{App, Events};
+    use bevy_crossbeam_event::CrossbeamEventApp;
+
+    use super::*;
+    use crate::{
+        client::{ChannelCallbackEvent, ConnectResultCallbackEvent},
+        message::payload::ReplyPayload,
+        mock::MockServer,
+    };
+
+    const WAIT: Duration = Duration::from_secs(5);
+
+    struct Harness {
+        server: MockServer,
+        app: App,
+        client: Client,
+        channel: ChannelManager,
+        id: Uuid,
+        lifecycle: Vec<ChannelLifecycleEvent>,
+        replies: Vec<PushReply>,
+        acks: Vec<BroadcastAck>,
+    }
+
+    impl Harness {
+        fn new() -> Self {
+            Self::with_channel(|_| {})
+        }
+
+        /// Like [Harness::new], with extra channel configuration
+        fn with_channel(configure: impl FnOnce(&mut ChannelBuilder)) -> Self {
+            let server = MockServer::start();
+
+            let mut app = App::new();
+            app.add_crossbeam_event::<ChannelCallbackEvent>()
+                .add_crossbeam_event::<ConnectResultCallbackEvent>()
+                .add_crossbeam_event::<PresenceStateCallbackEvent>()
+                .add_crossbeam_event::<ChannelStateCallbackEvent>()
+                .add_crossbeam_event::<BroadcastCallbackEvent>()
+                .add_crossbeam_event::<PresenceCallbackEvent>()
+                .add_crossbeam_event::<PostgresChangesCallbackEvent>()
+                .add_crossbeam_event::<BroadcastAck>()
+                .add_crossbeam_event::<PushReply>()
+                .add_crossbeam_event::<ChannelLifecycleEvent>();
+
+            let world = app.world();
+            let mut client = Client::builder(server.endpoint(), "key");
+            client
+                .name("test")
+                .reconnect_interval(ReconnectFn::new(|_| Duration::from_millis(10)));
+
+            let mut client = client.build(
+                world.resource::<CrossbeamEventSender<_>>().clone(),
+                world.resource::<CrossbeamEventSender<_>>().clone(),
+            );
+            client.connect().unwrap();
+
+            let mut builder = client.channel();
+            builder
+                .topic("test")
+                .rejoin_interval(ReconnectFn::new(|_| Duration::from_millis(10)));
+            configure(&mut builder);
+
+            let channel = builder.build(
+                &ClientManager::new(&client),
+                world.resource::<CrossbeamEventSender<_>>().clone(),
+                world.resource::<CrossbeamEventSender<_>>().clone(),
+                world.resource::<CrossbeamEventSender<_>>().clone(),
+                world.resource::<CrossbeamEventSender<_>>().clone(),
+                world.resource::<CrossbeamEventSender<_>>().clone(),
+                world.resource::<CrossbeamEventSender<_>>().clone(),
+                world.resource::<CrossbeamEventSender<_>>().clone(),
+                world.resource::<CrossbeamEventSender<_>>().clone(),
+            );
+
+            Self {
+                server,
+                app,
+                client,
+                channel,
+                id: builder.id,
+                lifecycle: vec![],
+                replies: vec![],
+                acks: vec![],
+            }
+        }
+
+        fn step(&mut self) {
+            let _ = self.client.step();
+            self.app.update();
+
+            let world = self.app.world_mut();
+            self.lifecycle.extend(
+                world
+                    .resource_mut::<Events<ChannelLifecycleEvent>>()
+                    .drain(),
+            );
+            self.replies
+                .extend(world.resource_mut::<Events<PushReply>>().drain());
+            self.acks
+                .extend(world.resource_mut::<Events<BroadcastAck>>().drain());
+        }
+
+        fn state(&self) -> ChannelState {
+            self.client.get_channel(self.id).unwrap().connection_state
+        }
+
+        /// Steps until the server gets a message with `event`, skipping any others
+        fn expect(&mut self, event: MessageEvent) -> RealtimeMessage {
+            let deadline = Instant::now() + WAIT;
+
+            while Instant::now() < deadline {
+                self.step();
+
+                while let Some(message) = self.server.try_recv() {
+                    if message.event == event {
+                        return message;
+                    }
+                }
+            }
+
+            panic!("server never got {:?}", event);
+        }
+
+        /// Steps for `duration`, failing if the server gets a message with `event`
+        fn expect_none(&mut self, event: MessageEvent, duration: Duration) {
+            let deadline = Instant::now() + duration;
+
+            while Instant::now() < deadline {
+                self.step();
+
+                while let Some(message) = self.server.try_recv() {
+                    assert_ne!(message.event, event, "server got {:?}", message);
+                }
+            }
+        }
+
+        fn step_until(&mut self, done: impl Fn(&Self) -> bool) {
+            let deadline = Instant::now() + WAIT;
+
+            while !done(self) {
+                assert!(Instant::now() < deadline, "timed out");
+                self.step();
+            }
+        }
+
+        fn reply(&self, to: &RealtimeMessage, status: &str) {
+            self.server.send(RealtimeMessage {
+                event: MessageEvent::PhxReply,
+                topic: to.topic.clone(),
+                payload: Payload::Reply(ReplyPayload {
+                    response: Value::Null,
+                    status: status.into(),
+                }),
+                message_ref: to.message_ref.clone(),
+            });
+        }
+
+        fn server_event(&self, event: MessageEvent, message_ref: Option<String>) {
+            self.server.send(RealtimeMessage {
+                event,
+                topic: "realtime:test".into(),
+                payload: Payload::Empty {},
+                message_ref,
+            });
+        }
+
+        /// Subscribes and answers the join
+        fn join(&mut self) -> RealtimeMessage {
+            self.channel.subscribe().unwrap();
+
+            let join = self.expect(MessageEvent::PhxJoin);
+            self.reply(&join, "ok");
+            self.step_until(|h| h.state() == ChannelState::Joined);
+
+            join
         }
     }
+
+    #[test]
+    fn join_reply_joins_channel() {
+        let mut h = Harness::new();
+
+        h.channel.subscribe().unwrap();
+
+        let join = h.expect(MessageEvent::PhxJoin);
+        assert_eq!(join.topic, "realtime:test");
+        assert_eq!(h.state(), ChannelState::Joining);
+
+        h.reply(&join, "ok");
+        h.step_until(|h| h.state() == ChannelState::Joined);
+
+        assert_eq!(h.replies.len(), 1);
+        assert_eq!(h.replies[0].kind, PushKind::Join);
+        assert_eq!(h.replies[0].message_ref, join.message_ref.unwrap());
+        assert_eq!(h.replies[0].client.as_deref(), Some("test"));
+        assert!(h.lifecycle.is_empty());
+    }
+
+    #[test]
+    fn join_error_reply_rejoins() {
+        let mut h = Harness::new();
+
+        h.channel.subscribe().unwrap();
+
+        let join = h.expect(MessageEvent::PhxJoin);
+        h.reply(&join, "error");
+
+        let rejoin = h.expect(MessageEvent::PhxJoin);
+        assert_ne!(rejoin.message_ref, join.message_ref);
+
+        h.reply(&rejoin, "ok");
+        h.step_until(|h| h.state() == ChannelState::Joined);
+    }
+
+    #[test]
+    fn join_timeout_rejoins() {
+        let mut h = Harness::with_channel(|builder| {
+            builder
+                .join_timeout(Duration::from_millis(50))
+                .rejoin_interval(ReconnectFn::new(|_| Duration::from_millis(200)));
+        });
+
+        h.channel.subscribe().unwrap();
+
+        let join = h.expect(MessageEvent::PhxJoin);
+        h.step_until(|h| h.state() == ChannelState::Errored);
+
+        assert!(h.client.get_channel(h.id).unwrap().rejoin_at.is_some());
+        assert_eq!(h.replies.len(), 1);
+        assert_eq!(h.replies[0].kind, PushKind::Join);
+        assert_eq!(h.replies[0].status, PushStatus::Timeout);
+
+        let rejoin = h.expect(MessageEvent::PhxJoin);
+        assert_ne!(rejoin.message_ref, join.message_ref);
+
+        h.reply(&rejoin, "ok");
+        h.step_until(|h| h.state() == ChannelState::Joined);
+    }
+
+    #[test]
+    fn phx_error_rejoins() {
+        let mut h = Harness::new();
+        h.join();
+
+        h.server_event(MessageEvent::PhxError, None);
+        h.step_until(|h| !h.lifecycle.is_empty());
+
+        assert_eq!(
+            h.lifecycle,
+            vec![ChannelLifecycleEvent::Errored {
+                client: Some("test".into()),
+                topic: "realtime:test".into()
+            }]
+        );
+        assert_eq!(h.state(), ChannelState::Errored);
+
+        let rejoin = h.expect(MessageEvent::PhxJoin);
+        h.reply(&rejoin, "ok");
+        h.step_until(|h| h.state() == ChannelState::Joined);
+    }
+
+    #[test]
+    fn server_close_rejoins() {
+        let mut h = Harness::new();
+        let join = h.join();
+
+        h.server_event(MessageEvent::PhxClose, join.message_ref);
+        h.step_until(|h| !h.lifecycle.is_empty());
+
+        assert_eq!(
+            h.lifecycle,
+            vec![ChannelLifecycleEvent::Closed {
+                client: Some("test".into()),
+                topic: "realtime:test".into(),
+                reason: CloseReason::Server,
+            }]
+        );
+
+        let rejoin = h.expect(MessageEvent::PhxJoin);
+        h.reply(&rejoin, "ok");
+        h.step_until(|h| h.state() == ChannelState::Joined);
+    }
+
+    #[test]
+    fn close_for_stale_join_is_ignored() {
+        let mut h = Harness::new();
+        h.join();
+
+        h.server_event(MessageEvent::PhxClose, Some("stale".into()));
+        h.expect_none(MessageEvent::PhxJoin, Duration::from_millis(100));
+
+        assert!(h.lifecycle.is_empty());
+        assert_eq!(h.state(), ChannelState::Joined);
+    }
+
+    #[test]
+    fn leave_closes_without_rejoin() {
+        let mut h = Harness::new();
+        h.join();
+
+        h.channel.unsubscribe().unwrap();
+
+        let leave = h.expect(MessageEvent::PhxLeave);
+        assert_eq!(h.state(), ChannelState::Leaving);
+
+        h.reply(&leave, "ok");
+        // The client drops the channel once it has left
+        h.step_until(|h| h.client.get_channel(h.id).is_none());
+
+        assert_eq!(
+            h.lifecycle,
+            vec![ChannelLifecycleEvent::Closed {
+                client: Some("test".into()),
+                topic: "realtime:test".into(),
+                reason: CloseReason::Leave,
+            }]
+        );
+
+        h.expect_none(MessageEvent::PhxJoin, Duration::from_millis(100));
+    }
+
+    #[test]
+    fn leave_error_reply_still_closes() {
+        let mut h = Harness::new();
+        h.join();
+
+        h.channel.unsubscribe().unwrap();
+
+        let leave = h.expect(MessageEvent::PhxLeave);
+        h.reply(&leave, "error");
+        h.step_until(|h| !h.lifecycle.is_empty());
+
+        assert_eq!(
+            h.lifecycle,
+            vec![ChannelLifecycleEvent::Closed {
+                client: Some("test".into()),
+                topic: "realtime:test".into(),
+                reason: CloseReason::Leave,
+            }]
+        );
+
+        h.expect_none(MessageEvent::PhxJoin, Duration::from_millis(100));
+    }
+
+    #[test]
+    fn unanswered_leave_removes_channel_from_client() {
+        let mut h = Harness::with_channel(|builder| {
+            builder.push_timeout(Duration::from_millis(50));
+        });
+        h.join();
+
+        h.channel.unsubscribe().unwrap();
+        h.expect(MessageEvent::PhxLeave);
+
+        h.step_until(|h| h.client.get_channel(h.id).is_none());
+
+        assert_eq!(
+            h.lifecycle,
+            vec![ChannelLifecycleEvent::Closed {
+                client: Some("test".into()),
+                topic: "realtime:test".into(),
+                reason: CloseReason::Leave,
+            }]
+        );
+    }
+
+    #[test]
+    fn custom_push_reply_keeps_its_label() {
+        let mut h = Harness::new();
+        h.join();
+
+        let push = Push::new(PushKind::Custom("ping".into()), Duration::from_secs(10));
+        let message_ref = push.message_ref().to_string();
+
+        h.channel
+            .push(
+                RealtimeMessage {
+                    event: MessageEvent::Broadcast,
+                    topic: "".into(),
+                    payload: Payload::Empty {},
+                    message_ref: None,
+                },
+                push,
+            )
+            .unwrap();
+
+        let sent = h.expect(MessageEvent::Broadcast);
+        assert_eq!(sent.message_ref.as_deref(), Some(message_ref.as_str()));
+
+        h.reply(&sent, "ok");
+        h.step_until(|h| h.replies.len() == 2);
+
+        assert_eq!(h.replies[1].kind, PushKind::Custom("ping".into()));
+        assert_eq!(h.replies[1].message_ref, message_ref);
+    }
+
+    fn acked_harness(timeout: Duration) -> Harness {
+        Harness::with_channel(|builder| {
+            builder
+                .set_broadcast_config(BroadcastConfig {
+                    broadcast_self: false,
+                    ack: true,
+                })
+                .broadcast_ack_timeout(timeout);
+        })
+    }
+
+    #[test]
+    fn broadcast_ack_reply_resolves_ok() {
+        let mut h = acked_harness(Duration::from_secs(10));
+        h.join();
+
+        let handle = h
+            .channel
+            .broadcast(BroadcastPayload::new("test", HashMap::new()))
+            .unwrap();
+
+        let broadcast = h.expect(MessageEvent::Broadcast);
+        assert_eq!(broadcast.message_ref, Some(handle.0.to_string()));
+
+        h.reply(&broadcast, "ok");
+        h.step_until(|h| !h.acks.is_empty());
+
+        assert_eq!(h.acks.len(), 1);
+        assert_eq!(h.acks[0].handle, handle);
+        assert_eq!(h.acks[0].result, BroadcastAckResult::Ok);
+        assert_eq!(h.acks[0].client.as_deref(), Some("test"));
+    }
+
+    #[test]
+    fn broadcast_ack_without_reply_times_out() {
+        let mut h = acked_harness(Duration::from_millis(50));
+        h.join();
+
+        let handle = h
+            .channel
+            .broadcast(BroadcastPayload::new("test", HashMap::new()))
+            .unwrap();
+
+        h.expect(MessageEvent::Broadcast);
+        h.step_until(|h| !h.acks.is_empty());
+
+        assert_eq!(h.acks.len(), 1);
+        assert_eq!(h.acks[0].handle, handle);
+        assert_eq!(h.acks[0].result, BroadcastAckResult::Timeout);
+    }
 }
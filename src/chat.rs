@@ -0,0 +1,674 @@
+use std::{
+    collections::{BTreeSet, HashMap, HashSet, VecDeque},
+    sync::Arc,
+    time::{Instant, SystemTime, UNIX_EPOCH},
+};
+
+use bevy::{ecs::system::SystemId, prelude::*};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use uuid::Uuid;
+
+use crate::{
+    channel::ChannelBuilder,
+    client_ready,
+    message::payload::{BroadcastConfig, BroadcastPayload, PresenceConfig},
+    presence::{PrescenceTrack, PresenceEvent, PresenceState},
+    rate_limit::TokenBucket,
+    BevyChannelBuilder, BuildChannel, Channel, Client,
+};
+
+const MESSAGE_EVENT: &str = "chat_message";
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ChatMessage {
+    pub id: Uuid,
+    pub room: String,
+    /// Presence key of the sender
+    pub sender: String,
+    pub text: String,
+    /// Sender's time in milliseconds since the Unix epoch
+    pub timestamp: u64,
+}
+
+/// What a [ChatPlugin::filter] does with a message
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FilterAction {
+    Allow,
+    /// Allow with the text replaced, e.g. masked profanity
+    Replace(String),
+    Block,
+}
+
+type ChatFilter = dyn Fn(&ChatMessage) -> FilterAction + Send + Sync;
+
+/// Chat rooms, each on its own channel.
+///
+/// Messages pass through the filters before being sent and again when received, so peers
+/// can't skip local moderation. Each sender, including this one, is held to the rate limit;
+/// received messages over it are dropped. Typing state is tracked as presence meta.
+///
+/// Received messages belong to the room of the channel they arrived on, and are dropped
+/// unless their sender is present in that room. Presence keys are picked by each client, so
+/// this stops casual spoofing but not a client tracking under someone else's key; use Realtime
+/// authorization to tie keys to users.
+///
+/// Drive it with [ChatCommand] events and follow it through [ChatEvent]s, [ChatHistory] and
+/// [ChatTyping]. Requires an unnamed [crate::RealtimePlugin].
+pub struct ChatPlugin {
+    topic_prefix: String,
+    player_key: String,
+    history: usize,
+    rate: usize,
+    burst: usize,
+    filters: Vec<Arc<ChatFilter>>,
+}
+
+impl ChatPlugin {
+    pub fn new(topic_prefix: impl Into<String>) -> Self {
+        Self {
+            topic_prefix: topic_prefix.into(),
+            player_key: Uuid::new_v4().to_string(),
+            history: 100,
+            rate: 1,
+            burst: 5,
+            filters: vec![],
+        }
+    }
+
+    /// Set the presence key identifying this player.
+    /// Default: random
+    pub fn player_key(mut self, player_key: impl Into<String>) -> Self {
+        self.player_key = player_key.into();
+        self
+    }
+
+    /// Set the number of messages kept per room.
+    /// Default: 100
+    pub fn history(mut self, history: usize) -> Self {
+        self.history = history;
+        self
+    }
+
+    /// Limit each sender to `rate` messages per second, with bursts of up to `burst`.
+    /// Default: 1 per second, bursts of 5
+    pub fn rate_limit(mut self, rate: usize, burst: usize) -> Self {
+        self.rate = rate;
+        self.burst = burst;
+        self
+    }
+
+    /// Add a moderation filter. Filters run in the order they were added.
+    pub fn filter(
+        mut self,
+        filter: impl Fn(&ChatMessage) -> FilterAction + Send + Sync + 'static,
+    ) -> Self {
+        self.filters.push(Arc::new(filter));
+        self
+    }
+}
+
+impl Plugin for ChatPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(ChatConfig {
+            topic_prefix: self.topic_prefix.clone(),
+            player_key: self.player_key.clone(),
+            history: self.history,
+            rate: self.rate,
+            burst: self.burst,
+            filters: self.filters.clone(),
+        })
+        .init_resource::<ChatRooms>()
+        .init_resource::<ChatHistory>()
+        .init_resource::<ChatTyping>()
+        .init_resource::<ChatRateLimits>()
+        .add_event::<ChatCommand>()
+        .add_event::<ChatEvent>()
+        .add_systems(Startup, setup_chat)
+        .add_systems(Update, handle_chat_commands.run_if(client_ready));
+    }
+}
+
+/// Requests handled by [ChatPlugin]
+#[derive(Event, Debug, Clone)]
+pub enum ChatCommand {
+    Join(String),
+    Leave(String),
+    Send { room: String, text: String },
+    SetTyping { room: String, typing: bool },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RejectReason {
+    NotJoined,
+    RateLimited,
+    Filtered,
+}
+
+#[derive(Event, Debug, Clone, PartialEq, Eq)]
+pub enum ChatEvent {
+    /// A message was sent or received, after filtering
+    Message(ChatMessage),
+    /// A message from this player wasn't sent
+    Rejected { room: String, reason: RejectReason },
+    /// The players typing in a room changed
+    Typing {
+        room: String,
+        players: BTreeSet<String>,
+    },
+}
+
+/// Recent messages by room, oldest first
+#[derive(Resource, Debug, Default)]
+pub struct ChatHistory(HashMap<String, VecDeque<ChatMessage>>);
+
+impl ChatHistory {
+    pub fn room(&self, room: &str) -> impl Iterator<Item = &ChatMessage> {
+        self.0.get(room).into_iter().flatten()
+    }
+
+    pub fn clear(&mut self, room: &str) {
+        self.0.remove(room);
+    }
+}
+
+/// Presence keys of the players typing, by room
+#[derive(Resource, Debug, Default)]
+pub struct ChatTyping(HashMap<String, BTreeSet<String>>);
+
+impl ChatTyping {
+    pub fn room(&self, room: &str) -> impl Iterator<Item = &str> {
+        self.0.get(room).into_iter().flatten().map(String::as_str)
+    }
+}
+
+/// Room of the chat channel on this entity
+#[derive(Component, Debug, Clone, PartialEq, Eq, Deref)]
+pub struct ChatRoom(pub String);
+
+#[derive(Resource)]
+struct ChatConfig {
+    topic_prefix: String,
+    player_key: String,
+    history: usize,
+    rate: usize,
+    burst: usize,
+    filters: Vec<Arc<ChatFilter>>,
+}
+
+impl ChatConfig {
+    /// Runs the filters, returning None if the message is blocked
+    fn filter(&self, mut message: ChatMessage) -> Option<ChatMessage> {
+        for filter in self.filters.iter() {
+            match filter(&message) {
+                FilterAction::Allow => {}
+                FilterAction::Replace(text) => message.text = text,
+                FilterAction::Block => return None,
+            }
+        }
+
+        Some(message)
+    }
+}
+
+/// Joined rooms with their channel entity once built
+#[derive(Resource, Default)]
+struct ChatRooms {
+    joined: HashMap<String, Option<Entity>>,
+    pending: VecDeque<String>,
+    /// Presence keys in each room
+    members: HashMap<String, HashSet<String>>,
+}
+
+impl ChatRooms {
+    fn is_member(&self, room: &str, player: &str) -> bool {
+        self.members
+            .get(room)
+            .is_some_and(|members| members.contains(player))
+    }
+}
+
+/// Rate limits of this player and the members of the joined rooms
+#[derive(Resource, Default)]
+struct ChatRateLimits(HashMap<String, TokenBucket>);
+
+impl ChatRateLimits {
+    fn try_take(&mut self, sender: &str, config: &ChatConfig) -> bool {
+        self.0
+            .entry(sender.into())
+            .or_insert_with(|| TokenBucket::new(config.rate, config.burst))
+            .try_take(Instant::now())
+    }
+
+    /// Forgets the limits of players no longer in any joined room
+    fn retain_members(&mut self, rooms: &ChatRooms, config: &ChatConfig) {
+        self.0.retain(|sender, _| {
+            *sender == config.player_key
+                || rooms
+                    .members
+                    .values()
+                    .any(|members| members.contains(sender))
+        });
+    }
+}
+
+/// Callbacks of a room's channel, unregistered when the room is left
+#[derive(Component)]
+struct ChatRoomSystems {
+    on_message: SystemId<In<HashMap<String, Value>>>,
+    on_state: SystemId<In<PresenceState>>,
+    on_change: SystemId<In<(String, PresenceState, PresenceState)>>,
+}
+
+#[derive(Resource)]
+struct ChatSystems {
+    build_room: SystemId<In<ChannelBuilder>>,
+}
+
+fn setup_chat(world: &mut World) {
+    let build_room = world.register_system(build_chat_room);
+    world.insert_resource(ChatSystems { build_room });
+}
+
+fn push_history(history: &mut ChatHistory, config: &ChatConfig, message: ChatMessage) {
+    let messages = history.0.entry(message.room.clone()).or_default();
+    messages.push_back(message);
+
+    while messages.len() > config.history {
+        messages.pop_front();
+    }
+}
+
+fn handle_chat_commands(
+    mut commands: Commands,
+    mut evr: EventReader<ChatCommand>,
+    mut evw: EventWriter<ChatEvent>,
+    mut rooms: ResMut<ChatRooms>,
+    mut history: ResMut<ChatHistory>,
+    mut typing: ResMut<ChatTyping>,
+    mut limits: ResMut<ChatRateLimits>,
+    config: Res<ChatConfig>,
+    systems: Res<ChatSystems>,
+    client: Res<Client>,
+    mut q: Query<(&Channel, &mut PrescenceTrack), With<ChatRoom>>,
+    room_systems: Query<&ChatRoomSystems>,
+) {
+    for command in evr.read() {
+        match command {
+            ChatCommand::Join(room) => {
+                if rooms.joined.contains_key(room) {
+                    continue;
+                }
+
+                if client.channel(systems.build_room).is_err() {
+                    error!("Failed to create chat channel for {}", room);
+                    continue;
+                }
+
+                rooms.joined.insert(room.clone(), None);
+                rooms.pending.push_back(room.clone());
+            }
+            ChatCommand::Leave(room) => {
+                let Some(entity) = rooms.joined.remove(room) else {
+                    continue;
+                };
+
+                if let Some(entity) = entity {
+                    if let Ok((channel, _)) = q.get(entity) {
+                        let _ = channel.unsubscribe();
+                    }
+
+                    if let Ok(room_systems) = room_systems.get(entity) {
+                        commands.unregister_system(room_systems.on_message);
+                        commands.unregister_system(room_systems.on_state);
+                        commands.unregister_system(room_systems.on_change);
+                    }
+
+                    commands.entity(entity).despawn_recursive();
+                }
+
+                typing.0.remove(room);
+                rooms.members.remove(room);
+                limits.retain_members(&rooms, &config);
+            }
+            ChatCommand::Send { room, text } => {
+                let channel = rooms
+                    .joined
+                    .get(room)
+                    .copied()
+                    .flatten()
+                    .and_then(|entity| q.get(entity).ok());
+
+                let Some((channel, _)) = channel else {
+                    evw.send(ChatEvent::Rejected {
+                        room: room.clone(),
+                        reason: RejectReason::NotJoined,
+                    });
+                    continue;
+                };
+
+                if !limits.try_take(&config.player_key, &config) {
+                    evw.send(ChatEvent::Rejected {
+                        room: room.clone(),
+                        reason: RejectReason::RateLimited,
+                    });
+                    continue;
+                }
+
+                let timestamp = SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .unwrap_or_default()
+                    .as_millis() as u64;
+
+                let Some(message) = config.filter(ChatMessage {
+                    id: Uuid::new_v4(),
+                    room: room.clone(),
+                    sender: config.player_key.clone(),
+                    text: text.clone(),
+                    timestamp,
+                }) else {
+                    evw.send(ChatEvent::Rejected {
+                        room: room.clone(),
+                        reason: RejectReason::Filtered,
+                    });
+                    continue;
+                };
+
+                let Ok(Value::Object(payload)) = serde_json::to_value(&message) else {
+                    continue;
+                };
+
+                let _ = channel.broadcast(BroadcastPayload {
+                    event: MESSAGE_EVENT.into(),
+                    payload: payload.into_iter().collect(),
+                    ..Default::default()
+                });
+
+                push_history(&mut history, &config, message.clone());
+                evw.send(ChatEvent::Message(message));
+            }
+            ChatCommand::SetTyping { room, typing } => {
+                if let Some(Some(entity)) = rooms.joined.get(room) {
+                    if let Ok((_, mut track)) = q.get_mut(*entity) {
+                        track.payload.insert("typing".into(), (*typing).into());
+                    }
+                }
+            }
+        }
+    }
+}
+
+fn build_chat_room(
+    mut channel_builder: In<ChannelBuilder>,
+    mut commands: Commands,
+    mut rooms: ResMut<ChatRooms>,
+    config: Res<ChatConfig>,
+) {
+    // Channel requests are answered in order
+    let Some(room) = rooms.pending.pop_front() else {
+        return;
+    };
+
+    // Left again before the channel was built, or already built for an earlier request
+    if rooms.joined.get(&room) != Some(&None) {
+        return;
+    }
+
+    let on_message = commands.register_system(on_chat_message(room.clone()));
+    let on_state = commands.register_system(on_chat_presence(room.clone()));
+
+    let change_room = room.clone();
+    let on_change = commands.register_system(
+        move |_: In<(String, PresenceState, PresenceState)>, q: Query<(&Channel, &ChatRoom)>| {
+            for (channel, chat_room) in q.iter() {
+                if chat_room.0 == change_room {
+                    let _ = channel.presence_state(on_state);
+                }
+            }
+        },
+    );
+
+    channel_builder
+        .topic(format!("{}:{}", config.topic_prefix, room))
+        .set_broadcast_config(BroadcastConfig {
+            broadcast_self: false,
+            ack: false,
+        })
+        .set_presence_config(PresenceConfig {
+            key: Some(config.player_key.clone()),
+        })
+        .on_broadcast(MESSAGE_EVENT, on_message)
+        .on_presence(PresenceEvent::Join, on_change)
+        .on_presence(PresenceEvent::Leave, on_change);
+
+    let mut track = HashMap::new();
+    track.insert("typing".into(), false.into());
+
+    let entity = commands
+        .spawn((
+            BevyChannelBuilder(channel_builder.0),
+            BuildChannel,
+            ChatRoom(room.clone()),
+            ChatRoomSystems {
+                on_message,
+                on_state,
+                on_change,
+            },
+            PrescenceTrack { payload: track },
+        ))
+        .id();
+
+    rooms.joined.insert(room, Some(entity));
+}
+
+/// Handles messages received on the channel of `room`
+fn on_chat_message(room: String) -> impl System<In = In<HashMap<String, Value>>, Out = ()> {
+    IntoSystem::into_system(
+        move |In(payload): In<HashMap<String, Value>>,
+              mut evw: EventWriter<ChatEvent>,
+              mut history: ResMut<ChatHistory>,
+              mut limits: ResMut<ChatRateLimits>,
+              rooms: Res<ChatRooms>,
+              config: Res<ChatConfig>| {
+            let mut message = match serde_json::from_value::<ChatMessage>(Value::Object(
+                payload.into_iter().collect(),
+            )) {
+                Ok(message) => message,
+                Err(e) => {
+                    debug!("Malformed chat message: {}", e);
+                    return;
+                }
+            };
+
+            message.room = room.clone();
+
+            if !rooms.joined.contains_key(&room) {
+                return;
+            }
+
+            if !rooms.is_member(&room, &message.sender) {
+                debug!(
+                    "Dropped chat message from {}, not present in {}",
+                    message.sender, room
+                );
+                return;
+            }
+
+            if !limits.try_take(&message.sender, &config) {
+                debug!(
+                    "Dropped chat message from {}, over rate limit",
+                    message.sender
+                );
+                return;
+            }
+
+            let Some(message) = config.filter(message) else {
+                return;
+            };
+
+            push_history(&mut history, &config, message.clone());
+            evw.send(ChatEvent::Message(message));
+        },
+    )
+}
+
+/// Updates the members and typing players of `room` from its presence state
+fn on_chat_presence(room: String) -> impl System<In = In<PresenceState>, Out = ()> {
+    IntoSystem::into_system(
+        move |In(state): In<PresenceState>,
+              mut rooms: ResMut<ChatRooms>,
+              mut limits: ResMut<ChatRateLimits>,
+              mut typing: ResMut<ChatTyping>,
+              mut evw: EventWriter<ChatEvent>,
+              config: Res<ChatConfig>| {
+            // Left since the state was requested
+            if !rooms.joined.contains_key(&room) {
+                return;
+            }
+
+            rooms
+                .members
+                .insert(room.clone(), state.0.keys().cloned().collect());
+            limits.retain_members(&rooms, &config);
+
+            let players: BTreeSet<String> = state
+                .0
+                .iter()
+                .filter(|(_, metas)| {
+                    metas
+                        .values()
+                        .any(|meta| meta.get("typing") == Some(&Value::Bool(true)))
+                })
+                .map(|(key, _)| key.clone())
+                .collect();
+
+            if typing.0.get(&room) != Some(&players) {
+                typing.0.insert(room.clone(), players.clone());
+                evw.send(ChatEvent::Typing {
+                    room: room.clone(),
+                    players,
+                });
+            }
+        },
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::ecs::system::RunSystemOnce;
+    use serde_json::json;
+
+    use super::*;
+
+    fn app() -> App {
+        let mut app = App::new();
+        app.insert_resource(ChatConfig {
+            topic_prefix: "chat".into(),
+            player_key: "me".into(),
+            history: 10,
+            rate: 1,
+            burst: 5,
+            filters: vec![],
+        })
+        .insert_resource(ChatRooms {
+            joined: HashMap::from([("general".into(), None), ("trade".into(), None)]),
+            ..Default::default()
+        })
+        .init_resource::<ChatHistory>()
+        .init_resource::<ChatTyping>()
+        .init_resource::<ChatRateLimits>()
+        .add_event::<ChatEvent>();
+        app
+    }
+
+    fn presence(app: &mut App, room: &str, members: &[&str]) {
+        let state = PresenceState(
+            members
+                .iter()
+                .map(|key| (key.to_string(), HashMap::new()))
+                .collect(),
+        );
+
+        app.world_mut()
+            .run_system_once_with(state, on_chat_presence(room.into()))
+            .unwrap();
+    }
+
+    fn receive(app: &mut App, room: &str, sender: &str, payload_room: &str) {
+        let Value::Object(payload) = json!({
+            "id": Uuid::new_v4(),
+            "room": payload_room,
+            "sender": sender,
+            "text": "hi",
+            "timestamp": 0,
+        }) else {
+            unreachable!();
+        };
+
+        app.world_mut()
+            .run_system_once_with(payload.into_iter().collect(), on_chat_message(room.into()))
+            .unwrap();
+    }
+
+    fn received(app: &App) -> Vec<(String, String)> {
+        app.world()
+            .resource::<Events<ChatEvent>>()
+            .iter_current_update_events()
+            .filter_map(|event| match event {
+                ChatEvent::Message(message) => Some((message.room.clone(), message.sender.clone())),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn messages_belong_to_the_receiving_room() {
+        let mut app = app();
+        presence(&mut app, "general", &["me", "them"]);
+
+        receive(&mut app, "general", "them", "trade");
+
+        assert_eq!(received(&app), vec![("general".into(), "them".into())]);
+        assert_eq!(
+            app.world().resource::<ChatHistory>().room("trade").count(),
+            0
+        );
+    }
+
+    #[test]
+    fn messages_from_absent_senders_are_dropped() {
+        let mut app = app();
+        presence(&mut app, "general", &["me", "them"]);
+
+        receive(&mut app, "general", "someone", "general");
+        receive(&mut app, "trade", "them", "trade");
+
+        assert!(received(&app).is_empty());
+    }
+
+    #[test]
+    fn rate_limits_are_dropped_with_members() {
+        let mut app = app();
+        presence(&mut app, "general", &["me", "them"]);
+        presence(&mut app, "trade", &["me", "them", "trader"]);
+
+        receive(&mut app, "general", "them", "general");
+        receive(&mut app, "trade", "trader", "trade");
+
+        assert_eq!(app.world().resource::<ChatRateLimits>().0.len(), 2);
+
+        presence(&mut app, "trade", &["me"]);
+
+        // Still in general
+        assert!(app
+            .world()
+            .resource::<ChatRateLimits>()
+            .0
+            .contains_key("them"));
+        assert!(!app
+            .world()
+            .resource::<ChatRateLimits>()
+            .0
+            .contains_key("trader"));
+    }
+}
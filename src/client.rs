@@ -3,11 +3,11 @@
 use std::error::Error;
 use std::fmt::{Debug, Display};
 use std::thread::sleep;
-use std::time::SystemTime;
+use std::time::{Instant, SystemTime};
 use std::{
     collections::HashMap,
     io,
-    net::{TcpStream, ToSocketAddrs},
+    net::{SocketAddr, TcpStream, ToSocketAddrs},
     time::Duration,
 };
 
@@ -15,8 +15,7 @@ use bevy::ecs::system::SystemId;
 use bevy::log::{debug, info};
 use bevy::prelude::*;
 use bevy_crossbeam_event::CrossbeamEventSender;
-use crossbeam::channel::{unbounded, Receiver, SendError, Sender, TryRecvError};
-use native_tls::TlsConnector;
+use crossbeam::channel::{unbounded, Receiver, RecvTimeoutError, SendError, Sender, TryRecvError};
 use tungstenite::{client, Message};
 use tungstenite::{
     client::{uri_mode, IntoClientRequest},
@@ -25,17 +24,38 @@ use tungstenite::{
     stream::{MaybeTlsStream, Mode, NoDelay},
     ClientHandshake, Error as TungsteniteError, HandshakeError, WebSocket as WebSocketWrapper,
 };
+use url::Url;
 use uuid::Uuid;
 
 use super::channel::{ChannelState, RealtimeChannel};
+use crate::delta::DeltaCodec;
 use crate::message::payload::Payload;
-use crate::message::realtime_message::RealtimeMessage;
+use crate::message::realtime_message::{MessageEvent, RealtimeMessage};
+use crate::outbound::{
+    bounded_channel, BoundedMessage, BoundedReceiver, BoundedSender, OutboundBackpressure,
+    OutboundDepth, OutboundMessage, OutboundQueue, QueueBound,
+};
+use crate::proxy::{ProxyConfig, ProxyErrorKind};
+use crate::rate_limit::TokenBucket;
+use crate::tls::{self, TlsConfig};
 
 use super::channel::ChannelBuilder;
 
 pub type Response = HttpResponse<Option<Vec<u8>>>;
 pub type WebSocket = WebSocketWrapper<MaybeTlsStream<TcpStream>>;
 
+/// Headers managed by the WebSocket handshake that configured headers may not override
+const WEBSOCKET_HANDSHAKE_HEADERS: [&str; 5] = [
+    "host",
+    "connection",
+    "upgrade",
+    "sec-websocket-version",
+    "sec-websocket-key",
+];
+
+/// Delay between starting concurrent connection attempts, as recommended by RFC 8305
+const CONNECTION_ATTEMPT_DELAY: Duration = Duration::from_millis(250);
+
 /// Connection state of [RealtimeClient]
 #[derive(PartialEq, Debug, Default, Clone, Copy, Event)]
 pub enum ConnectionState {
@@ -50,6 +70,13 @@ pub enum ConnectionState {
     Closed,
 }
 
+/// [ConnectionState] of a named client, see [crate::RealtimePlugin::named]
+#[derive(PartialEq, Debug, Clone, Event)]
+pub struct NamedConnectionState {
+    pub client: String,
+    pub state: ConnectionState,
+}
+
 /// Error returned by [RealtimeClient::next_message()].
 /// Can be WouldBlock
 #[derive(PartialEq, Debug)]
@@ -94,7 +121,8 @@ pub enum SocketError {
 /// Error returned by [RealtimeClient::connect()]
 #[derive(Debug, PartialEq, Clone, Copy)]
 pub enum ConnectError {
-    BadUri,
+    /// Endpoint could not be turned into a socket URL, the details are logged
+    BadUri(UriErrorKind),
     BadHost,
     BadAddrs,
     StreamError,
@@ -103,11 +131,30 @@ pub enum ConnectError {
     HandshakeError,
     MaxRetries,
     WrongProtocol,
+    TlsError,
+    BadAccessToken,
+    /// Connecting through the configured proxy failed, the details are logged
+    ProxyError(ProxyErrorKind),
 }
 
-pub(crate) struct MessageChannel((Sender<RealtimeMessage>, Receiver<RealtimeMessage>));
+/// Why the endpoint could not be turned into a socket URL
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UriErrorKind {
+    /// Endpoint is not a valid URL
+    Parse(url::ParseError),
+    /// Scheme is not one of http, https, ws or wss
+    UnsupportedScheme,
+    /// URL has no host to connect to
+    MissingHost,
+    /// URL can't have path segments, e.g. `mailto:`
+    CannotBeABase,
+    /// Socket URL was rejected when building the upgrade request
+    InvalidRequest,
+}
+
+pub(crate) struct MessageChannel<T = RealtimeMessage>((Sender<T>, Receiver<T>));
 
-impl Default for MessageChannel {
+impl<T> Default for MessageChannel<T> {
     fn default() -> Self {
         Self(crossbeam::channel::unbounded())
     }
@@ -128,7 +175,8 @@ enum MonitorSignal {
 
 #[derive(Clone)]
 pub struct ClientManager {
-    tx: Sender<ClientManagerMessage>,
+    tx: BoundedSender<ClientManagerMessage>,
+    outbound_depth: OutboundDepth,
 }
 
 pub enum ClientManagerMessage {
@@ -136,7 +184,7 @@ pub enum ClientManagerMessage {
         callback: SystemId<In<ChannelBuilder>>,
     },
     AddChannel {
-        channel: RealtimeChannel,
+        channel: Box<RealtimeChannel>,
     },
     SetAccessToken {
         token: String,
@@ -144,18 +192,40 @@ pub enum ClientManagerMessage {
     ConnectionState {
         sender: CrossbeamEventSender<ConnectionState>,
     },
+    NamedConnectionState {
+        name: String,
+        sender: CrossbeamEventSender<NamedConnectionState>,
+    },
     Connect {
         callback: SystemId<In<Result<(), ConnectError>>>,
     },
 }
 
+// Nothing sent to the client itself is safe to discard
+impl BoundedMessage for ClientManagerMessage {
+    fn bounded(&self) -> bool {
+        false
+    }
+}
+
 impl ClientManager {
     pub fn new(client: &Client) -> Self {
         Self {
             tx: client.manager_tx.clone(),
+            outbound_depth: client.outbound_depth.clone(),
         }
     }
 
+    /// Number of messages waiting for the client to process them
+    pub fn queue_depth(&self) -> usize {
+        self.tx.len()
+    }
+
+    /// Number of messages waiting to be written to the socket, as of the client's last step
+    pub fn outbound_depth(&self) -> usize {
+        self.outbound_depth.get()
+    }
+
     pub fn connect(
         &self,
         callback: SystemId<In<Result<(), ConnectError>>>,
@@ -174,7 +244,9 @@ impl ClientManager {
         &self,
         channel: RealtimeChannel,
     ) -> Result<(), SendError<ClientManagerMessage>> {
-        self.tx.send(ClientManagerMessage::AddChannel { channel })
+        self.tx.send(ClientManagerMessage::AddChannel {
+            channel: Box::new(channel),
+        })
     }
 
     pub fn set_access_token(&self, token: String) -> Result<(), SendError<ClientManagerMessage>> {
@@ -188,6 +260,16 @@ impl ClientManager {
         self.tx
             .send(ClientManagerMessage::ConnectionState { sender })
     }
+
+    /// Like [ClientManager::connection_state], with the state tagged with `name`
+    pub fn named_connection_state(
+        &self,
+        name: String,
+        sender: CrossbeamEventSender<NamedConnectionState>,
+    ) -> Result<(), SendError<ClientManagerMessage>> {
+        self.tx
+            .send(ClientManagerMessage::NamedConnectionState { name, sender })
+    }
 }
 
 /// Synchronous websocket client that interfaces with Supabase Realtime
@@ -195,11 +277,16 @@ pub struct Client {
     pub(crate) access_token: String,
     connection_state: ConnectionState,
     socket: Option<WebSocket>,
+    connected_addr: Option<SocketAddr>,
     channels: HashMap<Uuid, RealtimeChannel>,
-    messages_this_second: Vec<SystemTime>,
+    rate_limiter: TokenBucket,
     next_ref: Uuid,
     // mpsc
-    pub(crate) outbound_channel: MessageChannel,
+    pub(crate) outbound_channel: MessageChannel<OutboundMessage>,
+    outbound_queue: OutboundQueue,
+    pub(crate) outbound_depth: OutboundDepth,
+    outbound_dropped: usize,
+    outbound_saturated: bool,
     inbound_channel: MessageChannel,
     monitor_channel: MonitorChannel,
     middleware: HashMap<Uuid, Box<dyn Fn(RealtimeMessage) -> RealtimeMessage + Send + Sync>>,
@@ -208,23 +295,39 @@ pub struct Client {
     reconnect_delay: Duration,
     reconnect_attempts: usize,
     heartbeat_now: Option<SystemTime>,
+    pending_heartbeat: Option<(String, Instant)>,
     // builder options
     headers: HeaderMap,
     params: Option<HashMap<String, String>>,
     heartbeat_interval: Duration,
     encode: Option<Box<dyn Fn(RealtimeMessage) -> RealtimeMessage + Send + Sync>>,
-    decode: Option<Box<dyn Fn(RealtimeMessage) -> RealtimeMessage + Send + Sync>>,
+    decode: Option<Box<dyn Fn(RealtimeMessage) -> Option<RealtimeMessage> + Send + Sync>>,
     reconnect_interval: ReconnectFn,
     reconnect_max_attempts: usize,
     connection_timeout: Duration,
     auth_url: Option<String>,
     endpoint: String,
     max_events_per_second: usize,
+    tls_config: Option<TlsConfig>,
+    proxy: ProxyConfig,
+    pub(crate) outbound_bound: Option<QueueBound>,
+    pub(crate) manager_bound: Option<QueueBound>,
     // sync bridge
-    manager_rx: Receiver<ClientManagerMessage>,
-    manager_tx: Sender<ClientManagerMessage>,
+    manager_rx: BoundedReceiver<ClientManagerMessage>,
+    manager_tx: BoundedSender<ClientManagerMessage>,
     channel_callback_event_sender: CrossbeamEventSender<ChannelCallbackEvent>,
     connect_result_callback_event_sender: CrossbeamEventSender<ConnectResultCallbackEvent>,
+    outbound_backpressure_event_sender: Option<CrossbeamEventSender<OutboundBackpressure>>,
+    latency_sample_event_sender: Option<CrossbeamEventSender<LatencySample>>,
+    name: Option<String>,
+}
+
+/// Round trip time of a heartbeat, sent when its reply arrives
+#[derive(Event, Debug, Clone, PartialEq)]
+pub struct LatencySample {
+    /// Name of the client, see [ClientBuilder::name]
+    pub client: Option<String>,
+    pub rtt: Duration,
 }
 
 #[derive(Event, Clone)]
@@ -250,13 +353,19 @@ impl Client {
                     self.channel_callback_event_sender
                         .send(ChannelCallbackEvent((callback, c)));
                 }
-                ClientManagerMessage::AddChannel { channel } => self.add_channel(channel),
+                ClientManagerMessage::AddChannel { channel } => self.add_channel(*channel),
                 ClientManagerMessage::SetAccessToken { token } => {
                     self.access_token = token;
                 }
                 ClientManagerMessage::ConnectionState { sender } => {
                     sender.send(self.connection_state);
                 }
+                ClientManagerMessage::NamedConnectionState { name, sender } => {
+                    sender.send(NamedConnectionState {
+                        client: name,
+                        state: self.connection_state,
+                    });
+                }
                 ClientManagerMessage::Connect { callback } => {
                     let result = self.connect();
                     self.connect_result_callback_event_sender
@@ -277,6 +386,11 @@ impl Client {
         self.connection_state
     }
 
+    /// Returns the socket address the current connection was established with, if connected
+    pub fn connected_addr(&self) -> Option<SocketAddr> {
+        self.connected_addr
+    }
+
     /// Returns a new [RealtimeChannelBuilder] instantiated with the provided `topic`
     pub fn channel(&mut self) -> ChannelBuilder {
         ChannelBuilder::new(self)
@@ -289,69 +403,22 @@ impl Client {
 
         let _ = self.manager_recv();
 
-        let uri: Uri = match format!(
-            "{}/websocket?apikey={}&vsn=1.0.0",
-            self.endpoint, self.access_token
-        )
-        .parse()
-        {
-            Ok(uri) => uri,
-            Err(_e) => return Err(ConnectError::BadUri),
-        };
+        let uri = socket_uri(&self.endpoint, &self.access_token, self.params.as_ref())?;
 
-        // TODO REFAC tidy
-        let ws_scheme = match uri.scheme_str() {
-            Some(scheme) => {
-                if scheme == "http" {
-                    "ws"
-                } else {
-                    "wss"
-                }
-            }
-            None => "ws",
+        let mut request = match uri.into_client_request() {
+            Ok(request) => request,
+            Err(e) => return Err(bad_uri(UriErrorKind::InvalidRequest, e)),
         };
 
-        let mut add_params = String::new();
-        if let Some(params) = &self.params {
-            for (field, value) in params {
-                add_params = format!("{add_params}&{field}={value}");
-            }
-        }
-
-        let mut p_q = uri.path_and_query().unwrap().to_string();
-
-        if !add_params.is_empty() {
-            p_q = format!("{p_q}{add_params}");
-        }
-
-        let uri = Uri::builder()
-            .scheme(ws_scheme)
-            .authority(uri.authority().unwrap().clone())
-            .path_and_query(p_q)
-            .build()
-            .unwrap();
-
-        let Ok(mut request) = uri.clone().into_client_request() else {
-            return Err(ConnectError::BadUri);
-        };
-
-        let headers = request.headers_mut();
-
-        let auth: HeaderValue = format!("Bearer {}", self.access_token)
-            .parse()
-            .expect("malformed access token?");
-        headers.insert("Authorization", auth);
-
-        // unwrap: shouldn't fail
-        let xci: HeaderValue = "realtime-rs/0.1.0".to_string().parse().unwrap();
-        headers.insert("X-Client-Info", xci);
+        merge_handshake_headers(request.headers_mut(), &self.headers, &self.access_token)?;
 
         debug!("Connecting... Req: {:?}\n", request);
 
         let uri = request.uri();
 
-        let Ok(mode) = uri_mode(uri) else {
-            return Err(ConnectError::BadUri);
+        let mode = match uri_mode(uri) {
+            Ok(mode) => mode,
+            Err(e) => return Err(bad_uri(UriErrorKind::InvalidRequest, e)),
         };
 
         let Some(host) = uri.host() else {
@@ -363,21 +430,39 @@ impl Client {
             Mode::Tls => 443,
         });
 
-        let Ok(mut addrs) = (host, port).to_socket_addrs() else {
-            return Err(ConnectError::BadAddrs);
+        let proxy = self.proxy.resolve(host, matches!(mode, Mode::Tls))?;
+
+        let connected = match proxy {
+            Some(proxy) => proxy
+                .connect(host, port, self.connection_timeout)
+                .map_err(Some),
+            None => {
+                let Ok(addrs) = (host, port).to_socket_addrs() else {
+                    return Err(ConnectError::BadAddrs);
+                };
+
+                let addrs = interleave_addrs(addrs.collect());
+
+                if addrs.is_empty() {
+                    return Err(ConnectError::BadAddrs);
+                }
+
+                connect_happy_eyeballs(&addrs, self.connection_timeout).map_err(|e| {
+                    debug!("All addresses for {} failed, last error: {:?}", host, e);
+                    None
+                })
+            }
         };
 
-        let mut stream = match TcpStream::connect_timeout(
-            &addrs.next().expect("uhoh no addr"),
-            self.connection_timeout,
-        ) {
-            Ok(stream) => {
+        let mut stream = match connected {
+            Ok((stream, addr)) => {
+                debug!("TCP connection established with {}", addr);
                 self.reconnect_attempts = 0;
+                self.connected_addr = Some(addr);
                 stream
             }
-            Err(_e) => {
-                // TODO err data
-                return self.retry_connect();
+            Err(error) => {
+                return self.retry_connect(error);
             }
         };
 
@@ -385,28 +470,15 @@ impl Client {
             return Err(ConnectError::NoDelayError);
         };
 
-        let maybe_tls = match mode {
-            Mode::Tls => {
-                let connector = TlsConnector::new().expect("No TLS tings");
-
-                let connected_stream = connector
-                    .connect(host, stream.try_clone().expect("noclone"))
-                    .unwrap();
-
-                stream
-                    .set_nonblocking(true)
-                    .expect("blocking mode oh nooooo");
+        let Ok(handshake_stream) = stream.try_clone() else {
+            return Err(ConnectError::StreamError);
+        };
 
-                MaybeTlsStream::NativeTls(connected_stream)
-            }
-            Mode::Plain => {
-                stream
-                    .set_nonblocking(true)
-                    .expect("blocking mode oh nooooo");
+        let maybe_tls = tls::wrap_stream(handshake_stream, mode, host, self.tls_config.as_ref())?;
 
-                MaybeTlsStream::Plain(stream)
-            }
-        };
+        stream
+            .set_nonblocking(true)
+            .expect("blocking mode oh nooooo");
 
         let conn: Result<(WebSocket, Response), TungsteniteError> = match client(request, maybe_tls)
         {
@@ -415,15 +487,15 @@ impl Client {
                 Ok(stream)
             }
             Err(err) => match err {
-                HandshakeError::Failure(_err) => {
-                    // TODO err data
-                    return self.retry_connect();
+                HandshakeError::Failure(err) => {
+                    debug!("Handshake failed: {}", err);
+                    return self.retry_connect(None);
                 }
                 HandshakeError::Interrupted(mid_hs) => match self.retry_handshake(mid_hs) {
                     Ok(stream) => Ok(stream),
-                    Err(_err) => {
-                        // TODO err data
-                        return self.retry_connect();
+                    Err(err) => {
+                        debug!("Handshake failed: {:?}", err);
+                        return self.retry_connect(None);
                     }
                 },
             },
@@ -438,12 +510,20 @@ impl Client {
         self.socket = Some(socket);
 
         self.connection_state = ConnectionState::Open;
-        info!("connected");
+        self.heartbeat_now = None;
+        self.pending_heartbeat = None;
+
+        match self.connected_addr {
+            Some(addr) => info!("connected to {}", addr),
+            None => info!("connected"),
+        }
 
         Ok(())
     }
 
-    fn retry_connect(&mut self) -> Result<(), ConnectError> {
+    /// Reconnects after a backoff, failing with `error` or [ConnectError::MaxRetries] once out
+    /// of attempts
+    fn retry_connect(&mut self, error: Option<ConnectError>) -> Result<(), ConnectError> {
         debug!(
             "Retry count {}/{}",
             self.reconnect_attempts, self.reconnect_max_attempts
@@ -460,7 +540,7 @@ impl Client {
             return self.connect();
         }
 
-        Err(ConnectError::MaxRetries)
+        Err(error.unwrap_or(ConnectError::MaxRetries))
     }
 
     /// Disconnect the client
@@ -472,6 +552,7 @@ impl Client {
         self.remove_all_channels();
 
         self.connection_state = ConnectionState::Closed;
+        self.connected_addr = None;
 
         let Some(ref mut socket) = self.socket else {
             debug!("Already disconnected. {:?}", self.connection_state);
@@ -483,8 +564,14 @@ impl Client {
     }
 
     /// Queues a [RealtimeMessage] for sending to the server
+    // The unsent message is handed back to the caller
+    #[allow(clippy::result_large_err)]
     pub fn send(&mut self, msg: RealtimeMessage) -> Result<(), SendError<RealtimeMessage>> {
-        self.outbound_channel.0 .0.send(msg)
+        self.outbound_channel
+            .0
+             .0
+            .send(msg.into())
+            .map_err(|SendError(outbound)| SendError(outbound.message))
     }
 
     /// Returns an optional mutable reference to the [RealtimeChannel] with the provided [Uuid].
@@ -610,15 +697,21 @@ impl Client {
                 Ok(()) => {}
                 Err(e) => debug!("channel manager_recv error: {}", e),
             }
+
+            channel.check_push_timeouts();
         }
 
+        self.channels.retain(|_, channel| !channel.has_left());
+
+        self.queue_outbound();
+        self.report_backpressure();
+
         match self.connection_state {
             ConnectionState::Closed => {
                 return Err(NextMessageError::ClientClosed);
             }
             ConnectionState::Reconnect => {
                 let _ = self.monitor_channel.0 .0.send(MonitorSignal::Reconnect);
-                return Err(NextMessageError::SocketError(SocketError::Disconnected));
             }
             ConnectionState::Reconnecting => {
                 return Err(NextMessageError::WouldBlock);
@@ -640,8 +733,17 @@ impl Client {
             }
         }
 
+        // Waiting out the reconnect delay, the old socket is no use
+        if self.connection_state == ConnectionState::Reconnect {
+            return Err(NextMessageError::SocketError(SocketError::Disconnected));
+        }
+
         self.run_heartbeat();
 
+        for channel in self.channels.values_mut() {
+            channel.run_rejoin_timer();
+        }
+
         match self.write_socket() {
             Ok(()) => {}
             Err(SocketError::WouldBlock) => {}
@@ -662,6 +764,11 @@ impl Client {
         match self.inbound_channel.0 .1.try_recv() {
             Ok(mut message) => {
                 let mut ids = vec![];
+
+                if self.receive_heartbeat(&message) {
+                    return Ok(ids);
+                }
+
                 // TODO filter & route system messages and the like
 
                 // Run middleware
@@ -682,11 +789,12 @@ impl Client {
         }
     }
 
-    pub(crate) fn add_channel(&mut self, channel: RealtimeChannel) {
+    pub(crate) fn add_channel(&mut self, mut channel: RealtimeChannel) {
+        channel.client = self.name.clone();
         self.channels.insert(channel.id, channel);
     }
 
-    pub(crate) fn get_channel_tx(&self) -> Sender<RealtimeMessage> {
+    pub(crate) fn get_channel_tx(&self) -> Sender<OutboundMessage> {
         self.outbound_channel.0 .0.clone()
     }
 
@@ -796,9 +904,16 @@ impl Client {
                     self.reconnect_attempts += 1;
                     self.reconnect_now.take();
 
+                    for channel in self.channels.values_mut() {
+                        channel.connection_lost(&mut self.outbound_queue);
+                    }
+
+                    // Close the old connection, it may still be half-open after a missed heartbeat
+                    self.socket = None;
+
                     match self.connect() {
                         Ok(_) => {
-                            for channel in self.channels.values_mut() {
+                            for channel in self.channels.values_mut().filter(|c| !c.has_left()) {
                                 channel.subscribe().unwrap();
                             }
 
@@ -828,7 +943,50 @@ impl Client {
 
         self.heartbeat_now.take();
 
-        let _ = self.send(RealtimeMessage::heartbeat());
+        // No reply since the last heartbeat, the connection is likely half-open
+        if let Some((heartbeat_ref, _)) = self.pending_heartbeat.take() {
+            info!("Heartbeat {} timed out, reconnecting", heartbeat_ref);
+            self.reconnect();
+            return;
+        }
+
+        let heartbeat_ref = Uuid::new_v4().to_string();
+
+        let mut heartbeat = RealtimeMessage::heartbeat();
+        heartbeat.message_ref = Some(heartbeat_ref.clone());
+
+        if self.send(heartbeat).is_ok() {
+            self.pending_heartbeat = Some((heartbeat_ref, Instant::now()));
+        }
+    }
+
+    /// Records the round trip time if `message` is the reply to the pending heartbeat
+    fn receive_heartbeat(&mut self, message: &RealtimeMessage) -> bool {
+        if message.event != MessageEvent::PhxReply || message.topic != "phoenix" {
+            return false;
+        }
+
+        let Some((heartbeat_ref, sent_at)) = &self.pending_heartbeat else {
+            return false;
+        };
+
+        if message.message_ref.as_ref() != Some(heartbeat_ref) {
+            return false;
+        }
+
+        let rtt = sent_at.elapsed();
+        self.pending_heartbeat = None;
+
+        debug!("Heartbeat RTT {:?}", rtt);
+
+        if let Some(sender) = &self.latency_sample_event_sender {
+            sender.send(LatencySample {
+                client: self.name.clone(),
+                rtt,
+            });
+        }
+
+        true
     }
 
     fn read_socket(&mut self) -> Result<(), SocketError> {
@@ -850,7 +1008,11 @@ impl Client {
                     debug!("[RECV] {:?}", message);
 
                     if let Some(decode) = &self.decode {
-                        message = decode(message);
+                        let Some(decoded) = decode(message) else {
+                            return Ok(());
+                        };
+
+                        message = decoded;
                     }
 
                     if let Payload::Empty {} = message.payload {
@@ -882,6 +1044,54 @@ impl Client {
         }
     }
 
+    /// Moves sent messages into the outbound queue, applying the outbound [QueueBound]
+    fn queue_outbound(&mut self) {
+        while let Ok(message) = self.outbound_channel.0 .1.try_recv() {
+            self.outbound_dropped += self.outbound_queue.push(message);
+        }
+    }
+
+    /// Publishes the outbound queue depth, sending an [OutboundBackpressure] event when messages
+    /// were dropped or the queue fills up or drains
+    fn report_backpressure(&mut self) {
+        let depth = self.outbound_queue.len() + self.outbound_channel.0 .1.len();
+        self.outbound_depth.set(depth);
+
+        let Some(bound) = self.outbound_bound else {
+            return;
+        };
+
+        let saturated = if self.outbound_saturated {
+            depth > bound.capacity / 2
+        } else {
+            depth >= bound.capacity
+        };
+
+        if self.outbound_dropped == 0 && saturated == self.outbound_saturated {
+            return;
+        }
+
+        if self.outbound_dropped > 0 {
+            debug!(
+                "Outbound queue full, dropped {} messages",
+                self.outbound_dropped
+            );
+        }
+
+        if let Some(sender) = &self.outbound_backpressure_event_sender {
+            sender.send(OutboundBackpressure {
+                client: self.name.clone(),
+                depth,
+                capacity: bound.capacity,
+                dropped: self.outbound_dropped,
+                saturated,
+            });
+        }
+
+        self.outbound_saturated = saturated;
+        self.outbound_dropped = 0;
+    }
+
     fn write_socket(&mut self) -> Result<(), SocketError> {
         let Some(ref mut socket) = self.socket else {
             return Err(SocketError::NoSocket);
@@ -891,54 +1101,79 @@ impl Client {
             return Err(SocketError::NoWrite);
         }
 
-        // Throttling
-        let now = SystemTime::now();
+        // Drain as many queued messages as the rate limit allows, highest priority first
+        let mut sent = 0;
 
-        self.messages_this_second = self
-            .messages_this_second
-            .clone() // TODO do i need this clone? can i mutate in-place?
-            .into_iter()
-            .filter(|st| now.duration_since(*st).unwrap_or_default() < Duration::from_secs(1))
-            .collect();
+        while self.rate_limiter.ready(Instant::now()) {
+            let Some(mut outbound) = self.outbound_queue.pop() else {
+                break;
+            };
 
-        if self.messages_this_second.len() >= self.max_events_per_second {
-            return Err(SocketError::WouldBlock);
-        }
+            if outbound.message.message_ref.is_none() {
+                outbound.message.message_ref = Some(self.next_ref.into());
+                self.next_ref = Uuid::new_v4();
+            }
 
-        // Send to server
-        // TODO should drain outbound_channel
-        // TODO drain should respect throttling
-        let message = self.outbound_channel.0 .1.try_recv();
+            let mut message = outbound.message.clone();
 
-        // So should be a while try recv loop
+            if let Some(encode) = &self.encode {
+                message = encode(message);
+            }
 
-        match message {
-            Ok(mut message) => {
-                if message.message_ref.is_none() {
-                    message.message_ref = Some(self.next_ref.into());
-                    self.next_ref = Uuid::new_v4();
-                }
+            let raw = serde_json::to_string(&message);
+            debug!("[SEND] {:?}", raw);
 
-                if let Some(encode) = &self.encode {
-                    message = encode(message);
+            match socket.write(message.into()) {
+                Ok(()) => {}
+                // The message was buffered, only flushing it would block
+                Err(TungsteniteError::Io(e)) if e.kind() == io::ErrorKind::WouldBlock => {
+                    self.rate_limiter.try_take(Instant::now());
+                    sent += 1;
+                    break;
                 }
+                Err(TungsteniteError::Io(e)) => {
+                    debug!("Socket write error: {:?}", e);
+                    // The connection is gone, resend the message once reconnected
+                    self.outbound_queue.push_front(outbound);
+                    return Err(SocketError::Disconnected);
+                }
+                Err(e) => {
+                    debug!("Socket write error: {:?}", e);
+                    // Not written, keep it at the front of its lane for the next attempt
+                    self.outbound_queue.push_front(outbound);
+                    break;
+                }
+            }
+
+            self.rate_limiter.try_take(Instant::now());
+            sent += 1;
+        }
 
-                let raw = serde_json::to_string(&message);
-                debug!("[SEND] {:?}", raw);
+        if !self.outbound_queue.is_empty() {
+            let dropped = self.outbound_queue.drop_droppable();
 
-                let _ = socket.send(message.into());
-                self.messages_this_second.push(now);
-                Ok(())
+            if dropped > 0 {
+                debug!("Throttled, dropped {} droppable messages", dropped);
             }
-            Err(TryRecvError::Empty) => {
-                // do nothing
+        }
+
+        if sent == 0 {
+            return Ok(());
+        }
+
+        match socket.flush() {
+            Ok(()) => Ok(()),
+            Err(TungsteniteError::Io(err)) if err.kind() == io::ErrorKind::WouldBlock => {
+                // Remaining frames are flushed on the next write
                 Ok(())
             }
+            Err(TungsteniteError::Io(e)) => {
+                debug!("Socket flush error: {:?}", e);
+                Err(SocketError::Disconnected)
+            }
             Err(e) => {
-                debug!("outbound error: {:?}", e);
-                self.connection_state = ConnectionState::Reconnect;
-                let _ = self.monitor_channel.0 .0.send(MonitorSignal::Reconnect);
-                Err(SocketError::WouldBlock)
+                debug!("Socket flush error: {:?}", e);
+                Ok(())
             }
         }
     }
@@ -978,7 +1213,7 @@ pub struct ClientBuilder {
     params: Option<HashMap<String, String>>,
     heartbeat_interval: Duration,
     encode: Option<Box<dyn Fn(RealtimeMessage) -> RealtimeMessage + Send + Sync>>,
-    decode: Option<Box<dyn Fn(RealtimeMessage) -> RealtimeMessage + Send + Sync>>,
+    decode: Option<Box<dyn Fn(RealtimeMessage) -> Option<RealtimeMessage> + Send + Sync>>,
     reconnect_interval: ReconnectFn,
     reconnect_max_attempts: usize,
     connection_timeout: Duration,
@@ -986,6 +1221,14 @@ pub struct ClientBuilder {
     endpoint: String,
     access_token: String,
     max_events_per_second: usize,
+    max_events_burst: usize,
+    tls_config: Option<TlsConfig>,
+    proxy: ProxyConfig,
+    outbound_bound: Option<QueueBound>,
+    manager_bound: Option<QueueBound>,
+    outbound_backpressure_event_sender: Option<CrossbeamEventSender<OutboundBackpressure>>,
+    latency_sample_event_sender: Option<CrossbeamEventSender<LatencySample>>,
+    name: Option<String>,
 }
 
 impl ClientBuilder {
@@ -1006,6 +1249,14 @@ impl ClientBuilder {
             endpoint: endpoint.into(),
             access_token: access_token.into(),
             max_events_per_second: 10,
+            max_events_burst: 10,
+            tls_config: Default::default(),
+            proxy: Default::default(),
+            outbound_bound: Default::default(),
+            manager_bound: Default::default(),
+            outbound_backpressure_event_sender: Default::default(),
+            latency_sample_event_sender: Default::default(),
+            name: None,
         }
     }
 
@@ -1020,7 +1271,10 @@ impl ClientBuilder {
         self
     }
 
-    /// Merges provided [HeaderMap] with currently held headers
+    /// Merges provided [HeaderMap] with currently held headers.
+    ///
+    /// Headers are sent with the WebSocket upgrade request. An `Authorization` header set here
+    /// replaces the one derived from the access token.
     pub fn add_headers(&mut self, headers: HeaderMap) -> &mut Self {
         self.headers.extend(headers);
         self
@@ -1032,7 +1286,9 @@ impl ClientBuilder {
         self
     }
 
-    /// Set [Duration] between heartbeat packets. Default 29 seconds.
+    /// Set [Duration] between heartbeat packets. If a heartbeat hasn't been replied to by the time
+    /// the next is due the connection is considered dead and the client reconnects.
+    /// Default 29 seconds.
     pub fn heartbeat_interval(&mut self, heartbeat_interval: Duration) -> &mut Self {
         self.heartbeat_interval = heartbeat_interval;
         self
@@ -1082,13 +1338,81 @@ impl ClientBuilder {
         self
     }
 
-    /// Sets the max messages we can send in a second.
+    /// Sets the max messages we can send in a second, averaged over time.
     /// Default: 10
     pub fn max_events_per_second(&mut self, count: usize) -> &mut Self {
         self.max_events_per_second = count;
         self
     }
 
+    /// Sets how many messages can be sent at once after the client has been idle, on top of
+    /// the steady [ClientBuilder::max_events_per_second] rate.
+    /// Default: 10
+    /// Minimum: 1
+    pub fn max_events_burst(&mut self, count: usize) -> &mut Self {
+        self.max_events_burst = count;
+        self
+    }
+
+    /// Set the TLS configuration used for `wss://` endpoints, e.g. to trust a private CA or
+    /// present a client certificate.
+    /// Default: the enabled backend's default connector
+    #[cfg(any(feature = "native-tls", feature = "rustls"))]
+    pub fn tls_config(&mut self, tls_config: impl Into<TlsConfig>) -> &mut Self {
+        self.tls_config = Some(tls_config.into());
+        self
+    }
+
+    /// Tunnel the socket through an HTTP CONNECT or SOCKS5 proxy, see [ProxyConfig].
+    /// Default: [ProxyConfig::None]
+    pub fn proxy(&mut self, proxy: ProxyConfig) -> &mut Self {
+        self.proxy = proxy;
+        self
+    }
+
+    /// Bound the queue of messages waiting to be written to the socket. Heartbeats, joins and
+    /// leaves are exempt from the bound.
+    ///
+    /// [crate::outbound::OverflowPolicy::Reject] is applied when broadcasting through a
+    /// [crate::channel::ChannelManager], other policies are applied as messages are queued.
+    /// Default: unbounded
+    pub fn outbound_queue_bound(&mut self, bound: QueueBound) -> &mut Self {
+        self.outbound_bound = Some(bound);
+        self
+    }
+
+    /// Bound the queues of messages sent to the client and its channels through
+    /// [ClientManager] and [crate::channel::ChannelManager]. Only broadcasts count against the
+    /// bound, joins, leaves, presence updates and client messages are never discarded.
+    /// Default: unbounded
+    pub fn manager_queue_bound(&mut self, bound: QueueBound) -> &mut Self {
+        self.manager_bound = Some(bound);
+        self
+    }
+
+    /// Send [OutboundBackpressure] events through `sender` while the outbound queue is bounded
+    pub fn outbound_backpressure_events(
+        &mut self,
+        sender: CrossbeamEventSender<OutboundBackpressure>,
+    ) -> &mut Self {
+        self.outbound_backpressure_event_sender = Some(sender);
+        self
+    }
+
+    /// Tag the events sent by this client and its channels with `name`, to tell several clients
+    /// apart. See [crate::RealtimePlugin::named].
+    /// Default: None
+    pub fn name(&mut self, name: impl Into<String>) -> &mut Self {
+        self.name = Some(name.into());
+        self
+    }
+
+    /// Send a [LatencySample] through `sender` for every answered heartbeat
+    pub fn latency_events(&mut self, sender: CrossbeamEventSender<LatencySample>) -> &mut Self {
+        self.latency_sample_event_sender = Some(sender);
+        self
+    }
+
     pub fn encode(
         &mut self,
         encode: impl Fn(RealtimeMessage) -> RealtimeMessage + 'static + Send + Sync,
@@ -1101,7 +1425,18 @@ impl ClientBuilder {
         &mut self,
         decode: impl Fn(RealtimeMessage) -> RealtimeMessage + 'static + Send + Sync,
     ) -> &mut Self {
-        self.decode = Some(Box::new(decode));
+        self.decode = Some(Box::new(move |message| Some(decode(message))));
+        self
+    }
+
+    /// Delta compress broadcasts with `codec`, replacing [Self::encode] and [Self::decode].
+    /// The client gets its own copy of the codec's state, so one codec can configure several
+    /// clients.
+    pub fn delta_codec(&mut self, codec: DeltaCodec) -> &mut Self {
+        let codec = codec.unshared();
+        let decoder = codec.clone();
+        self.encode(move |message| codec.encode(message));
+        self.decode = Some(Box::new(move |message| decoder.decode(message)));
         self
     }
 
@@ -1111,7 +1446,7 @@ impl ClientBuilder {
         channel_callback_event_sender: CrossbeamEventSender<ChannelCallbackEvent>,
         connect_result_callback_event_sender: CrossbeamEventSender<ConnectResultCallbackEvent>,
     ) -> Client {
-        let (manager_tx, manager_rx) = unbounded();
+        let (manager_tx, manager_rx) = bounded_channel(self.manager_bound);
         Client {
             headers: self.headers,
             params: self.params,
@@ -1125,12 +1460,21 @@ impl ClientBuilder {
             endpoint: self.endpoint,
             access_token: self.access_token,
             max_events_per_second: self.max_events_per_second,
+            tls_config: self.tls_config,
+            proxy: self.proxy,
             next_ref: Uuid::new_v4(),
             connection_state: Default::default(),
             socket: Default::default(),
+            connected_addr: Default::default(),
             channels: Default::default(),
-            messages_this_second: Default::default(),
+            rate_limiter: TokenBucket::new(self.max_events_per_second, self.max_events_burst),
             outbound_channel: Default::default(),
+            outbound_queue: OutboundQueue::new(self.outbound_bound),
+            outbound_depth: Default::default(),
+            outbound_dropped: Default::default(),
+            outbound_saturated: Default::default(),
+            outbound_bound: self.outbound_bound,
+            manager_bound: self.manager_bound,
             inbound_channel: Default::default(),
             monitor_channel: Default::default(),
             middleware: Default::default(),
@@ -1138,10 +1482,208 @@ impl ClientBuilder {
             reconnect_delay: Default::default(),
             reconnect_attempts: Default::default(),
             heartbeat_now: Default::default(),
+            pending_heartbeat: Default::default(),
             manager_rx,
             manager_tx,
             channel_callback_event_sender,
             connect_result_callback_event_sender,
+            outbound_backpressure_event_sender: self.outbound_backpressure_event_sender,
+            latency_sample_event_sender: self.latency_sample_event_sender,
+            name: self.name,
+        }
+    }
+}
+
+/// Builds the websocket URL from the configured endpoint, preserving any base path and query.
+///
+/// `http(s)://` endpoints are mapped to `ws(s)://`, and the api key and params are
+/// percent-encoded into the query string.
+fn socket_uri(
+    endpoint: &str,
+    apikey: &str,
+    params: Option<&HashMap<String, String>>,
+) -> Result<Uri, ConnectError> {
+    let mut url = match Url::parse(endpoint) {
+        Ok(url) => url,
+        Err(e) => return Err(bad_uri(UriErrorKind::Parse(e), format!("{endpoint}: {e}"))),
+    };
+
+    let ws_scheme = match url.scheme() {
+        "http" | "ws" => "ws",
+        "https" | "wss" => "wss",
+        scheme => {
+            return Err(bad_uri(
+                UriErrorKind::UnsupportedScheme,
+                format!("unsupported scheme `{scheme}`, expected one of http, https, ws, wss"),
+            ))
+        }
+    };
+
+    // Both schemes are special, so this can't fail
+    let _ = url.set_scheme(ws_scheme);
+
+    if url.host_str().is_none() {
+        return Err(bad_uri(
+            UriErrorKind::MissingHost,
+            format!("{endpoint}: missing host"),
+        ));
+    }
+
+    let has_websocket_segment = url
+        .path_segments()
+        .and_then(|mut segments| segments.next_back())
+        .is_some_and(|last| last == "websocket");
+
+    if !has_websocket_segment {
+        match url.path_segments_mut() {
+            Ok(mut segments) => {
+                segments.pop_if_empty().push("websocket");
+            }
+            Err(()) => {
+                return Err(bad_uri(
+                    UriErrorKind::CannotBeABase,
+                    format!("{endpoint}: cannot be used as a base URL"),
+                ))
+            }
+        }
+    }
+
+    {
+        let mut query = url.query_pairs_mut();
+        query
+            .append_pair("apikey", apikey)
+            .append_pair("vsn", "1.0.0");
+
+        if let Some(params) = params {
+            for (field, value) in params {
+                query.append_pair(field, value);
+            }
+        }
+    }
+
+    match url.as_str().parse() {
+        Ok(uri) => Ok(uri),
+        Err(e) => Err(bad_uri(UriErrorKind::InvalidRequest, format!("{url}: {e}"))),
+    }
+}
+
+fn bad_uri(kind: UriErrorKind, detail: impl Display) -> ConnectError {
+    warn!("Bad socket URL: {}", detail);
+    ConnectError::BadUri(kind)
+}
+
+/// Adds the configured headers to the upgrade request's `headers`, skipping the ones in
+/// [WEBSOCKET_HANDSHAKE_HEADERS], then a bearer `Authorization` header unless one was configured.
+fn merge_handshake_headers(
+    headers: &mut HeaderMap,
+    configured: &HeaderMap,
+    access_token: &str,
+) -> Result<(), ConnectError> {
+    for (name, value) in configured {
+        if WEBSOCKET_HANDSHAKE_HEADERS.contains(&name.as_str()) {
+            debug!("Ignoring configured handshake header {:?}", name);
+            continue;
+        }
+
+        headers.append(name, value.clone());
+    }
+
+    // Configured headers take precedence, e.g. for gateways that expect their own auth
+    if !headers.contains_key("Authorization") {
+        let Ok(auth) = format!("Bearer {}", access_token).parse::<HeaderValue>() else {
+            return Err(ConnectError::BadAccessToken);
+        };
+        headers.insert("Authorization", auth);
+    }
+
+    Ok(())
+}
+
+/// Orders resolved addresses so that address families alternate, keeping the resolver's preferred
+/// family first (RFC 8305 section 4).
+fn interleave_addrs(addrs: Vec<SocketAddr>) -> Vec<SocketAddr> {
+    let Some(first) = addrs.first() else {
+        return addrs;
+    };
+
+    let prefer_v6 = first.is_ipv6();
+
+    let (preferred, other): (Vec<SocketAddr>, Vec<SocketAddr>) =
+        addrs.into_iter().partition(|a| a.is_ipv6() == prefer_v6);
+
+    let mut preferred = preferred.into_iter();
+    let mut other = other.into_iter();
+    let mut ordered = vec![];
+
+    loop {
+        match (preferred.next(), other.next()) {
+            (None, None) => break,
+            (a, b) => ordered.extend(a.into_iter().chain(b)),
+        }
+    }
+
+    ordered
+}
+
+/// Races TCP connections to the provided addresses, starting a new attempt every
+/// [CONNECTION_ATTEMPT_DELAY] or as soon as the previous one fails, until one succeeds or
+/// `timeout` elapses.
+///
+/// Returns the first established stream along with the address it is connected to.
+pub(crate) fn connect_happy_eyeballs(
+    addrs: &[SocketAddr],
+    timeout: Duration,
+) -> Result<(TcpStream, SocketAddr), io::Error> {
+    let deadline = Instant::now() + timeout;
+    let (tx, rx) = unbounded();
+
+    let mut next = 0;
+    let mut pending = 0;
+    let mut last_error = io::Error::new(io::ErrorKind::TimedOut, "connection timed out");
+
+    loop {
+        let now = Instant::now();
+
+        if now >= deadline {
+            return Err(last_error);
+        }
+
+        let remaining = deadline - now;
+
+        if let Some(addr) = addrs.get(next) {
+            let addr = *addr;
+            let tx = tx.clone();
+
+            debug!("Attempting connection to {}", addr);
+
+            std::thread::spawn(move || {
+                // Receiver may be gone if another attempt won the race, dropping the stream
+                let _ = tx.send((addr, TcpStream::connect_timeout(&addr, remaining)));
+            });
+
+            next += 1;
+            pending += 1;
+        }
+
+        let wait = if next < addrs.len() {
+            CONNECTION_ATTEMPT_DELAY.min(remaining)
+        } else {
+            remaining
+        };
+
+        match rx.recv_timeout(wait) {
+            Ok((addr, Ok(stream))) => return Ok((stream, addr)),
+            Ok((addr, Err(e))) => {
+                debug!("Connection to {} failed: {:?}", addr, e);
+                pending -= 1;
+                last_error = e;
+
+                if pending == 0 && next >= addrs.len() {
+                    return Err(last_error);
+                }
+            }
+            Err(RecvTimeoutError::Timeout) => {}
+            Err(RecvTimeoutError::Disconnected) => return Err(last_error),
         }
     }
 }
@@ -1151,3 +1693,278 @@ fn backoff(attempts: usize) -> Duration {
 
     Duration::from_secs(times[attempts.min(times.len() - 1)])
 }
+
+#[cfg(test)]
+mod tests {
+    use std::net::TcpListener;
+
+    use bevy_crossbeam_event::CrossbeamEventApp;
+
+    use super::*;
+    use crate::mock::MockServer;
+
+    fn addr(addr: &str) -> SocketAddr {
+        addr.parse().unwrap()
+    }
+
+    #[test]
+    fn interleave_addrs_alternates_families() {
+        let addrs = vec![
+            addr("[::1]:443"),
+            addr("[::2]:443"),
+            addr("[::3]:443"),
+            addr("10.0.0.1:443"),
+            addr("10.0.0.2:443"),
+        ];
+
+        assert_eq!(
+            interleave_addrs(addrs),
+            vec![
+                addr("[::1]:443"),
+                addr("10.0.0.1:443"),
+                addr("[::2]:443"),
+                addr("10.0.0.2:443"),
+                addr("[::3]:443"),
+            ]
+        );
+    }
+
+    #[test]
+    fn interleave_addrs_keeps_preferred_family_first() {
+        let addrs = vec![
+            addr("10.0.0.1:443"),
+            addr("10.0.0.2:443"),
+            addr("[::1]:443"),
+        ];
+
+        assert_eq!(
+            interleave_addrs(addrs),
+            vec![
+                addr("10.0.0.1:443"),
+                addr("[::1]:443"),
+                addr("10.0.0.2:443"),
+            ]
+        );
+    }
+
+    #[test]
+    fn socket_uri_maps_scheme_and_appends_websocket() {
+        let uri = socket_uri("https://abc.supabase.co/realtime/v1", "key", None).unwrap();
+        assert_eq!(
+            uri.to_string(),
+            "wss://abc.supabase.co/realtime/v1/websocket?apikey=key&vsn=1.0.0"
+        );
+
+        let uri = socket_uri("http://localhost:4000/socket/", "key", None).unwrap();
+        assert_eq!(
+            uri.to_string(),
+            "ws://localhost:4000/socket/websocket?apikey=key&vsn=1.0.0"
+        );
+    }
+
+    #[test]
+    fn socket_uri_keeps_existing_websocket_segment_and_query() {
+        let uri = socket_uri(
+            "wss://example.com/realtime/v1/websocket?region=eu",
+            "key",
+            None,
+        )
+        .unwrap();
+        assert_eq!(
+            uri.to_string(),
+            "wss://example.com/realtime/v1/websocket?region=eu&apikey=key&vsn=1.0.0"
+        );
+    }
+
+    #[test]
+    fn socket_uri_percent_encodes_query() {
+        let mut params = HashMap::new();
+        params.insert("log_level".to_string(), "info&debug".to_string());
+
+        let uri = socket_uri("https://example.com", "a b/c=", Some(&params)).unwrap();
+        assert_eq!(
+            uri.to_string(),
+            "wss://example.com/websocket?apikey=a+b%2Fc%3D&vsn=1.0.0&log_level=info%26debug"
+        );
+    }
+
+    #[test]
+    fn socket_uri_rejects_bad_endpoints() {
+        assert_eq!(
+            socket_uri("ftp://example.com", "key", None),
+            Err(ConnectError::BadUri(UriErrorKind::UnsupportedScheme))
+        );
+        assert_eq!(
+            socket_uri("not a url", "key", None),
+            Err(ConnectError::BadUri(UriErrorKind::Parse(
+                url::ParseError::RelativeUrlWithoutBase
+            )))
+        );
+        assert_eq!(
+            socket_uri("http://", "key", None),
+            Err(ConnectError::BadUri(UriErrorKind::Parse(
+                url::ParseError::EmptyHost
+            )))
+        );
+        assert_eq!(
+            socket_uri("mailto:someone@example.com", "key", None),
+            Err(ConnectError::BadUri(UriErrorKind::UnsupportedScheme))
+        );
+    }
+
+    #[test]
+    fn merge_handshake_headers_skips_handshake_headers() {
+        let mut headers = HeaderMap::new();
+        headers.insert("host", HeaderValue::from_static("realtime.example.com"));
+
+        let mut configured = HeaderMap::new();
+        configured.insert("Host", HeaderValue::from_static("evil.example.com"));
+        configured.insert("Upgrade", HeaderValue::from_static("h2c"));
+        configured.insert("X-Client-Info", HeaderValue::from_static("game/1.0"));
+
+        merge_handshake_headers(&mut headers, &configured, "token").unwrap();
+
+        assert_eq!(headers["host"], "realtime.example.com");
+        assert!(!headers.contains_key("upgrade"));
+        assert_eq!(headers["x-client-info"], "game/1.0");
+        assert_eq!(headers["authorization"], "Bearer token");
+    }
+
+    #[test]
+    fn merge_handshake_headers_keeps_configured_authorization() {
+        let mut headers = HeaderMap::new();
+
+        let mut configured = HeaderMap::new();
+        configured.insert("Authorization", HeaderValue::from_static("Custom abc"));
+
+        merge_handshake_headers(&mut headers, &configured, "token").unwrap();
+
+        assert_eq!(headers.get_all("authorization").iter().count(), 1);
+        assert_eq!(headers["authorization"], "Custom abc");
+    }
+
+    #[test]
+    fn merge_handshake_headers_rejects_bad_access_token() {
+        let mut headers = HeaderMap::new();
+
+        assert_eq!(
+            merge_handshake_headers(&mut headers, &HeaderMap::new(), "bad\ntoken"),
+            Err(ConnectError::BadAccessToken)
+        );
+    }
+
+    #[test]
+    fn connect_happy_eyeballs_falls_back_to_next_address() {
+        let closed = TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap();
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let listening = listener.local_addr().unwrap();
+
+        let (_stream, connected) =
+            connect_happy_eyeballs(&[closed, listening], Duration::from_secs(5)).unwrap();
+
+        assert_eq!(connected, listening);
+    }
+
+    #[test]
+    fn connect_records_connected_addr() {
+        let server = MockServer::start();
+
+        let mut app = App::new();
+        app.add_crossbeam_event::<ChannelCallbackEvent>()
+            .add_crossbeam_event::<ConnectResultCallbackEvent>();
+
+        let world = app.world();
+        let mut client = Client::builder(server.endpoint(), "key").build(
+            world.resource::<CrossbeamEventSender<_>>().clone(),
+            world.resource::<CrossbeamEventSender<_>>().clone(),
+        );
+
+        assert_eq!(client.connected_addr(), None);
+        client.connect().unwrap();
+        assert_eq!(client.connected_addr(), Some(server.addr()));
+    }
+
+    fn heartbeat_client(server: &MockServer, app: &mut App) -> Client {
+        app.add_crossbeam_event::<ChannelCallbackEvent>()
+            .add_crossbeam_event::<ConnectResultCallbackEvent>()
+            .add_crossbeam_event::<LatencySample>();
+
+        let world = app.world();
+        let mut builder = Client::builder(server.endpoint(), "key");
+        builder
+            .heartbeat_interval(Duration::from_millis(50))
+            .reconnect_interval(ReconnectFn::new(|_| Duration::from_millis(10)))
+            .latency_events(world.resource::<CrossbeamEventSender<_>>().clone());
+
+        let mut client = builder.build(
+            world.resource::<CrossbeamEventSender<_>>().clone(),
+            world.resource::<CrossbeamEventSender<_>>().clone(),
+        );
+        client.connect().unwrap();
+
+        client
+    }
+
+    fn step_until(client: &mut Client, app: &mut App, mut done: impl FnMut(&Client, &App) -> bool) {
+        let deadline = Instant::now() + Duration::from_secs(5);
+
+        while !done(client, app) {
+            assert!(Instant::now() < deadline, "timed out");
+            let _ = client.step();
+            app.update();
+        }
+    }
+
+    #[test]
+    fn answered_heartbeat_sends_latency_sample() {
+        let server = MockServer::start();
+        let mut app = App::new();
+        let mut client = heartbeat_client(&server, &mut app);
+
+        let mut heartbeat = None;
+        step_until(&mut client, &mut app, |_, _| {
+            heartbeat = server
+                .try_recv()
+                .filter(|message| message.event == MessageEvent::Heartbeat);
+            heartbeat.is_some()
+        });
+
+        server.send(RealtimeMessage {
+            event: MessageEvent::PhxReply,
+            topic: "phoenix".into(),
+            payload: Payload::Empty {},
+            message_ref: heartbeat.unwrap().message_ref,
+        });
+
+        step_until(&mut client, &mut app, |_, app| {
+            !app.world().resource::<Events<LatencySample>>().is_empty()
+        });
+
+        assert_eq!(client.get_status(), ConnectionState::Open);
+        assert_eq!(server.connections(), 1);
+    }
+
+    #[test]
+    fn unanswered_heartbeat_reconnects() {
+        let server = MockServer::start();
+        let mut app = App::new();
+        let mut client = heartbeat_client(&server, &mut app);
+
+        step_until(&mut client, &mut app, |client, _| {
+            server.connections() == 2 && client.get_status() == ConnectionState::Open
+        });
+
+        assert!(app.world().resource::<Events<LatencySample>>().is_empty());
+    }
+
+    #[test]
+    fn interleave_addrs_single_family_and_empty() {
+        let addrs = vec![addr("10.0.0.1:443"), addr("10.0.0.2:443")];
+
+        assert_eq!(interleave_addrs(addrs.clone()), addrs);
+        assert!(interleave_addrs(vec![]).is_empty());
+    }
+}
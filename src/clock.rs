@@ -0,0 +1,395 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    time::{Duration, Instant},
+};
+
+use bevy::{prelude::*, time::common_conditions::on_timer};
+use serde_json::Value;
+use uuid::Uuid;
+
+use crate::{
+    channel::ChannelBuilder,
+    client_ready,
+    message::payload::{BroadcastConfig, BroadcastPayload},
+    outbound::SendPolicy,
+    BevyChannelBuilder, BuildChannel, Channel, Client,
+};
+
+const PING_EVENT: &str = "clock_ping";
+const PONG_EVENT: &str = "clock_pong";
+
+/// Whether this peer's clock defines the shared timeline
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ClockRole {
+    /// Answers pings, shared time is this peer's local time
+    Reference,
+    /// Pings the reference and estimates the offset to its clock
+    Follower,
+}
+
+/// Synchronizes a [NetworkClock] with a reference peer over a dedicated broadcast channel.
+///
+/// Followers broadcast a ping every `ping_interval` which the reference answers with its own
+/// time. As with NTP, the offset is taken from the sample with the lowest round trip out of the
+/// most recent `samples`, since that is the least affected by queueing delay. Requires an
+/// unnamed [crate::RealtimePlugin].
+///
+/// Heartbeat replies carry no server timestamp, so the server's own clock can't be used as the
+/// reference; one peer, e.g. the host, has to take the [ClockRole::Reference] role.
+pub struct NetworkClockPlugin {
+    topic: String,
+    role: ClockRole,
+    ping_interval: Duration,
+    samples: usize,
+}
+
+impl NetworkClockPlugin {
+    pub fn new(topic: impl Into<String>, role: ClockRole) -> Self {
+        Self {
+            topic: topic.into(),
+            role,
+            ping_interval: Duration::from_secs(2),
+            samples: 8,
+        }
+    }
+
+    /// Set the time between pings.
+    /// Default: 2 seconds
+    pub fn ping_interval(mut self, ping_interval: Duration) -> Self {
+        self.ping_interval = ping_interval;
+        self
+    }
+
+    /// Set how many recent samples the offset is picked from.
+    /// Default: 8
+    /// Minimum: 1
+    pub fn samples(mut self, samples: usize) -> Self {
+        self.samples = samples.max(1);
+        self
+    }
+}
+
+impl Plugin for NetworkClockPlugin {
+    fn build(&self, app: &mut App) {
+        let startup = app
+            .world()
+            .get_resource::<Time<Real>>()
+            .map(|time| time.startup())
+            .unwrap_or_else(Instant::now);
+
+        app.insert_resource(NetworkClock {
+            peer_id: Uuid::new_v4(),
+            role: self.role,
+            startup,
+            offset: None,
+            rtt: None,
+            samples: VecDeque::with_capacity(self.samples),
+            max_samples: self.samples,
+        })
+        .insert_resource(ClockTopic(self.topic.clone()))
+        .add_systems(Startup, setup_clock_channel);
+
+        if self.role == ClockRole::Follower {
+            app.add_systems(
+                Update,
+                send_clock_ping
+                    .run_if(client_ready)
+                    .run_if(on_timer(self.ping_interval)),
+            );
+        }
+    }
+}
+
+/// Time shared between all peers synchronized by [NetworkClockPlugin], in seconds.
+///
+/// Local time is measured from the app's [Time<Real>] startup, so shared time is the
+/// reference peer's real time since it started.
+#[derive(Resource, Debug, Clone)]
+pub struct NetworkClock {
+    peer_id: Uuid,
+    role: ClockRole,
+    startup: Instant,
+    offset: Option<f64>,
+    rtt: Option<Duration>,
+    samples: VecDeque<(Duration, f64)>,
+    max_samples: usize,
+}
+
+impl NetworkClock {
+    /// Current shared time. Until the first sample arrives this is local time.
+    pub fn now(&self) -> f64 {
+        self.to_shared(self.local_now())
+    }
+
+    /// Current local time
+    pub fn local_now(&self) -> f64 {
+        self.startup.elapsed().as_secs_f64()
+    }
+
+    /// Converts a local timestamp to shared time
+    pub fn to_shared(&self, local: f64) -> f64 {
+        local + self.offset.unwrap_or_default()
+    }
+
+    /// Converts a shared timestamp to local time
+    pub fn to_local(&self, shared: f64) -> f64 {
+        shared - self.offset.unwrap_or_default()
+    }
+
+    /// Estimated offset of the reference clock from the local clock, in seconds
+    pub fn offset(&self) -> Option<f64> {
+        match self.role {
+            ClockRole::Reference => Some(0.0),
+            ClockRole::Follower => self.offset,
+        }
+    }
+
+    /// Round trip time of the sample the offset was taken from
+    pub fn rtt(&self) -> Option<Duration> {
+        self.rtt
+    }
+
+    /// True once shared time is known
+    pub fn is_synced(&self) -> bool {
+        self.offset().is_some()
+    }
+
+    pub fn role(&self) -> ClockRole {
+        self.role
+    }
+
+    /// Id used to address pongs to this peer
+    pub fn peer_id(&self) -> Uuid {
+        self.peer_id
+    }
+
+    fn add_sample(&mut self, rtt: Duration, offset: f64) {
+        if self.samples.len() == self.max_samples {
+            self.samples.pop_front();
+        }
+
+        self.samples.push_back((rtt, offset));
+
+        if let Some((rtt, offset)) = self.samples.iter().min_by_key(|(rtt, _)| *rtt) {
+            self.rtt = Some(*rtt);
+            self.offset = Some(*offset);
+        }
+    }
+}
+
+#[derive(Resource, Deref)]
+struct ClockTopic(String);
+
+/// Marks the channel used by [NetworkClockPlugin]
+#[derive(Component)]
+pub struct ClockChannel;
+
+fn setup_clock_channel(world: &mut World) {
+    let build_channel = world.register_system(build_clock_channel);
+    let client = world.resource::<Client>();
+
+    if client.channel(build_channel).is_err() {
+        error!("Failed to create clock channel");
+    }
+}
+
+fn build_clock_channel(
+    mut channel_builder: In<ChannelBuilder>,
+    mut commands: Commands,
+    topic: Res<ClockTopic>,
+    clock: Res<NetworkClock>,
+) {
+    channel_builder
+        .topic(topic.as_str())
+        .set_broadcast_config(BroadcastConfig {
+            broadcast_self: false,
+            ack: false,
+        })
+        .broadcast_policy(PING_EVENT, SendPolicy::Droppable)
+        .broadcast_policy(PONG_EVENT, SendPolicy::Droppable);
+
+    match clock.role {
+        ClockRole::Reference => {
+            let on_ping = commands.register_system(on_clock_ping);
+            channel_builder.on_broadcast(PING_EVENT, on_ping);
+        }
+        ClockRole::Follower => {
+            let on_pong = commands.register_system(on_clock_pong);
+            channel_builder.on_broadcast(PONG_EVENT, on_pong);
+        }
+    }
+
+    commands.spawn((
+        BevyChannelBuilder(channel_builder.0),
+        BuildChannel,
+        ClockChannel,
+    ));
+}
+
+fn send_clock_ping(clock: Res<NetworkClock>, q: Query<&Channel, With<ClockChannel>>) {
+    let mut payload = HashMap::new();
+    payload.insert("from".into(), clock.peer_id.to_string().into());
+    payload.insert("t0".into(), clock.local_now().into());
+
+    for channel in q.iter() {
+        let _ = channel.broadcast(BroadcastPayload {
+            event: PING_EVENT.into(),
+            payload: payload.clone(),
+            ..Default::default()
+        });
+    }
+}
+
+fn on_clock_ping(
+    In(ping): In<HashMap<String, Value>>,
+    clock: Res<NetworkClock>,
+    q: Query<&Channel, With<ClockChannel>>,
+) {
+    let (Some(from), Some(t0)) = (ping.get("from"), ping.get("t0")) else {
+        return;
+    };
+
+    let mut payload = HashMap::new();
+    payload.insert("to".into(), from.clone());
+    payload.insert("t0".into(), t0.clone());
+    payload.insert("t1".into(), clock.local_now().into());
+
+    for channel in q.iter() {
+        let _ = channel.broadcast(BroadcastPayload {
+            event: PONG_EVENT.into(),
+            payload: payload.clone(),
+            ..Default::default()
+        });
+    }
+}
+
+fn on_clock_pong(In(pong): In<HashMap<String, Value>>, mut clock: ResMut<NetworkClock>) {
+    let t3 = clock.local_now();
+
+    if let Some((rtt, offset)) = pong_sample(&pong, clock.peer_id, t3) {
+        clock.add_sample(rtt, offset);
+    }
+}
+
+/// Reads the round trip and offset from a pong addressed to `peer_id`, received at local time
+/// `t3`
+fn pong_sample(pong: &HashMap<String, Value>, peer_id: Uuid, t3: f64) -> Option<(Duration, f64)> {
+    if pong.get("to").and_then(Value::as_str) != Some(peer_id.to_string().as_str()) {
+        return None;
+    }
+
+    let t0 = pong.get("t0").and_then(Value::as_f64)?;
+    let t1 = pong.get("t1").and_then(Value::as_f64)?;
+
+    if t3 < t0 {
+        return None;
+    }
+
+    // Assume the reference replied halfway through the round trip
+    let rtt = Duration::from_secs_f64(t3 - t0);
+    let offset = t1 - (t0 + t3) / 2.0;
+
+    Some((rtt, offset))
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::ecs::system::RunSystemOnce;
+    use serde_json::json;
+
+    use super::*;
+
+    fn follower(max_samples: usize) -> NetworkClock {
+        NetworkClock {
+            peer_id: Uuid::new_v4(),
+            role: ClockRole::Follower,
+            startup: Instant::now(),
+            offset: None,
+            rtt: None,
+            samples: VecDeque::with_capacity(max_samples),
+            max_samples,
+        }
+    }
+
+    fn pong(to: Uuid, t0: f64, t1: f64) -> HashMap<String, Value> {
+        HashMap::from([
+            ("to".to_string(), json!(to.to_string())),
+            ("t0".to_string(), json!(t0)),
+            ("t1".to_string(), json!(t1)),
+        ])
+    }
+
+    fn ms(ms: u64) -> Duration {
+        Duration::from_millis(ms)
+    }
+
+    #[test]
+    fn add_sample_uses_lowest_rtt_in_window() {
+        let mut clock = follower(2);
+
+        clock.add_sample(ms(30), 1.0);
+        clock.add_sample(ms(10), 2.0);
+        assert_eq!(clock.offset(), Some(2.0));
+        assert_eq!(clock.rtt(), Some(ms(10)));
+
+        // Evicts the 30ms sample, the 10ms one is still the best
+        clock.add_sample(ms(20), 3.0);
+        assert_eq!(clock.offset(), Some(2.0));
+
+        // Evicts the 10ms sample
+        clock.add_sample(ms(40), 4.0);
+        assert_eq!(clock.offset(), Some(3.0));
+        assert_eq!(clock.rtt(), Some(ms(20)));
+    }
+
+    #[test]
+    fn pong_sample_assumes_reply_halfway() {
+        let peer_id = Uuid::new_v4();
+
+        let (rtt, offset) = pong_sample(&pong(peer_id, 1.0, 5.5), peer_id, 2.0).unwrap();
+
+        assert_eq!(rtt, Duration::from_secs(1));
+        assert_eq!(offset, 4.0);
+    }
+
+    #[test]
+    fn pong_sample_ignores_other_peers_and_bad_timestamps() {
+        let peer_id = Uuid::new_v4();
+
+        assert_eq!(
+            pong_sample(&pong(Uuid::new_v4(), 1.0, 5.5), peer_id, 2.0),
+            None
+        );
+        // Received before it was sent
+        assert_eq!(pong_sample(&pong(peer_id, 3.0, 5.5), peer_id, 2.0), None);
+
+        let mut missing_t1 = pong(peer_id, 1.0, 5.5);
+        missing_t1.remove("t1");
+        assert_eq!(pong_sample(&missing_t1, peer_id, 2.0), None);
+    }
+
+    #[test]
+    fn on_clock_pong_syncs_follower() {
+        let mut world = World::new();
+        world.insert_resource(follower(8));
+
+        let peer_id = world.resource::<NetworkClock>().peer_id();
+
+        world
+            .run_system_once_with(pong(Uuid::new_v4(), 0.0, 100.0), on_clock_pong)
+            .unwrap();
+        assert!(!world.resource::<NetworkClock>().is_synced());
+
+        // Reference is 100 seconds ahead
+        let t0 = world.resource::<NetworkClock>().local_now();
+        world
+            .run_system_once_with(pong(peer_id, t0, t0 + 100.0), on_clock_pong)
+            .unwrap();
+
+        let clock = world.resource::<NetworkClock>();
+        let offset = clock.offset().unwrap();
+
+        assert!((offset - 100.0).abs() < 0.1, "offset {offset}");
+        assert!(clock.rtt().is_some());
+    }
+}
@@ -0,0 +1,459 @@
+use std::{
+    collections::{HashMap, HashSet},
+    sync::{Arc, Mutex},
+};
+
+use bevy::log::debug;
+use serde_json::{Map, Number, Value};
+use uuid::Uuid;
+
+use crate::message::{
+    payload::{BroadcastPayload, Payload},
+    realtime_message::{MessageEvent, RealtimeMessage},
+};
+
+/// Reserved payload key carrying codec metadata
+const DELTA_KEY: &str = "_delta";
+
+/// Opt-in delta compression for high frequency broadcast events.
+///
+/// Every `keyframe_interval`th message of an event is sent in full, the rest only carry the
+/// top level fields that differ from the last keyframe, plus the fields that were removed
+/// since. Floats are rounded to `decimals` places before comparison, so jitter below that
+/// precision isn't resent. Receivers apply deltas to the last keyframe seen from each sender
+/// and get the full payload, so callbacks are unaware of the codec, and a lost delta doesn't
+/// corrupt the ones after it. Deltas based on a keyframe the receiver hasn't seen, e.g. after
+/// joining late or losing the keyframe, are dropped until the next keyframe arrives.
+///
+/// Encoding happens as messages are written to the socket, after any outbound queue drops.
+/// Install on both sides with [crate::client::ClientBuilder::delta_codec] or
+/// [crate::RealtimePlugin::delta_codec].
+#[derive(Clone)]
+pub struct DeltaCodec {
+    sender_id: String,
+    events: HashSet<String>,
+    decimals: Option<u32>,
+    keyframe_interval: u32,
+    state: Arc<Mutex<CodecState>>,
+}
+
+#[derive(Default)]
+struct CodecState {
+    sent: HashMap<(String, String), SentState>,
+    received: HashMap<(String, String, String), Keyframe>,
+}
+
+struct SentState {
+    keyframe: Keyframe,
+    seq: u32,
+}
+
+struct Keyframe {
+    fields: Map<String, Value>,
+    seq: u32,
+}
+
+impl Default for DeltaCodec {
+    fn default() -> Self {
+        Self {
+            sender_id: Uuid::new_v4().to_string(),
+            events: HashSet::new(),
+            decimals: Some(3),
+            keyframe_interval: 30,
+            state: Default::default(),
+        }
+    }
+}
+
+impl DeltaCodec {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Compress broadcasts with this event name, on any channel
+    pub fn event(mut self, event: impl Into<String>) -> Self {
+        self.events.insert(event.into());
+        self
+    }
+
+    /// Set the number of decimal places floats are rounded to, or None to send them exactly.
+    /// Default: 3
+    pub fn decimals(mut self, decimals: Option<u32>) -> Self {
+        self.decimals = decimals;
+        self
+    }
+
+    /// Set how many messages apart full keyframes are sent.
+    /// Default: 30
+    /// Minimum: 1
+    pub fn keyframe_interval(mut self, keyframe_interval: u32) -> Self {
+        self.keyframe_interval = keyframe_interval.max(1);
+        self
+    }
+
+    /// Copy of the configuration with a new sender id and no state, for another client
+    pub(crate) fn unshared(&self) -> Self {
+        Self {
+            sender_id: Uuid::new_v4().to_string(),
+            events: self.events.clone(),
+            decimals: self.decimals,
+            keyframe_interval: self.keyframe_interval,
+            state: Default::default(),
+        }
+    }
+
+    pub fn encode(&self, mut message: RealtimeMessage) -> RealtimeMessage {
+        let topic = message.topic.clone();
+
+        let Some(broadcast) = self.broadcast_mut(&mut message) else {
+            return message;
+        };
+
+        let mut fields: Map<String, Value> =
+            std::mem::take(&mut broadcast.payload).into_iter().collect();
+
+        if let Some(decimals) = self.decimals {
+            for value in fields.values_mut() {
+                quantize(value, decimals);
+            }
+        }
+
+        let key = (topic, broadcast.event.clone());
+        let mut state = self.state.lock().unwrap();
+
+        let (seq, mut payload, mut meta) = match state.sent.get_mut(&key) {
+            Some(sent) if (sent.seq + 1) % self.keyframe_interval != 0 => {
+                sent.seq += 1;
+
+                let keyframe = &sent.keyframe;
+                let mut changed = Map::new();
+
+                for (field, value) in fields.iter() {
+                    if keyframe.fields.get(field) != Some(value) {
+                        changed.insert(field.clone(), value.clone());
+                    }
+                }
+
+                let removed: Vec<Value> = keyframe
+                    .fields
+                    .keys()
+                    .filter(|field| !fields.contains_key(*field))
+                    .map(|field| Value::String(field.clone()))
+                    .collect();
+
+                let mut meta = Map::new();
+                meta.insert("base".into(), keyframe.seq.into());
+                meta.insert("removed".into(), removed.into());
+
+                (sent.seq, changed, meta)
+            }
+            sent => {
+                let seq = sent.map(|sent| sent.seq + 1).unwrap_or_default();
+                let mut meta = Map::new();
+                meta.insert("keyframe".into(), true.into());
+
+                state.sent.insert(
+                    key,
+                    SentState {
+                        keyframe: Keyframe {
+                            fields: fields.clone(),
+                            seq,
+                        },
+                        seq,
+                    },
+                );
+
+                (seq, fields, meta)
+            }
+        };
+
+        meta.insert("from".into(), self.sender_id.clone().into());
+        meta.insert("seq".into(), seq.into());
+        payload.insert(DELTA_KEY.into(), meta.into());
+
+        broadcast.payload = payload.into_iter().collect();
+        message
+    }
+
+    /// Applies a delta to its keyframe. Returns [None] if the keyframe is unknown, since only
+    /// the changed fields could be passed on.
+    pub fn decode(&self, mut message: RealtimeMessage) -> Option<RealtimeMessage> {
+        let topic = message.topic.clone();
+
+        let Some(broadcast) = self.broadcast_mut(&mut message) else {
+            return Some(message);
+        };
+
+        let Some(Value::Object(meta)) = broadcast.payload.remove(DELTA_KEY) else {
+            return Some(message);
+        };
+
+        let Some(from) = meta.get("from").and_then(Value::as_str) else {
+            return Some(message);
+        };
+
+        let key = (topic, broadcast.event.clone(), from.to_string());
+        let seq = meta.get("seq").and_then(Value::as_u64).unwrap_or_default() as u32;
+        let mut state = self.state.lock().unwrap();
+
+        if meta.get("keyframe").and_then(Value::as_bool) == Some(true) {
+            state.received.insert(
+                key,
+                Keyframe {
+                    fields: broadcast.payload.clone().into_iter().collect(),
+                    seq,
+                },
+            );
+
+            return Some(message);
+        }
+
+        let base = meta
+            .get("base")
+            .and_then(Value::as_u64)
+            .map(|base| base as u32);
+
+        // Without the keyframe the delta is based on, only the sent fields are known
+        let Some(keyframe) = state
+            .received
+            .get(&key)
+            .filter(|keyframe| Some(keyframe.seq) == base)
+        else {
+            debug!("Dropping {} delta without its keyframe", broadcast.event);
+            return None;
+        };
+
+        let mut fields = keyframe.fields.clone();
+
+        if let Some(Value::Array(removed)) = meta.get("removed") {
+            for field in removed.iter().filter_map(Value::as_str) {
+                fields.remove(field);
+            }
+        }
+
+        fields.extend(std::mem::take(&mut broadcast.payload));
+        broadcast.payload = fields.into_iter().collect();
+
+        Some(message)
+    }
+
+    fn broadcast_mut<'a>(
+        &self,
+        message: &'a mut RealtimeMessage,
+    ) -> Option<&'a mut BroadcastPayload> {
+        if message.event != MessageEvent::Broadcast {
+            return None;
+        }
+
+        match &mut message.payload {
+            Payload::Broadcast(broadcast) if self.events.contains(&broadcast.event) => {
+                Some(broadcast)
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Rounds every float in `value` to `decimals` places
+fn quantize(value: &mut Value, decimals: u32) {
+    match value {
+        Value::Number(number) if number.is_f64() => {
+            let scale = 10f64.powi(decimals as i32);
+            let rounded = (number.as_f64().unwrap_or_default() * scale).round() / scale;
+
+            if let Some(rounded) = Number::from_f64(rounded) {
+                *number = rounded;
+            }
+        }
+        Value::Array(values) => values
+            .iter_mut()
+            .for_each(|value| quantize(value, decimals)),
+        Value::Object(fields) => fields
+            .values_mut()
+            .for_each(|value| quantize(value, decimals)),
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn codec() -> DeltaCodec {
+        DeltaCodec::new().event("state").keyframe_interval(4)
+    }
+
+    fn broadcast(event: &str, payload: Value) -> RealtimeMessage {
+        let Value::Object(payload) = payload else {
+            unreachable!();
+        };
+
+        RealtimeMessage {
+            event: MessageEvent::Broadcast,
+            topic: "realtime:game".into(),
+            payload: Payload::Broadcast(BroadcastPayload {
+                event: event.into(),
+                payload: payload.into_iter().collect(),
+                ..Default::default()
+            }),
+            message_ref: None,
+        }
+    }
+
+    fn payload(message: &RealtimeMessage) -> Value {
+        let Payload::Broadcast(broadcast) = &message.payload else {
+            unreachable!();
+        };
+
+        let mut fields: Map<String, Value> = broadcast.payload.clone().into_iter().collect();
+        fields.remove(DELTA_KEY);
+        fields.into()
+    }
+
+    fn meta(message: &RealtimeMessage) -> Value {
+        let Payload::Broadcast(broadcast) = &message.payload else {
+            unreachable!();
+        };
+
+        broadcast
+            .payload
+            .get(DELTA_KEY)
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    #[test]
+    fn deltas_only_carry_changes_and_decode_in_full() {
+        let sender = codec();
+        let receiver = codec();
+
+        let states = [
+            json!({ "x": 1.0, "y": 2.0, "name": "a" }),
+            json!({ "x": 1.5, "y": 2.0, "name": "a" }),
+            json!({ "x": 1.5, "y": 3.0 }),
+        ];
+
+        let encoded: Vec<_> = states
+            .iter()
+            .map(|state| sender.encode(broadcast("state", state.clone())))
+            .collect();
+
+        assert_eq!(meta(&encoded[0])["keyframe"], json!(true));
+        assert_eq!(payload(&encoded[1]), json!({ "x": 1.5 }));
+        assert_eq!(payload(&encoded[2]), json!({ "x": 1.5, "y": 3.0 }));
+        assert_eq!(meta(&encoded[2])["removed"], json!(["name"]));
+
+        for (message, state) in encoded.into_iter().zip(states) {
+            assert_eq!(payload(&receiver.decode(message).unwrap()), state);
+        }
+    }
+
+    #[test]
+    fn lost_deltas_do_not_corrupt_later_ones() {
+        let sender = codec();
+        let receiver = codec();
+
+        let keyframe = sender.encode(broadcast("state", json!({ "x": 0.0, "y": 0.0 })));
+        let _lost = sender.encode(broadcast("state", json!({ "x": 1.0, "y": 0.0 })));
+        let delta = sender.encode(broadcast("state", json!({ "x": 1.0, "y": 5.0 })));
+
+        receiver.decode(keyframe);
+
+        assert_eq!(
+            payload(&receiver.decode(delta).unwrap()),
+            json!({ "x": 1.0, "y": 5.0 })
+        );
+    }
+
+    #[test]
+    fn deltas_without_their_keyframe_are_dropped_until_the_next_one() {
+        let sender = codec();
+        let receiver = codec();
+
+        sender.encode(broadcast("state", json!({ "x": 0.0, "y": 0.0 })));
+        let delta = sender.encode(broadcast("state", json!({ "x": 1.0, "y": 0.0 })));
+
+        assert!(receiver.decode(delta).is_none());
+
+        sender.encode(broadcast("state", json!({ "x": 2.0, "y": 0.0 })));
+        sender.encode(broadcast("state", json!({ "x": 2.5, "y": 0.0 })));
+        let keyframe = sender.encode(broadcast("state", json!({ "x": 3.0, "y": 0.0 })));
+        let delta = sender.encode(broadcast("state", json!({ "x": 3.5, "y": 0.0 })));
+
+        assert_eq!(meta(&keyframe)["keyframe"], json!(true));
+        assert_eq!(
+            payload(&receiver.decode(keyframe).unwrap()),
+            json!({ "x": 3.0, "y": 0.0 })
+        );
+        assert_eq!(
+            payload(&receiver.decode(delta).unwrap()),
+            json!({ "x": 3.5, "y": 0.0 })
+        );
+    }
+
+    #[test]
+    fn deltas_based_on_a_stale_keyframe_are_dropped() {
+        let sender = codec().keyframe_interval(2);
+        let receiver = codec();
+
+        let first = sender.encode(broadcast("state", json!({ "x": 0.0 })));
+        let _first_delta = sender.encode(broadcast("state", json!({ "x": 1.0 })));
+        let _lost_keyframe = sender.encode(broadcast("state", json!({ "x": 2.0 })));
+        let delta = sender.encode(broadcast("state", json!({ "x": 3.0 })));
+
+        receiver.decode(first).unwrap();
+
+        assert_eq!(meta(&delta)["base"], json!(2));
+        assert!(receiver.decode(delta).is_none());
+    }
+
+    #[test]
+    fn senders_are_decoded_separately() {
+        let a = codec();
+        let b = codec();
+        let receiver = codec();
+
+        receiver.decode(a.encode(broadcast("state", json!({ "x": 0.0 }))));
+        receiver.decode(b.encode(broadcast("state", json!({ "x": 10.0, "y": 1.0 }))));
+
+        let from_a = a.encode(broadcast("state", json!({ "x": 1.0 })));
+
+        assert_eq!(
+            payload(&receiver.decode(from_a).unwrap()),
+            json!({ "x": 1.0 })
+        );
+    }
+
+    #[test]
+    fn jitter_below_precision_is_not_resent() {
+        let sender = codec().decimals(Some(2));
+
+        sender.encode(broadcast("state", json!({ "x": 1.0 })));
+        let delta = sender.encode(broadcast("state", json!({ "x": 1.001 })));
+
+        assert_eq!(payload(&delta), json!({}));
+    }
+
+    #[test]
+    fn other_events_pass_through() {
+        let sender = codec();
+        let message = sender.encode(broadcast("chat", json!({ "x": 1.0 })));
+
+        assert_eq!(payload(&message), json!({ "x": 1.0 }));
+        assert_eq!(meta(&message), Value::Null);
+    }
+
+    #[test]
+    fn unshared_copies_encode_separately() {
+        let codec = codec();
+        let copy = codec.unshared();
+
+        codec.encode(broadcast("state", json!({ "x": 0.0 })));
+        let first = copy.encode(broadcast("state", json!({ "x": 0.0 })));
+
+        assert_eq!(meta(&first)["keyframe"], json!(true));
+        assert_ne!(meta(&first)["from"], json!(codec.sender_id));
+    }
+}
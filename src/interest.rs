@@ -0,0 +1,477 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+
+use bevy::{ecs::system::SystemId, prelude::*};
+use serde_json::Value;
+
+use crate::{
+    channel::ChannelBuilder,
+    client_ready,
+    message::payload::{BroadcastConfig, PresenceConfig},
+    presence::{PrescenceTrack, PresenceEvent, PresenceState},
+    replication::{
+        on_owner_join, on_replicate, NetworkEntities, NetworkId, RemoteOwner, ReplicationChannel,
+        ReplicationOwner, REPLICATE_EVENT,
+    },
+    BevyChannelBuilder, BuildChannel, Channel, Client,
+};
+
+/// Plane of the world the interest grid is laid out on
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum InterestPlane {
+    /// 2D games
+    #[default]
+    XY,
+    /// 3D games with Y up
+    XZ,
+}
+
+/// Maps world positions to grid cells
+#[derive(Resource, Debug, Clone, Copy)]
+pub struct InterestGrid {
+    cell_size: f32,
+    plane: InterestPlane,
+}
+
+impl InterestGrid {
+    pub fn new(cell_size: f32, plane: InterestPlane) -> Self {
+        Self { cell_size, plane }
+    }
+
+    pub fn cell_of(&self, position: Vec3) -> IVec2 {
+        let position = match self.plane {
+            InterestPlane::XY => position.xy(),
+            InterestPlane::XZ => position.xz(),
+        };
+
+        (position / self.cell_size).floor().as_ivec2()
+    }
+
+    pub fn cell_size(&self) -> f32 {
+        self.cell_size
+    }
+}
+
+/// Area of interest management with a channel per grid cell.
+///
+/// The channels of all cells within `radius` of the [InterestFocus] entity's cell are joined,
+/// and left once the focus is more than `leave_radius` cells away, so moving back and forth
+/// over a cell border doesn't rejoin. Distances are counted in cells along either axis.
+///
+/// With [crate::replication::ReplicationPlugin] the cell channels also carry replication:
+/// entities are only sent to the channel of the cell they're in, and mirrors outside of the
+/// joined cells are despawned. Requires an unnamed [crate::RealtimePlugin].
+pub struct InterestPlugin {
+    topic_prefix: String,
+    grid: InterestGrid,
+    radius: u32,
+    leave_radius: Option<u32>,
+}
+
+impl InterestPlugin {
+    pub fn new(topic_prefix: impl Into<String>) -> Self {
+        Self {
+            topic_prefix: topic_prefix.into(),
+            grid: InterestGrid::new(100.0, InterestPlane::XY),
+            radius: 1,
+            leave_radius: None,
+        }
+    }
+
+    /// Set the side length of a cell in world units.
+    /// Default: 100
+    pub fn cell_size(mut self, cell_size: f32) -> Self {
+        self.grid.cell_size = cell_size;
+        self
+    }
+
+    /// Default: [InterestPlane::XY]
+    pub fn plane(mut self, plane: InterestPlane) -> Self {
+        self.grid.plane = plane;
+        self
+    }
+
+    /// Set how many cells around the focus are joined.
+    /// Default: 1
+    pub fn radius(mut self, radius: u32) -> Self {
+        self.radius = radius;
+        self
+    }
+
+    /// Set how many cells away from the focus a joined cell is left.
+    /// Default: radius + 1
+    /// Minimum: radius
+    pub fn leave_radius(mut self, leave_radius: u32) -> Self {
+        self.leave_radius = Some(leave_radius);
+        self
+    }
+}
+
+impl Plugin for InterestPlugin {
+    fn build(&self, app: &mut App) {
+        let leave_radius = self
+            .leave_radius
+            .unwrap_or(self.radius + 1)
+            .max(self.radius);
+
+        app.insert_resource(self.grid)
+            .insert_resource(InterestConfig {
+                topic_prefix: self.topic_prefix.clone(),
+                radius: self.radius as i32,
+                leave_radius: leave_radius as i32,
+            })
+            .init_resource::<InterestCells>()
+            .add_systems(Startup, setup_interest)
+            .add_systems(
+                Update,
+                (
+                    update_interest.run_if(client_ready),
+                    despawn_out_of_interest,
+                ),
+            );
+    }
+}
+
+/// Marks the entity whose position decides which cells are joined, e.g. the local player
+#[derive(Component, Debug, Default)]
+pub struct InterestFocus;
+
+/// Cell of the channel on this entity
+#[derive(Component, Debug, Clone, Copy, PartialEq, Eq, Hash, Deref)]
+pub struct InterestCell(pub IVec2);
+
+/// Cells whose channels a mirror's updates arrived on, to cull mirrors without a [Transform]
+#[derive(Component, Debug, Default)]
+pub(crate) struct MirrorCells(pub(crate) HashSet<IVec2>);
+
+/// Cells currently joined, or being joined
+#[derive(Resource, Debug, Default)]
+pub struct InterestCells {
+    joined: HashMap<IVec2, Option<Entity>>,
+    pending: VecDeque<IVec2>,
+}
+
+impl InterestCells {
+    pub fn contains(&self, cell: IVec2) -> bool {
+        self.joined.contains_key(&cell)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = IVec2> + '_ {
+        self.joined.keys().copied()
+    }
+
+    /// Entity of the channel for `cell`, once it has been built
+    pub fn channel(&self, cell: IVec2) -> Option<Entity> {
+        self.joined.get(&cell).copied().flatten()
+    }
+}
+
+#[derive(Resource)]
+struct InterestConfig {
+    topic_prefix: String,
+    radius: i32,
+    leave_radius: i32,
+}
+
+#[derive(Resource)]
+struct InterestSystems {
+    build_cell: SystemId<In<ChannelBuilder>>,
+    on_replicate: SystemId<In<HashMap<String, Value>>>,
+    on_owner_join: SystemId<In<(String, PresenceState, PresenceState)>>,
+}
+
+fn cell_distance(a: IVec2, b: IVec2) -> i32 {
+    (a - b).abs().max_element()
+}
+
+fn setup_interest(world: &mut World) {
+    let systems = InterestSystems {
+        build_cell: world.register_system(build_cell_channel),
+        on_replicate: world.register_system(on_replicate),
+        on_owner_join: world.register_system(on_owner_join),
+    };
+    world.insert_resource(systems);
+}
+
+fn update_interest(
+    mut commands: Commands,
+    mut cells: ResMut<InterestCells>,
+    mut last_focus: Local<Option<IVec2>>,
+    grid: Res<InterestGrid>,
+    config: Res<InterestConfig>,
+    systems: Res<InterestSystems>,
+    client: Res<Client>,
+    focus: Query<&GlobalTransform, With<InterestFocus>>,
+    channels: Query<&Channel>,
+) {
+    let Ok(transform) = focus.get_single() else {
+        return;
+    };
+
+    let center = grid.cell_of(transform.translation());
+
+    if *last_focus == Some(center) {
+        return;
+    }
+
+    *last_focus = Some(center);
+
+    let left: Vec<IVec2> = cells
+        .joined
+        .keys()
+        .filter(|cell| cell_distance(**cell, center) > config.leave_radius)
+        .copied()
+        .collect();
+
+    for cell in left {
+        if let Some(Some(entity)) = cells.joined.remove(&cell) {
+            if let Ok(channel) = channels.get(entity) {
+                let _ = channel.unsubscribe();
+            }
+
+            commands.entity(entity).despawn_recursive();
+        }
+    }
+
+    for x in -config.radius..=config.radius {
+        for y in -config.radius..=config.radius {
+            let cell = center + IVec2::new(x, y);
+
+            if cells.contains(cell) {
+                continue;
+            }
+
+            if client.channel(systems.build_cell).is_err() {
+                error!("Failed to create interest channel for cell {}", cell);
+                continue;
+            }
+
+            cells.joined.insert(cell, None);
+            cells.pending.push_back(cell);
+        }
+    }
+}
+
+fn build_cell_channel(
+    mut channel_builder: In<ChannelBuilder>,
+    mut commands: Commands,
+    mut cells: ResMut<InterestCells>,
+    config: Res<InterestConfig>,
+    systems: Res<InterestSystems>,
+    owner: Option<Res<ReplicationOwner>>,
+) {
+    // Channel requests are answered in order
+    let Some(cell) = cells.pending.pop_front() else {
+        return;
+    };
+
+    // Left again before the channel was built, or already built for an earlier request
+    if cells.joined.get(&cell) != Some(&None) {
+        return;
+    }
+
+    channel_builder.topic(format!("{}:{}:{}", config.topic_prefix, cell.x, cell.y));
+
+    let mut entity = commands.spawn(InterestCell(cell));
+
+    if let Some(owner) = owner {
+        channel_builder
+            .set_broadcast_config(BroadcastConfig {
+                broadcast_self: false,
+                ack: false,
+            })
+            .set_presence_config(PresenceConfig {
+                key: Some(owner.0.clone()),
+            })
+            .on_broadcast(REPLICATE_EVENT, systems.on_replicate)
+            .on_presence(PresenceEvent::Join, systems.on_owner_join);
+
+        entity.insert((
+            ReplicationChannel,
+            PrescenceTrack {
+                payload: HashMap::new(),
+            },
+        ));
+    }
+
+    entity.insert((BevyChannelBuilder(channel_builder.0), BuildChannel));
+    cells.joined.insert(cell, Some(entity.id()));
+}
+
+/// Despawns mirrors outside of the joined cells, their updates are no longer received.
+///
+/// Mirrors with a [Transform] are placed by it, others by the cells they were heard in.
+fn despawn_out_of_interest(
+    mut commands: Commands,
+    cells: Res<InterestCells>,
+    grid: Res<InterestGrid>,
+    entities: Option<ResMut<NetworkEntities>>,
+    q: Query<
+        (
+            Entity,
+            &NetworkId,
+            Option<Ref<Transform>>,
+            Option<Ref<MirrorCells>>,
+        ),
+        With<RemoteOwner>,
+    >,
+) {
+    let Some(mut entities) = entities else {
+        return;
+    };
+
+    for (entity, id, transform, mirror_cells) in q.iter() {
+        let in_interest = match (transform, mirror_cells) {
+            (Some(transform), _) => {
+                if !cells.is_changed() && !transform.is_changed() {
+                    continue;
+                }
+
+                cells.contains(grid.cell_of(transform.translation))
+            }
+            (None, Some(mirror_cells)) => {
+                if !cells.is_changed() && !mirror_cells.is_changed() {
+                    continue;
+                }
+
+                mirror_cells.0.iter().any(|cell| cells.contains(*cell))
+            }
+            (None, None) => continue,
+        };
+
+        if !in_interest {
+            entities.remove(id);
+            commands.entity(entity).despawn_recursive();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn app(joined: &[IVec2]) -> App {
+        let mut app = App::new();
+        app.insert_resource(InterestGrid::new(10.0, InterestPlane::XY))
+            .insert_resource(InterestCells {
+                joined: joined.iter().map(|cell| (*cell, None)).collect(),
+                pending: VecDeque::new(),
+            })
+            .init_resource::<NetworkEntities>()
+            .add_systems(Update, despawn_out_of_interest);
+        app
+    }
+
+    fn mirror(app: &mut App, bundle: impl Bundle) -> Entity {
+        app.world_mut()
+            .spawn((NetworkId::default(), RemoteOwner("them".into()), bundle))
+            .id()
+    }
+
+    #[test]
+    fn mirrors_without_transform_are_culled_by_cell() {
+        let mut app = app(&[IVec2::ZERO]);
+
+        let near = mirror(&mut app, MirrorCells([IVec2::ZERO].into()));
+        let far = mirror(&mut app, MirrorCells([IVec2::new(5, 5)].into()));
+        let placed = mirror(&mut app, Transform::from_xyz(5.0, 5.0, 0.0));
+
+        app.update();
+
+        assert!(app.world().get_entity(near).is_ok());
+        assert!(app.world().get_entity(far).is_err());
+        assert!(app.world().get_entity(placed).is_ok());
+
+        // Leaving the cell culls mirrors that didn't change
+        app.world_mut()
+            .resource_mut::<InterestCells>()
+            .joined
+            .clear();
+        app.update();
+
+        assert!(app.world().get_entity(near).is_err());
+        assert!(app.world().get_entity(placed).is_err());
+    }
+
+    #[test]
+    fn cell_of_floors_on_both_planes() {
+        let xy = InterestGrid::new(10.0, InterestPlane::XY);
+        assert_eq!(xy.cell_of(Vec3::new(15.0, -5.0, 99.0)), IVec2::new(1, -1));
+        assert_eq!(xy.cell_of(Vec3::new(-0.5, 20.0, 0.0)), IVec2::new(-1, 2));
+
+        let xz = InterestGrid::new(10.0, InterestPlane::XZ);
+        assert_eq!(xz.cell_of(Vec3::new(15.0, 99.0, -5.0)), IVec2::new(1, -1));
+        assert_eq!(xz.cell_of(Vec3::new(-0.5, 0.0, 20.0)), IVec2::new(-1, 2));
+    }
+
+    fn joined(app: &App) -> HashSet<IVec2> {
+        app.world().resource::<InterestCells>().iter().collect()
+    }
+
+    fn row(xs: std::ops::RangeInclusive<i32>) -> HashSet<IVec2> {
+        xs.flat_map(|x| (-1..=1).map(move |y| IVec2::new(x, y)))
+            .collect()
+    }
+
+    #[test]
+    fn update_interest_has_hysteresis() {
+        use bevy_crossbeam_event::{CrossbeamEventApp, CrossbeamEventSender};
+
+        use crate::client::{ChannelCallbackEvent, ClientManager, ConnectResultCallbackEvent};
+
+        let mut app = App::new();
+        app.add_crossbeam_event::<ChannelCallbackEvent>()
+            .add_crossbeam_event::<ConnectResultCallbackEvent>();
+
+        // Kept alive so the manager's requests have somewhere to go
+        let realtime = crate::client::Client::builder("http://127.0.0.1:1", "key").build(
+            app.world().resource::<CrossbeamEventSender<_>>().clone(),
+            app.world().resource::<CrossbeamEventSender<_>>().clone(),
+        );
+
+        let world = app.world_mut();
+        let systems = InterestSystems {
+            build_cell: world.register_system(|_: In<ChannelBuilder>| {}),
+            on_replicate: world.register_system(|_: In<HashMap<String, Value>>| {}),
+            on_owner_join: world
+                .register_system(|_: In<(String, PresenceState, PresenceState)>| {}),
+        };
+
+        app.insert_resource(systems)
+            .insert_resource(Client(ClientManager::new(&realtime)))
+            .insert_resource(InterestGrid::new(10.0, InterestPlane::XY))
+            .insert_resource(InterestConfig {
+                topic_prefix: "cell".into(),
+                radius: 1,
+                leave_radius: 2,
+            })
+            .init_resource::<InterestCells>()
+            .add_systems(Update, update_interest);
+
+        let focus = app
+            .world_mut()
+            .spawn((InterestFocus, GlobalTransform::from_xyz(5.0, 5.0, 0.0)))
+            .id();
+
+        let move_to = |app: &mut App, x: f32| {
+            *app.world_mut().get_mut::<GlobalTransform>(focus).unwrap() =
+                GlobalTransform::from_xyz(x, 5.0, 0.0);
+            app.update();
+        };
+
+        app.update();
+        assert_eq!(joined(&app), row(-1..=1));
+
+        // Crossing into the next cell joins the new column and keeps the old one
+        move_to(&mut app, 15.0);
+        assert_eq!(joined(&app), row(-1..=2));
+
+        // Going back neither leaves nor re-joins anything
+        move_to(&mut app, 5.0);
+        assert_eq!(joined(&app), row(-1..=2));
+        assert_eq!(app.world().resource::<InterestCells>().pending.len(), 12);
+
+        // Cells past the leave radius are left
+        move_to(&mut app, 45.0);
+        assert_eq!(joined(&app), row(2..=5));
+    }
+}
@@ -0,0 +1,294 @@
+use std::{collections::VecDeque, time::Duration};
+
+use bevy::prelude::*;
+
+/// Values that can be blended between two snapshots.
+///
+/// `t` is 0 at `self` and 1 at `other`. Values above 1 extrapolate past `other`.
+pub trait Interpolate: Clone {
+    fn interpolate(&self, other: &Self, t: f32) -> Self;
+}
+
+impl Interpolate for f32 {
+    fn interpolate(&self, other: &Self, t: f32) -> Self {
+        self + (other - self) * t
+    }
+}
+
+impl Interpolate for Vec2 {
+    fn interpolate(&self, other: &Self, t: f32) -> Self {
+        self.lerp(*other, t)
+    }
+}
+
+impl Interpolate for Vec3 {
+    fn interpolate(&self, other: &Self, t: f32) -> Self {
+        self.lerp(*other, t)
+    }
+}
+
+impl Interpolate for Quat {
+    fn interpolate(&self, other: &Self, t: f32) -> Self {
+        self.slerp(*other, t)
+    }
+}
+
+impl Interpolate for Transform {
+    fn interpolate(&self, other: &Self, t: f32) -> Self {
+        Transform {
+            translation: self.translation.interpolate(&other.translation, t),
+            rotation: self.rotation.interpolate(&other.rotation, t),
+            scale: self.scale.interpolate(&other.scale, t),
+        }
+    }
+}
+
+/// Timestamped samples of `T` received from the network, rendered `delay` behind the latest.
+///
+/// Sample times are in seconds on the same clock as [Time], e.g. `time.elapsed_secs_f64()`
+/// when the broadcast was received, or a sender timestamp converted with
+/// [crate::clock::NetworkClock::to_local]. When samples stop arriving the last two are
+/// extrapolated for at most `max_extrapolation` before the value holds still.
+#[derive(Component, Debug, Clone)]
+pub struct SnapshotBuffer<T: Interpolate + Send + Sync + 'static> {
+    samples: VecDeque<(f64, T)>,
+    delay: Duration,
+    max_extrapolation: Duration,
+    capacity: usize,
+}
+
+impl<T: Interpolate + Send + Sync + 'static> Default for SnapshotBuffer<T> {
+    fn default() -> Self {
+        Self {
+            samples: VecDeque::new(),
+            delay: Duration::from_millis(100),
+            max_extrapolation: Duration::from_millis(250),
+            capacity: 32,
+        }
+    }
+}
+
+impl<T: Interpolate + Send + Sync + 'static> SnapshotBuffer<T> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set how far behind the current time samples are rendered.
+    /// Default: 100ms
+    pub fn delay(mut self, delay: Duration) -> Self {
+        self.delay = delay;
+        self
+    }
+
+    /// Set how long to extrapolate past the newest sample.
+    /// Default: 250ms
+    pub fn max_extrapolation(mut self, max_extrapolation: Duration) -> Self {
+        self.max_extrapolation = max_extrapolation;
+        self
+    }
+
+    /// Set the maximum number of buffered samples.
+    /// Default: 32
+    /// Minimum: 2
+    pub fn capacity(mut self, capacity: usize) -> Self {
+        self.capacity = capacity.max(2);
+        self
+    }
+
+    /// Adds a sample taken at `time`. Samples arriving out of order are sorted in, and the
+    /// oldest are dropped past capacity.
+    pub fn push(&mut self, time: f64, value: T) {
+        let index = self.samples.partition_point(|(t, _)| *t <= time);
+        self.samples.insert(index, (time, value));
+
+        while self.samples.len() > self.capacity {
+            self.samples.pop_front();
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.samples.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.samples.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.samples.clear();
+    }
+
+    /// Value to render at `time`, or None if there are no samples
+    pub fn sample(&self, time: f64) -> Option<T> {
+        let target = time - self.delay.as_secs_f64();
+        let index = self.samples.partition_point(|(t, _)| *t <= target);
+
+        match (
+            index.checked_sub(1).and_then(|i| self.samples.get(i)),
+            self.samples.get(index),
+        ) {
+            (Some((t0, from)), Some((t1, to))) => {
+                Some(from.interpolate(to, ((target - t0) / (t1 - t0)) as f32))
+            }
+            (None, Some((_, first))) => Some(first.clone()),
+            (Some((last_time, last)), None) => {
+                let Some((prev_time, prev)) =
+                    index.checked_sub(2).and_then(|i| self.samples.get(i))
+                else {
+                    return Some(last.clone());
+                };
+
+                let span = last_time - prev_time;
+
+                if span <= 0.0 {
+                    return Some(last.clone());
+                }
+
+                let ahead = (target - last_time).min(self.max_extrapolation.as_secs_f64());
+
+                Some(prev.interpolate(last, (1.0 + ahead / span) as f32))
+            }
+            (None, None) => None,
+        }
+    }
+
+    /// Drops samples no longer needed to render at `time` or later
+    pub fn prune(&mut self, time: f64) {
+        let target = time - self.delay.as_secs_f64();
+
+        // Keep the sample before the target to interpolate from, and one more to extrapolate
+        while self.samples.len() > 2 && self.samples[2].0 <= target {
+            self.samples.pop_front();
+        }
+    }
+}
+
+/// Writes the buffered value of every [SnapshotBuffer<T>] into its entity's `T`.
+///
+/// Added for [Transform] by [SnapshotInterpolationPlugin]; add it to `Update` for other
+/// components.
+pub fn interpolate_snapshots<T>(time: Res<Time>, mut q: Query<(&mut SnapshotBuffer<T>, &mut T)>)
+where
+    T: Interpolate + Component,
+{
+    let now = time.elapsed_secs_f64();
+
+    for (mut buffer, mut value) in q.iter_mut() {
+        buffer.prune(now);
+
+        if let Some(sampled) = buffer.sample(now) {
+            *value = sampled;
+        }
+    }
+}
+
+/// Smooths remote [Transform]s with [SnapshotBuffer<Transform>]
+pub struct SnapshotInterpolationPlugin;
+
+impl Plugin for SnapshotInterpolationPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Update, interpolate_snapshots::<Transform>);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::time::{TimePlugin, TimeUpdateStrategy};
+
+    use super::*;
+
+    fn buffered(samples: &[(f64, f32)]) -> SnapshotBuffer<f32> {
+        let mut buffer = SnapshotBuffer::new()
+            .delay(Duration::from_millis(100))
+            .max_extrapolation(Duration::from_millis(100));
+
+        for (time, value) in samples {
+            buffer.push(*time, *value);
+        }
+
+        buffer
+    }
+
+    fn assert_near(a: f32, b: f32) {
+        assert!((a - b).abs() < 1e-4, "{} != {}", a, b);
+    }
+
+    #[test]
+    fn samples_are_rendered_delay_behind() {
+        let buffer = buffered(&[(1.0, 0.0), (2.0, 10.0)]);
+
+        assert_near(buffer.sample(1.6).unwrap(), 5.0);
+        assert_near(buffer.sample(2.1).unwrap(), 10.0);
+        // Before the first sample it holds still
+        assert_near(buffer.sample(0.5).unwrap(), 0.0);
+        assert_eq!(SnapshotBuffer::<f32>::new().sample(1.0), None);
+    }
+
+    #[test]
+    fn extrapolation_is_capped() {
+        let buffer = buffered(&[(1.0, 0.0), (2.0, 10.0)]);
+
+        assert_near(buffer.sample(2.15).unwrap(), 10.5);
+        assert_near(buffer.sample(2.2).unwrap(), 11.0);
+        assert_near(buffer.sample(5.0).unwrap(), 11.0);
+
+        // One sample can't be extrapolated
+        assert_near(buffered(&[(1.0, 3.0)]).sample(5.0).unwrap(), 3.0);
+    }
+
+    #[test]
+    fn out_of_order_samples_are_sorted_and_capped() {
+        let mut buffer = buffered(&[(2.0, 10.0), (1.0, 0.0)]).capacity(3);
+
+        assert_near(buffer.sample(1.6).unwrap(), 5.0);
+
+        buffer.push(3.0, 20.0);
+        buffer.push(4.0, 30.0);
+
+        assert_eq!(buffer.len(), 3);
+        // The oldest sample was dropped
+        assert_near(buffer.sample(1.0).unwrap(), 10.0);
+    }
+
+    #[test]
+    fn prune_keeps_samples_around_the_target() {
+        let mut buffer = buffered(&[(1.0, 0.0), (2.0, 10.0), (3.0, 20.0), (4.0, 30.0)]);
+
+        buffer.prune(3.6);
+
+        assert_eq!(buffer.len(), 3);
+        assert_near(buffer.sample(3.6).unwrap(), 25.0);
+
+        buffer.prune(10.0);
+
+        assert_eq!(buffer.len(), 2);
+        assert_near(buffer.sample(4.1).unwrap(), 30.0);
+    }
+
+    #[test]
+    fn transforms_follow_the_buffer() {
+        let mut app = App::new();
+        app.add_plugins((TimePlugin, SnapshotInterpolationPlugin))
+            .insert_resource(TimeUpdateStrategy::ManualDuration(Duration::from_millis(
+                25,
+            )));
+
+        let mut buffer = SnapshotBuffer::new().delay(Duration::from_millis(100));
+        buffer.push(0.0, Transform::default());
+        buffer.push(1.0, Transform::from_xyz(10.0, 0.0, 0.0));
+
+        let entity = app.world_mut().spawn((Transform::default(), buffer)).id();
+
+        for _ in 0..20 {
+            app.update();
+
+            let now = app.world().resource::<Time>().elapsed_secs_f64();
+            let expected = (10.0 * (now - 0.1)).max(0.0) as f32;
+            let transform = app.world().get::<Transform>(entity).unwrap();
+
+            assert_near(transform.translation.x, expected);
+        }
+
+        assert!(app.world().resource::<Time>().elapsed_secs_f64() > 0.1);
+    }
+}
@@ -0,0 +1,345 @@
+use bevy::{ecs::system::SystemId, prelude::*};
+use serde_json::Value;
+
+use crate::{
+    build_channels, build_named_channels,
+    channel::ChannelLifecycleEvent,
+    presence::{PresenceEvent, PresenceState},
+    BevyChannelBuilder, Channel, ChannelClient,
+};
+
+/// Elects a leader among the presence members of the channel on the same entity.
+///
+/// Add it next to the [BevyChannelBuilder] before the channel is built. Members are eligible if
+/// any of their presence metas has `eligible_key` set to `true`, or always if it's None. The
+/// eligible member with the lowest presence key leads, which every client computes the same way
+/// from the same presence state, so no extra messages are needed. The result is kept in
+/// [ChannelLeader] and changes are sent as [LeaderChanged]. Once the channel closes, e.g. after
+/// leaving it, there is no leader until it rejoins. Works with channels of named clients, see
+/// [ChannelClient]. Requires [LeaderElectionPlugin].
+#[derive(Component, Debug, Clone)]
+#[require(ChannelLeader)]
+pub struct LeaderElection {
+    eligible_key: Option<String>,
+}
+
+impl Default for LeaderElection {
+    fn default() -> Self {
+        Self {
+            eligible_key: Some("eligible".into()),
+        }
+    }
+}
+
+impl LeaderElection {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the presence meta key marking members as eligible, or None to make all eligible.
+    /// Default: "eligible"
+    pub fn eligible_key(mut self, eligible_key: Option<String>) -> Self {
+        self.eligible_key = eligible_key;
+        self
+    }
+
+    /// Picks the leader from `state`
+    pub fn elect(&self, state: &PresenceState) -> Option<String> {
+        state
+            .0
+            .iter()
+            .filter(|(_, metas)| match &self.eligible_key {
+                Some(eligible_key) => metas
+                    .values()
+                    .any(|meta| meta.get(eligible_key) == Some(&Value::Bool(true))),
+                None => true,
+            })
+            .map(|(key, _)| key)
+            .min()
+            .cloned()
+    }
+}
+
+/// Presence key of the current leader of the channel on this entity, if there are eligible
+/// members
+#[derive(Component, Debug, Clone, Default, PartialEq, Eq, Deref)]
+pub struct ChannelLeader(pub Option<String>);
+
+impl ChannelLeader {
+    pub fn is(&self, key: &str) -> bool {
+        self.0.as_deref() == Some(key)
+    }
+}
+
+/// Sent when the leader of a channel with [LeaderElection] changes
+#[derive(Event, Debug, Clone)]
+pub struct LeaderChanged {
+    pub channel: Entity,
+    pub previous: Option<String>,
+    pub leader: Option<String>,
+}
+
+pub struct LeaderElectionPlugin;
+
+impl Plugin for LeaderElectionPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<LeaderChanged>().add_systems(
+            Update,
+            (
+                setup_leader_election
+                    .before(build_channels)
+                    .before(build_named_channels),
+                clear_closed_leaders,
+            ),
+        );
+    }
+}
+
+#[derive(Component)]
+struct LeaderStateCallback(SystemId<In<PresenceState>>);
+
+/// Client name and topic of the channel, to match its [ChannelLifecycleEvent]s
+#[derive(Component)]
+struct LeaderChannel {
+    client: Option<String>,
+    topic: String,
+}
+
+fn setup_leader_election(
+    mut commands: Commands,
+    mut q: Query<(Entity, &mut BevyChannelBuilder, Option<&ChannelClient>), Added<LeaderElection>>,
+) {
+    for (entity, mut channel_builder, client) in q.iter_mut() {
+        let on_state = commands.register_system(on_leader_state(entity));
+
+        // Join and leave callbacks fire before the diff is applied, so fetch the full state
+        let on_change = commands.register_system(
+            move |_: In<(String, PresenceState, PresenceState)>,
+                  q: Query<(&Channel, &LeaderStateCallback)>| {
+                if let Ok((channel, callback)) = q.get(entity) {
+                    if channel.presence_state(callback.0).is_err() {
+                        error!("Failed to request presence state for leader election");
+                    }
+                }
+            },
+        );
+
+        channel_builder
+            .on_presence(PresenceEvent::Join, on_change)
+            .on_presence(PresenceEvent::Leave, on_change);
+
+        commands.entity(entity).insert((
+            LeaderStateCallback(on_state),
+            LeaderChannel {
+                client: client.map(|client| client.0.clone()),
+                topic: channel_builder.get_topic().to_string(),
+            },
+        ));
+    }
+}
+
+/// Re-elects the leader of the channel on `entity` from its presence state
+fn on_leader_state(entity: Entity) -> impl System<In = In<PresenceState>, Out = ()> {
+    IntoSystem::into_system(
+        move |In(state): In<PresenceState>,
+              mut q: Query<(&LeaderElection, &mut ChannelLeader)>,
+              mut evw: EventWriter<LeaderChanged>| {
+            let Ok((election, mut leader)) = q.get_mut(entity) else {
+                return;
+            };
+
+            let elected = election.elect(&state);
+            set_leader(entity, &mut leader, elected, &mut evw);
+        },
+    )
+}
+
+/// Clears the leader of channels that closed, the presence state is gone with the channel
+fn clear_closed_leaders(
+    mut evr: EventReader<ChannelLifecycleEvent>,
+    mut q: Query<(Entity, &LeaderChannel, &mut ChannelLeader)>,
+    mut evw: EventWriter<LeaderChanged>,
+) {
+    for event in evr.read() {
+        let ChannelLifecycleEvent::Closed { client, topic, .. } = event else {
+            continue;
+        };
+
+        for (entity, channel, mut leader) in q.iter_mut() {
+            if channel.client == *client && channel.topic == *topic {
+                set_leader(entity, &mut leader, None, &mut evw);
+            }
+        }
+    }
+}
+
+fn set_leader(
+    entity: Entity,
+    leader: &mut ChannelLeader,
+    elected: Option<String>,
+    evw: &mut EventWriter<LeaderChanged>,
+) {
+    if elected == leader.0 {
+        return;
+    }
+
+    let previous = std::mem::replace(&mut leader.0, elected.clone());
+
+    evw.send(LeaderChanged {
+        channel: entity,
+        previous,
+        leader: elected,
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use bevy::ecs::system::RunSystemOnce;
+    use serde_json::json;
+
+    use super::*;
+    use crate::channel::CloseReason;
+
+    /// Presence state with one meta per member, setting `eligible` if given
+    fn state(members: &[(&str, Option<bool>)]) -> PresenceState {
+        PresenceState(
+            members
+                .iter()
+                .map(|(key, eligible)| {
+                    let mut meta = HashMap::new();
+
+                    if let Some(eligible) = eligible {
+                        meta.insert("eligible".to_string(), json!(eligible));
+                    }
+
+                    (key.to_string(), HashMap::from([("ref".to_string(), meta)]))
+                })
+                .collect(),
+        )
+    }
+
+    fn app() -> (App, Entity) {
+        let mut app = App::new();
+        app.add_event::<LeaderChanged>()
+            .add_event::<ChannelLifecycleEvent>();
+
+        let entity = app
+            .world_mut()
+            .spawn((
+                LeaderElection::new(),
+                LeaderChannel {
+                    client: None,
+                    topic: "realtime:game".into(),
+                },
+            ))
+            .id();
+
+        (app, entity)
+    }
+
+    fn leader(app: &App, entity: Entity) -> Option<String> {
+        app.world().get::<ChannelLeader>(entity).unwrap().0.clone()
+    }
+
+    fn changes(app: &mut App) -> Vec<(Option<String>, Option<String>)> {
+        app.world_mut()
+            .resource_mut::<Events<LeaderChanged>>()
+            .drain()
+            .map(|change| (change.previous, change.leader))
+            .collect()
+    }
+
+    #[test]
+    fn elect_picks_lowest_eligible_key() {
+        let election = LeaderElection::new();
+
+        let state = state(&[("c", Some(true)), ("a", Some(false)), ("b", Some(true))]);
+
+        assert_eq!(election.elect(&state), Some("b".into()));
+    }
+
+    #[test]
+    fn elect_filters_by_eligible_key() {
+        let state = state(&[("a", None), ("b", Some(true))]);
+
+        assert_eq!(LeaderElection::new().elect(&state), Some("b".into()));
+        assert_eq!(
+            LeaderElection::new()
+                .eligible_key(Some("host".into()))
+                .elect(&state),
+            None
+        );
+        assert_eq!(
+            LeaderElection::new().eligible_key(None).elect(&state),
+            Some("a".into())
+        );
+    }
+
+    #[test]
+    fn elect_is_none_without_eligible_members() {
+        let election = LeaderElection::new();
+
+        assert_eq!(election.elect(&state(&[])), None);
+        assert_eq!(
+            election.elect(&state(&[("a", Some(false)), ("b", None)])),
+            None
+        );
+    }
+
+    #[test]
+    fn leader_hands_over_when_it_leaves_presence() {
+        let (mut app, entity) = app();
+
+        for members in [
+            vec![("a", Some(true)), ("b", Some(true))],
+            vec![("a", Some(true)), ("b", Some(true))],
+            vec![("b", Some(true))],
+        ] {
+            app.world_mut()
+                .run_system_once_with(state(&members), on_leader_state(entity))
+                .unwrap();
+        }
+
+        assert_eq!(leader(&app, entity), Some("b".into()));
+        assert_eq!(
+            changes(&mut app),
+            vec![
+                (None, Some("a".into())),
+                (Some("a".into()), Some("b".into())),
+            ]
+        );
+    }
+
+    #[test]
+    fn closing_the_channel_clears_the_leader() {
+        let (mut app, entity) = app();
+
+        app.world_mut()
+            .run_system_once_with(state(&[("a", Some(true))]), on_leader_state(entity))
+            .unwrap();
+        changes(&mut app);
+
+        let world = app.world_mut();
+        world.send_event(ChannelLifecycleEvent::Closed {
+            client: Some("other".into()),
+            topic: "realtime:game".into(),
+            reason: CloseReason::Leave,
+        });
+        world.run_system_once(clear_closed_leaders).unwrap();
+
+        assert_eq!(leader(&app, entity), Some("a".into()));
+
+        let world = app.world_mut();
+        world.send_event(ChannelLifecycleEvent::Closed {
+            client: None,
+            topic: "realtime:game".into(),
+            reason: CloseReason::Leave,
+        });
+        world.run_system_once(clear_closed_leaders).unwrap();
+
+        assert_eq!(leader(&app, entity), None);
+        assert_eq!(changes(&mut app), vec![(Some("a".into()), None)]);
+    }
+}
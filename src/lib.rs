@@ -1,30 +1,141 @@
 #![allow(clippy::type_complexity)]
 #![allow(clippy::too_many_arguments)]
 
+// Lets derive macros name the crate the same way from inside it
+extern crate self as bevy_realtime;
+
 pub mod channel;
+pub mod chat;
 pub mod client;
+pub mod clock;
+pub mod delta;
+pub mod interest;
+pub mod interpolation;
+pub mod leader;
+pub mod lobby;
+pub mod lockstep;
 pub mod message;
+#[cfg(test)]
+mod mock;
+pub mod outbound;
 pub mod presence;
+pub mod proxy;
+pub mod push;
+mod rate_limit;
+pub mod replication;
+pub mod rpc;
+pub mod tls;
 
-use std::{thread::sleep, time::Duration};
+use std::{collections::HashMap, thread::sleep, time::Duration};
 
-use bevy::prelude::*;
+use bevy::{ecs::system::SystemParam, prelude::*};
 use bevy_crossbeam_event::{CrossbeamEventApp, CrossbeamEventSender};
 use channel::{
-    BroadcastCallbackEvent, ChannelBuilder, ChannelManager, ChannelStateCallbackEvent,
-    PostgresChangesCallbackEvent, PresenceStateCallbackEvent,
+    BroadcastAck, BroadcastCallbackEvent, ChannelBuilder, ChannelLifecycleEvent, ChannelManager,
+    ChannelStateCallbackEvent, PostgresChangesCallbackEvent, PresenceStateCallbackEvent,
 };
 use client::{
     ChannelCallbackEvent, ClientBuilder, ClientManager, ConnectResultCallbackEvent,
-    ConnectionState, NextMessageError,
+    ConnectionState, LatencySample, NamedConnectionState, NextMessageError,
 };
+use delta::DeltaCodec;
+use outbound::{OutboundBackpressure, QueueBound};
 use presence::PresenceCallbackEvent;
+use proxy::ProxyConfig;
+use push::PushReply;
+#[cfg(any(feature = "native-tls", feature = "rustls"))]
+use tls::TlsConfig;
+use tungstenite::http::HeaderMap;
 
 use crate::presence::{presence_untrack, update_presence_track};
 
 #[derive(Resource, Deref)]
 pub struct Client(pub ClientManager);
 
+/// Clients added with [RealtimePlugin::named], by name
+#[derive(Resource, Default)]
+pub struct RealtimeClients {
+    clients: HashMap<String, NamedClient>,
+}
+
+struct NamedClient {
+    manager: ClientManager,
+    state: ConnectionState,
+    latency: RealtimeLatency,
+}
+
+impl RealtimeClients {
+    pub fn get(&self, name: &str) -> Option<&ClientManager> {
+        self.clients.get(name).map(|client| &client.manager)
+    }
+
+    /// Last known [ConnectionState] of the client, refreshed every frame
+    pub fn state(&self, name: &str) -> Option<ConnectionState> {
+        self.clients.get(name).map(|client| client.state)
+    }
+
+    /// Heartbeat round trip times of the client, like [RealtimeLatency] for the unnamed one
+    pub fn latency(&self, name: &str) -> Option<RealtimeLatency> {
+        self.clients.get(name).map(|client| client.latency)
+    }
+
+    pub fn is_ready(&self, name: &str) -> bool {
+        self.state(name) == Some(ConnectionState::Open)
+    }
+
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.clients.keys().map(String::as_str)
+    }
+}
+
+/// Builds the channel on this entity with the client added under this name with
+/// [RealtimePlugin::named], rather than the default [Client]
+#[derive(Component, Debug, Clone, PartialEq, Eq, Deref)]
+pub struct ChannelClient(pub String);
+
+impl ChannelClient {
+    pub fn new(name: impl Into<String>) -> Self {
+        Self(name.into())
+    }
+}
+
+/// Heartbeat round trip times of the unnamed [Client] to the realtime server. Named clients'
+/// are in [RealtimeClients::latency].
+#[derive(Resource, Debug, Default, Clone, Copy)]
+pub struct RealtimeLatency {
+    /// Most recent round trip time
+    pub last: Option<Duration>,
+    /// Exponentially smoothed round trip time, steadier for display
+    pub smoothed: Option<Duration>,
+}
+
+impl RealtimeLatency {
+    fn add_sample(&mut self, rtt: Duration) {
+        self.last = Some(rtt);
+        self.smoothed = Some(match self.smoothed {
+            Some(smoothed) => smoothed.mul_f64(0.875) + rtt.mul_f64(0.125),
+            None => rtt,
+        });
+    }
+}
+
+fn update_latency(
+    mut evr: EventReader<LatencySample>,
+    mut latency: ResMut<RealtimeLatency>,
+    mut clients: ResMut<RealtimeClients>,
+) {
+    for sample in evr.read() {
+        match &sample.client {
+            Some(name) => {
+                if let Some(client) = clients.clients.get_mut(name) {
+                    client.latency.add_sample(sample.rtt);
+                }
+            }
+            None => latency.add_sample(sample.rtt),
+        }
+    }
+}
+
 #[derive(Component, Deref, DerefMut)]
 pub struct BevyChannelBuilder(pub ChannelBuilder);
 
@@ -34,27 +145,73 @@ pub struct Channel(pub ChannelManager);
 #[derive(Component)]
 pub struct BuildChannel;
 
+#[derive(SystemParam)]
+struct ChannelEventSenders<'w> {
+    presence_state: Res<'w, CrossbeamEventSender<PresenceStateCallbackEvent>>,
+    channel_state: Res<'w, CrossbeamEventSender<ChannelStateCallbackEvent>>,
+    broadcast: Res<'w, CrossbeamEventSender<BroadcastCallbackEvent>>,
+    presence: Res<'w, CrossbeamEventSender<PresenceCallbackEvent>>,
+    postgres_changes: Res<'w, CrossbeamEventSender<PostgresChangesCallbackEvent>>,
+    broadcast_ack: Res<'w, CrossbeamEventSender<BroadcastAck>>,
+    push_reply: Res<'w, CrossbeamEventSender<PushReply>>,
+    lifecycle: Res<'w, CrossbeamEventSender<ChannelLifecycleEvent>>,
+}
+
+impl ChannelEventSenders<'_> {
+    fn build(&self, builder: &ChannelBuilder, client: &ClientManager) -> ChannelManager {
+        builder.build(
+            client,
+            self.presence_state.clone(),
+            self.channel_state.clone(),
+            self.broadcast.clone(),
+            self.presence.clone(),
+            self.postgres_changes.clone(),
+            self.broadcast_ack.clone(),
+            self.push_reply.clone(),
+            self.lifecycle.clone(),
+        )
+    }
+}
+
 fn build_channels(
     mut commands: Commands,
-    mut q: Query<(Entity, &mut BevyChannelBuilder), With<BuildChannel>>,
+    q: Query<(Entity, &BevyChannelBuilder), (With<BuildChannel>, Without<ChannelClient>)>,
     client: Res<Client>,
-    presence_state_callback_event_sender: Res<CrossbeamEventSender<PresenceStateCallbackEvent>>,
-    channel_state_callback_event_sender: Res<CrossbeamEventSender<ChannelStateCallbackEvent>>,
-    broadcast_callback_event_sender: Res<CrossbeamEventSender<BroadcastCallbackEvent>>,
-    presence_callback_event_sender: Res<CrossbeamEventSender<PresenceCallbackEvent>>,
-    postgres_changes_callback_event_sender: Res<CrossbeamEventSender<PostgresChangesCallbackEvent>>,
+    senders: ChannelEventSenders,
 ) {
-    for (e, c) in q.iter_mut() {
+    for (e, c) in q.iter() {
         commands.entity(e).remove::<BevyChannelBuilder>();
 
-        let channel = c.build(
-            &client.0,
-            presence_state_callback_event_sender.clone(),
-            channel_state_callback_event_sender.clone(),
-            broadcast_callback_event_sender.clone(),
-            presence_callback_event_sender.clone(),
-            postgres_changes_callback_event_sender.clone(),
-        );
+        let channel = senders.build(c, &client.0);
+
+        channel.subscribe().unwrap();
+        commands.entity(e).insert(Channel(channel));
+    }
+}
+
+/// Builds channels of [ChannelClient] entities once their client is connected
+fn build_named_channels(
+    mut commands: Commands,
+    q: Query<(Entity, &BevyChannelBuilder, &ChannelClient), With<BuildChannel>>,
+    clients: Res<RealtimeClients>,
+    senders: ChannelEventSenders,
+) {
+    for (e, c, name) in q.iter() {
+        let Some(client) = clients.get(name) else {
+            error!("No realtime client named {}, dropping channel", name.0);
+            commands
+                .entity(e)
+                .remove::<(BevyChannelBuilder, BuildChannel)>();
+            continue;
+        };
+
+        if !clients.is_ready(name) {
+            continue;
+        }
+
+        commands.entity(e).remove::<BevyChannelBuilder>();
+
+        let channel = senders.build(c, client);
 
         channel.subscribe().unwrap();
         commands.entity(e).insert(Channel(channel));
@@ -64,44 +221,194 @@ fn build_channels(
 pub struct RealtimePlugin {
     endpoint: String,
     apikey: String,
+    headers: HeaderMap,
+    proxy: ProxyConfig,
+    outbound_bound: Option<QueueBound>,
+    manager_bound: Option<QueueBound>,
+    delta_codec: Option<DeltaCodec>,
+    max_events_per_second: Option<usize>,
+    max_events_burst: Option<usize>,
+    name: Option<String>,
+    #[cfg(any(feature = "native-tls", feature = "rustls"))]
+    tls_config: Option<TlsConfig>,
 }
 
 impl RealtimePlugin {
     pub fn new(endpoint: String, apikey: String) -> Self {
-        Self { endpoint, apikey }
+        Self {
+            endpoint,
+            apikey,
+            headers: HeaderMap::new(),
+            proxy: ProxyConfig::None,
+            outbound_bound: None,
+            manager_bound: None,
+            delta_codec: None,
+            max_events_per_second: None,
+            max_events_burst: None,
+            name: None,
+            #[cfg(any(feature = "native-tls", feature = "rustls"))]
+            tls_config: None,
+        }
+    }
+
+    /// Add headers to send with the WebSocket upgrade request, see [ClientBuilder::add_headers]
+    pub fn headers(mut self, headers: HeaderMap) -> Self {
+        self.headers.extend(headers);
+        self
+    }
+
+    /// Set the proxy used to reach the server, see [ClientBuilder::proxy]
+    pub fn proxy(mut self, proxy: ProxyConfig) -> Self {
+        self.proxy = proxy;
+        self
+    }
+
+    /// Bound the outbound message queue, see [ClientBuilder::outbound_queue_bound]
+    pub fn outbound_queue_bound(mut self, bound: QueueBound) -> Self {
+        self.outbound_bound = Some(bound);
+        self
+    }
+
+    /// Bound the client and channel manager queues, see [ClientBuilder::manager_queue_bound]
+    pub fn manager_queue_bound(mut self, bound: QueueBound) -> Self {
+        self.manager_bound = Some(bound);
+        self
+    }
+
+    /// Delta compress broadcasts, see [ClientBuilder::delta_codec]
+    pub fn delta_codec(mut self, codec: DeltaCodec) -> Self {
+        self.delta_codec = Some(codec);
+        self
+    }
+
+    /// Limit the events sent per second, see [ClientBuilder::max_events_per_second]
+    pub fn max_events_per_second(mut self, count: usize) -> Self {
+        self.max_events_per_second = Some(count);
+        self
+    }
+
+    /// Set how many events can be sent at once, see [ClientBuilder::max_events_burst]
+    pub fn max_events_burst(mut self, count: usize) -> Self {
+        self.max_events_burst = Some(count);
+        self
+    }
+
+    /// Add this as a named client, so several can be connected at once. Named clients are kept in
+    /// [RealtimeClients] rather than [Client], and only build channels on entities with a
+    /// matching [ChannelClient]. Their events, e.g. [ChannelLifecycleEvent] and
+    /// [OutboundBackpressure], carry the name, see [ClientBuilder::name].
+    ///
+    /// The gameplay plugins, e.g. [lobby::LobbyPlugin] and [replication::ReplicationPlugin],
+    /// always use the unnamed [Client], so add one for them.
+    /// Default: unnamed
+    pub fn named(mut self, name: impl Into<String>) -> Self {
+        self.name = Some(name.into());
+        self
+    }
+
+    /// Set the TLS configuration for the client, see [ClientBuilder::tls_config]
+    #[cfg(any(feature = "native-tls", feature = "rustls"))]
+    pub fn tls_config(mut self, tls_config: impl Into<TlsConfig>) -> Self {
+        self.tls_config = Some(tls_config.into());
+        self
     }
 }
 
 impl Plugin for RealtimePlugin {
     fn build(&self, app: &mut App) {
-        app.add_crossbeam_event::<ConnectionState>()
-            .add_crossbeam_event::<ChannelCallbackEvent>()
-            .add_crossbeam_event::<PresenceStateCallbackEvent>()
-            .add_crossbeam_event::<ChannelStateCallbackEvent>()
-            .add_crossbeam_event::<BroadcastCallbackEvent>()
-            .add_crossbeam_event::<PresenceCallbackEvent>()
-            .add_crossbeam_event::<PostgresChangesCallbackEvent>()
-            .add_crossbeam_event::<ConnectResultCallbackEvent>()
-            .add_systems(
-                Update,
-                (
-                    ((
-                        //
-                        update_presence_track,
-                        presence_untrack,
-                        build_channels,
+        match &self.name {
+            Some(name) => {
+                if app
+                    .world()
+                    .get_resource::<RealtimeClients>()
+                    .is_some_and(|clients| clients.get(name).is_some())
+                {
+                    panic!("A realtime client named {} was already added", name);
+                }
+            }
+            None => {
+                if app.world().contains_resource::<Client>() {
+                    panic!("An unnamed realtime client was already added, use RealtimePlugin::named to add more");
+                }
+            }
+        }
+
+        // Shared by all clients, so only set up for the first one
+        if !app.world().contains_resource::<RealtimeClients>() {
+            app.add_crossbeam_event::<ConnectionState>()
+                .add_crossbeam_event::<NamedConnectionState>()
+                .add_crossbeam_event::<ChannelCallbackEvent>()
+                .add_crossbeam_event::<PresenceStateCallbackEvent>()
+                .add_crossbeam_event::<ChannelStateCallbackEvent>()
+                .add_crossbeam_event::<BroadcastCallbackEvent>()
+                .add_crossbeam_event::<PresenceCallbackEvent>()
+                .add_crossbeam_event::<PostgresChangesCallbackEvent>()
+                .add_crossbeam_event::<ConnectResultCallbackEvent>()
+                .add_crossbeam_event::<OutboundBackpressure>()
+                .add_crossbeam_event::<BroadcastAck>()
+                .add_crossbeam_event::<PushReply>()
+                .add_crossbeam_event::<ChannelLifecycleEvent>()
+                .add_crossbeam_event::<LatencySample>()
+                .init_resource::<RealtimeLatency>()
+                .init_resource::<RealtimeClients>()
+                .add_systems(
+                    Update,
+                    (
+                        update_named_client_states,
+                        (
+                            //
+                            update_presence_track,
+                            presence_untrack,
+                        )
+                            .chain()
+                            .run_if(client_ready.or(named_clients_ready)),
+                        build_channels.run_if(client_ready),
+                        build_named_channels,
+                        run_callbacks,
                     )
-                        .chain()
-                        .run_if(client_ready),),
-                    run_callbacks,
+                        .chain(),
                 )
-                    .chain(),
-            );
+                .add_systems(Update, update_latency);
+        }
 
         // TODO: Allow this to fail and be retried later at user request
 
         let mut client = ClientBuilder::new(self.endpoint.clone(), self.apikey.clone());
+        if let Some(name) = &self.name {
+            client.name(name.clone());
+        }
         client.reconnect_max_attempts(3);
+        client.add_headers(self.headers.clone());
+        client.proxy(self.proxy.clone());
+        if let Some(bound) = self.outbound_bound {
+            client.outbound_queue_bound(bound);
+        }
+        if let Some(bound) = self.manager_bound {
+            client.manager_queue_bound(bound);
+        }
+        if let Some(count) = self.max_events_per_second {
+            client.max_events_per_second(count);
+        }
+        if let Some(count) = self.max_events_burst {
+            client.max_events_burst(count);
+        }
+        if let Some(codec) = &self.delta_codec {
+            client.delta_codec(codec.clone());
+        }
+        #[cfg(any(feature = "native-tls", feature = "rustls"))]
+        if let Some(tls_config) = &self.tls_config {
+            client.tls_config(tls_config.clone());
+        }
+        client.outbound_backpressure_events(
+            app.world()
+                .resource::<CrossbeamEventSender<OutboundBackpressure>>()
+                .clone(),
+        );
+        client.latency_events(
+            app.world()
+                .resource::<CrossbeamEventSender<LatencySample>>()
+                .clone(),
+        );
         let mut client = client.build(
             app.world_mut()
                 .resource::<CrossbeamEventSender<ChannelCallbackEvent>>()
@@ -111,7 +418,26 @@ impl Plugin for RealtimePlugin {
                 .clone(),
         );
 
-        app.insert_resource(Client(ClientManager::new(&client)));
+        let manager = ClientManager::new(&client);
+
+        match &self.name {
+            Some(name) => {
+                app.world_mut()
+                    .resource_mut::<RealtimeClients>()
+                    .clients
+                    .insert(
+                        name.clone(),
+                        NamedClient {
+                            manager,
+                            state: ConnectionState::default(),
+                            latency: RealtimeLatency::default(),
+                        },
+                    );
+            }
+            None => {
+                app.insert_resource(Client(manager));
+            }
+        }
 
         // Start off thread client
         let _thread = std::thread::spawn(move || {
@@ -127,6 +453,10 @@ impl Plugin for RealtimePlugin {
             }
         });
     }
+
+    fn is_unique(&self) -> bool {
+        false
+    }
 }
 
 fn run_callbacks(
@@ -171,7 +501,7 @@ fn run_callbacks(
     }
 
     for ev in connect_evr.read() {
-        let (callback, input) = ev.0.clone();
+        let (callback, input) = ev.0;
         commands.run_system_with_input(callback, input);
     }
 }
@@ -179,9 +509,13 @@ fn run_callbacks(
 pub fn client_ready(
     mut evr: EventReader<ConnectionState>,
     mut last_state: Local<ConnectionState>,
-    client: Res<Client>,
+    client: Option<Res<Client>>,
     sender: Res<CrossbeamEventSender<ConnectionState>>,
 ) -> bool {
+    let Some(client) = client else {
+        return false;
+    };
+
     client.connection_state(sender.clone()).unwrap_or(());
 
     for ev in evr.read() {
@@ -190,3 +524,35 @@ pub fn client_ready(
 
     *last_state == ConnectionState::Open
 }
+
+/// Run condition for the client added with [RealtimePlugin::named] under `name` being connected
+pub fn named_client_ready(name: impl Into<String>) -> impl FnMut(Res<RealtimeClients>) -> bool {
+    let name = name.into();
+    move |clients: Res<RealtimeClients>| clients.is_ready(&name)
+}
+
+fn named_clients_ready(clients: Res<RealtimeClients>) -> bool {
+    clients
+        .clients
+        .values()
+        .any(|client| client.state == ConnectionState::Open)
+}
+
+fn update_named_client_states(
+    mut evr: EventReader<NamedConnectionState>,
+    mut clients: ResMut<RealtimeClients>,
+    sender: Res<CrossbeamEventSender<NamedConnectionState>>,
+) {
+    for ev in evr.read() {
+        if let Some(client) = clients.clients.get_mut(&ev.client) {
+            client.state = ev.state;
+        }
+    }
+
+    for (name, client) in clients.clients.iter() {
+        client
+            .manager
+            .named_connection_state(name.clone(), sender.clone())
+            .unwrap_or(());
+    }
+}
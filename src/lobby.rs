@@ -0,0 +1,784 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+use bevy::{ecs::system::SystemId, prelude::*};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use uuid::Uuid;
+
+use crate::{
+    channel::ChannelBuilder,
+    client_ready,
+    message::payload::{BroadcastConfig, BroadcastPayload, PresenceConfig},
+    presence::{PrescenceTrack, PresenceEvent, PresenceState},
+    BevyChannelBuilder, BuildChannel, Channel, Client,
+};
+
+const KICK_EVENT: &str = "lobby_kick";
+const START_EVENT: &str = "lobby_start";
+
+/// Lobbies and matchmaking on top of presence and broadcast.
+///
+/// Every player joins the directory channel, where hosts track their [LobbyInfo] as presence
+/// meta, giving everyone the [LobbyDirectory]. Each lobby has its own channel in which members
+/// track their ready state. The host enforces the player limit and bans by kicking joiners,
+/// and starts the match by broadcasting a fresh match topic to everyone in the lobby.
+///
+/// Kicks and bans are advisory: they are broadcasts the kicked client obeys, so a modified
+/// client can ignore them and stay in the lobby channel. Use Realtime authorization, i.e. RLS
+/// policies on `realtime.messages`, to actually keep players out of a lobby's topic.
+///
+/// Drive it with [LobbyCommand] events, follow it through [LobbyEvent]s and [CurrentLobby].
+/// Requires an unnamed [crate::RealtimePlugin].
+pub struct LobbyPlugin {
+    directory_topic: String,
+    player_key: String,
+}
+
+impl LobbyPlugin {
+    pub fn new(directory_topic: impl Into<String>) -> Self {
+        Self {
+            directory_topic: directory_topic.into(),
+            player_key: Uuid::new_v4().to_string(),
+        }
+    }
+
+    /// Set the presence key identifying this player.
+    /// Default: random
+    pub fn player_key(mut self, player_key: impl Into<String>) -> Self {
+        self.player_key = player_key.into();
+        self
+    }
+}
+
+impl Plugin for LobbyPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(LobbyPlayer(self.player_key.clone()))
+            .insert_resource(LobbyTopic(self.directory_topic.clone()))
+            .init_resource::<LobbyDirectory>()
+            .init_resource::<CurrentLobby>()
+            .add_event::<LobbyCommand>()
+            .add_event::<LobbyEvent>()
+            .add_event::<LobbyExit>()
+            .add_systems(Startup, setup_lobby_channels)
+            .add_systems(
+                Update,
+                (handle_lobby_commands.run_if(client_ready), exit_lobby).chain(),
+            );
+    }
+}
+
+/// Presence key of this player
+#[derive(Resource, Debug, Clone, PartialEq, Eq, Deref)]
+pub struct LobbyPlayer(pub String);
+
+/// A lobby as advertised in the directory
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LobbyInfo {
+    pub id: String,
+    pub name: String,
+    /// Presence key of the host
+    pub host: String,
+    pub players: usize,
+    pub max_players: usize,
+}
+
+impl LobbyInfo {
+    pub fn is_full(&self) -> bool {
+        self.players >= self.max_players
+    }
+}
+
+/// Lobbies currently advertised in the directory, by id
+#[derive(Resource, Debug, Default)]
+pub struct LobbyDirectory(BTreeMap<String, LobbyInfo>);
+
+impl LobbyDirectory {
+    pub fn get(&self, id: &str) -> Option<&LobbyInfo> {
+        self.0.get(id)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &LobbyInfo> {
+        self.0.values()
+    }
+
+    /// Lobbies with room for another player
+    pub fn open(&self) -> impl Iterator<Item = &LobbyInfo> {
+        self.iter().filter(|info| !info.is_full())
+    }
+}
+
+/// The lobby this player is in
+#[derive(Debug, Clone)]
+pub struct Lobby {
+    info: LobbyInfo,
+    /// Ready state by presence key
+    members: BTreeMap<String, bool>,
+    banned: HashSet<String>,
+}
+
+impl Lobby {
+    pub fn info(&self) -> &LobbyInfo {
+        &self.info
+    }
+
+    /// Ready state of each member by presence key
+    pub fn members(&self) -> &BTreeMap<String, bool> {
+        &self.members
+    }
+
+    pub fn is_host(&self, player: &str) -> bool {
+        self.info.host == player
+    }
+
+    pub fn all_ready(&self) -> bool {
+        !self.members.is_empty() && self.members.values().all(|ready| *ready)
+    }
+}
+
+#[derive(Resource, Debug, Default, Deref)]
+pub struct CurrentLobby(Option<Lobby>);
+
+/// Requests handled by [LobbyPlugin]
+#[derive(Event, Debug, Clone)]
+pub enum LobbyCommand {
+    /// Create and host a lobby
+    Create {
+        name: String,
+        max_players: usize,
+    },
+    /// Join a lobby from the [LobbyDirectory] by id
+    Join(String),
+    Leave,
+    SetReady(bool),
+    /// Host only, ask a player to leave. Advisory, see [LobbyPlugin]
+    Kick(String),
+    /// Host only, ask a player to leave and kick them whenever they rejoin. Advisory, see
+    /// [LobbyPlugin]
+    Ban(String),
+    /// Host only, move everyone to a new match topic
+    StartMatch,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum JoinError {
+    AlreadyInLobby,
+    NotFound,
+    Full,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LeaveReason {
+    Left,
+    Kicked,
+    Banned,
+    /// The lobby was at its player limit
+    Full,
+    /// The host left
+    Closed,
+}
+
+#[derive(Event, Debug, Clone, PartialEq, Eq)]
+pub enum LobbyEvent {
+    Joined(String),
+    JoinFailed {
+        id: String,
+        error: JoinError,
+    },
+    Left {
+        id: String,
+        reason: LeaveReason,
+    },
+    MemberJoined(String),
+    MemberLeft(String),
+    ReadyChanged {
+        player: String,
+        ready: bool,
+    },
+    /// Every member is ready
+    AllReady,
+    /// The lobby was closed and its members moved to `topic`
+    MatchStarted {
+        topic: String,
+    },
+}
+
+/// Leaves the current lobby, with the [LobbyEvent::Left] reason if any
+#[derive(Event)]
+struct LobbyExit(Option<LeaveReason>);
+
+#[derive(Resource, Deref)]
+struct LobbyTopic(String);
+
+#[derive(Resource)]
+struct LobbySystems {
+    build_lobby: SystemId<In<ChannelBuilder>>,
+    on_directory_state: SystemId<In<PresenceState>>,
+    on_lobby_state: SystemId<In<PresenceState>>,
+    on_lobby_join: SystemId<In<(String, PresenceState, PresenceState)>>,
+    on_lobby_leave: SystemId<In<(String, PresenceState, PresenceState)>>,
+    on_lobby_kick: SystemId<In<HashMap<String, Value>>>,
+    on_lobby_start: SystemId<In<HashMap<String, Value>>>,
+}
+
+/// Marks the directory channel used by [LobbyPlugin]
+#[derive(Component)]
+pub struct LobbyDirectoryChannel;
+
+/// Marks the channel of the current lobby
+#[derive(Component)]
+pub struct LobbyChannel;
+
+fn lobby_topic(directory_topic: &str, id: &str) -> String {
+    format!("{}:lobby:{}", directory_topic, id)
+}
+
+fn setup_lobby_channels(world: &mut World) {
+    let systems = LobbySystems {
+        build_lobby: world.register_system(build_lobby_channel),
+        on_directory_state: world.register_system(on_directory_state),
+        on_lobby_state: world.register_system(on_lobby_state),
+        on_lobby_join: world.register_system(on_lobby_join),
+        on_lobby_leave: world.register_system(on_lobby_leave),
+        on_lobby_kick: world.register_system(on_lobby_kick),
+        on_lobby_start: world.register_system(on_lobby_start),
+    };
+    world.insert_resource(systems);
+
+    let build_directory = world.register_system(build_directory_channel);
+    let client = world.resource::<Client>();
+
+    if client.channel(build_directory).is_err() {
+        error!("Failed to create lobby directory channel");
+    }
+}
+
+fn build_directory_channel(
+    mut channel_builder: In<ChannelBuilder>,
+    mut commands: Commands,
+    topic: Res<LobbyTopic>,
+    player: Res<LobbyPlayer>,
+) {
+    let on_change = commands.register_system(on_directory_change);
+
+    channel_builder
+        .topic(topic.as_str())
+        .set_presence_config(PresenceConfig {
+            key: Some(player.0.clone()),
+        })
+        .on_presence(PresenceEvent::Join, on_change)
+        .on_presence(PresenceEvent::Leave, on_change);
+
+    commands.spawn((
+        BevyChannelBuilder(channel_builder.0),
+        BuildChannel,
+        LobbyDirectoryChannel,
+    ));
+}
+
+fn build_lobby_channel(
+    mut channel_builder: In<ChannelBuilder>,
+    mut commands: Commands,
+    topic: Res<LobbyTopic>,
+    player: Res<LobbyPlayer>,
+    lobby: Res<CurrentLobby>,
+    systems: Res<LobbySystems>,
+) {
+    let Some(lobby) = &lobby.0 else {
+        return;
+    };
+
+    channel_builder
+        .topic(lobby_topic(&topic, &lobby.info.id))
+        .set_broadcast_config(BroadcastConfig {
+            broadcast_self: false,
+            ack: false,
+        })
+        .set_presence_config(PresenceConfig {
+            key: Some(player.0.clone()),
+        })
+        .on_presence(PresenceEvent::Join, systems.on_lobby_join)
+        .on_presence(PresenceEvent::Leave, systems.on_lobby_leave)
+        .on_broadcast(KICK_EVENT, systems.on_lobby_kick)
+        .on_broadcast(START_EVENT, systems.on_lobby_start);
+
+    let mut track = HashMap::new();
+    track.insert("ready".into(), false.into());
+
+    commands.spawn((
+        BevyChannelBuilder(channel_builder.0),
+        BuildChannel,
+        LobbyChannel,
+        PrescenceTrack { payload: track },
+    ));
+}
+
+fn handle_lobby_commands(
+    mut commands: Commands,
+    mut evr: EventReader<LobbyCommand>,
+    mut evw: EventWriter<LobbyEvent>,
+    mut exits: EventWriter<LobbyExit>,
+    mut lobby: ResMut<CurrentLobby>,
+    directory: Res<LobbyDirectory>,
+    player: Res<LobbyPlayer>,
+    topic: Res<LobbyTopic>,
+    systems: Res<LobbySystems>,
+    client: Res<Client>,
+    directory_channel: Query<Entity, With<LobbyDirectoryChannel>>,
+    mut lobby_channel: Query<(&Channel, &mut PrescenceTrack), With<LobbyChannel>>,
+) {
+    for command in evr.read() {
+        match command {
+            LobbyCommand::Create { name, max_players } => {
+                let id = Uuid::new_v4().to_string();
+
+                if lobby.0.is_some() {
+                    evw.send(LobbyEvent::JoinFailed {
+                        id,
+                        error: JoinError::AlreadyInLobby,
+                    });
+                    continue;
+                }
+
+                let info = LobbyInfo {
+                    id: id.clone(),
+                    name: name.clone(),
+                    host: player.0.clone(),
+                    players: 1,
+                    max_players: (*max_players).max(1),
+                };
+
+                for entity in directory_channel.iter() {
+                    commands.entity(entity).insert(PrescenceTrack {
+                        payload: to_payload(&info),
+                    });
+                }
+
+                lobby.0 = Some(Lobby {
+                    info,
+                    members: BTreeMap::new(),
+                    banned: HashSet::new(),
+                });
+
+                if client.channel(systems.build_lobby).is_err() {
+                    error!("Failed to create lobby channel");
+                }
+
+                evw.send(LobbyEvent::Joined(id));
+            }
+            LobbyCommand::Join(id) => {
+                let error = match directory.get(id) {
+                    _ if lobby.0.is_some() => Some(JoinError::AlreadyInLobby),
+                    None => Some(JoinError::NotFound),
+                    Some(info) if info.is_full() => Some(JoinError::Full),
+                    Some(_) => None,
+                };
+
+                if let Some(error) = error {
+                    evw.send(LobbyEvent::JoinFailed {
+                        id: id.clone(),
+                        error,
+                    });
+                    continue;
+                }
+
+                lobby.0 = directory.get(id).map(|info| Lobby {
+                    info: info.clone(),
+                    members: BTreeMap::new(),
+                    banned: HashSet::new(),
+                });
+
+                if client.channel(systems.build_lobby).is_err() {
+                    error!("Failed to create lobby channel");
+                }
+
+                evw.send(LobbyEvent::Joined(id.clone()));
+            }
+            LobbyCommand::Leave => {
+                exits.send(LobbyExit(Some(LeaveReason::Left)));
+            }
+            LobbyCommand::SetReady(ready) => {
+                for (_, mut track) in lobby_channel.iter_mut() {
+                    track.payload.insert("ready".into(), (*ready).into());
+                }
+            }
+            LobbyCommand::Kick(target) | LobbyCommand::Ban(target) => {
+                let Some(lobby) = lobby.0.as_mut().filter(|lobby| lobby.is_host(&player)) else {
+                    continue;
+                };
+
+                let reason = match command {
+                    LobbyCommand::Ban(_) => {
+                        lobby.banned.insert(target.clone());
+                        LeaveReason::Banned
+                    }
+                    _ => LeaveReason::Kicked,
+                };
+
+                for (channel, _) in lobby_channel.iter() {
+                    send_kick(channel, &lobby.info.id, target, reason);
+                }
+            }
+            LobbyCommand::StartMatch => {
+                let Some(lobby) = lobby.0.as_ref().filter(|lobby| lobby.is_host(&player)) else {
+                    continue;
+                };
+
+                let match_topic = format!("{}:match:{}", topic.0, Uuid::new_v4());
+
+                let mut payload = HashMap::new();
+                payload.insert("lobby".into(), lobby.info.id.clone().into());
+                payload.insert("topic".into(), match_topic.clone().into());
+
+                for (channel, _) in lobby_channel.iter() {
+                    let _ = channel.broadcast(BroadcastPayload {
+                        event: START_EVENT.into(),
+                        payload: payload.clone(),
+                        ..Default::default()
+                    });
+                }
+
+                evw.send(LobbyEvent::MatchStarted { topic: match_topic });
+                exits.send(LobbyExit(None));
+            }
+        }
+    }
+}
+
+fn exit_lobby(
+    mut commands: Commands,
+    mut exits: EventReader<LobbyExit>,
+    mut evw: EventWriter<LobbyEvent>,
+    mut lobby: ResMut<CurrentLobby>,
+    directory_channel: Query<Entity, With<LobbyDirectoryChannel>>,
+    lobby_channel: Query<(Entity, Option<&Channel>), With<LobbyChannel>>,
+) {
+    let Some(LobbyExit(reason)) = exits.read().last() else {
+        return;
+    };
+
+    let Some(left) = lobby.0.take() else {
+        return;
+    };
+
+    for (entity, channel) in lobby_channel.iter() {
+        if let Some(channel) = channel {
+            let _ = channel.unsubscribe();
+        }
+
+        commands.entity(entity).despawn_recursive();
+    }
+
+    for entity in directory_channel.iter() {
+        commands.entity(entity).remove::<PrescenceTrack>();
+    }
+
+    if let Some(reason) = reason {
+        evw.send(LobbyEvent::Left {
+            id: left.info.id,
+            reason: *reason,
+        });
+    }
+}
+
+fn send_kick(channel: &Channel, lobby: &str, player: &str, reason: LeaveReason) {
+    let reason = match reason {
+        LeaveReason::Banned => "banned",
+        LeaveReason::Full => "full",
+        _ => "kicked",
+    };
+
+    let mut payload = HashMap::new();
+    payload.insert("lobby".into(), lobby.into());
+    payload.insert("player".into(), player.into());
+    payload.insert("reason".into(), reason.into());
+
+    let _ = channel.broadcast(BroadcastPayload {
+        event: KICK_EVENT.into(),
+        payload,
+        ..Default::default()
+    });
+}
+
+fn to_payload(info: &LobbyInfo) -> HashMap<String, Value> {
+    match serde_json::to_value(info) {
+        Ok(Value::Object(fields)) => fields.into_iter().collect(),
+        _ => HashMap::new(),
+    }
+}
+
+fn on_directory_change(
+    _: In<(String, PresenceState, PresenceState)>,
+    systems: Res<LobbySystems>,
+    q: Query<&Channel, With<LobbyDirectoryChannel>>,
+) {
+    for channel in q.iter() {
+        let _ = channel.presence_state(systems.on_directory_state);
+    }
+}
+
+fn on_directory_state(In(state): In<PresenceState>, mut directory: ResMut<LobbyDirectory>) {
+    directory.0 = state
+        .get_phx_map()
+        .into_values()
+        .filter_map(|meta| {
+            serde_json::from_value::<LobbyInfo>(Value::Object(meta.into_iter().collect())).ok()
+        })
+        .map(|info| (info.id.clone(), info))
+        .collect();
+}
+
+fn on_lobby_join(
+    In((key, state, joins)): In<(String, PresenceState, PresenceState)>,
+    systems: Res<LobbySystems>,
+    lobby: Res<CurrentLobby>,
+    player: Res<LobbyPlayer>,
+    q: Query<&Channel, With<LobbyChannel>>,
+) {
+    for channel in q.iter() {
+        let _ = channel.presence_state(systems.on_lobby_state);
+    }
+
+    let Some(lobby) = lobby.0.as_ref().filter(|lobby| lobby.is_host(&player)) else {
+        return;
+    };
+
+    if key == player.0 || state.0.contains_key(&key) {
+        return;
+    }
+
+    let members = state.0.keys().chain(joins.0.keys()).collect::<HashSet<_>>();
+
+    let reason = if lobby.banned.contains(&key) {
+        Some(LeaveReason::Banned)
+    } else if members.len() > lobby.info.max_players {
+        Some(LeaveReason::Full)
+    } else {
+        None
+    };
+
+    if let Some(reason) = reason {
+        for channel in q.iter() {
+            send_kick(channel, &lobby.info.id, &key, reason);
+        }
+    }
+}
+
+fn on_lobby_leave(
+    _: In<(String, PresenceState, PresenceState)>,
+    systems: Res<LobbySystems>,
+    q: Query<&Channel, With<LobbyChannel>>,
+) {
+    for channel in q.iter() {
+        let _ = channel.presence_state(systems.on_lobby_state);
+    }
+}
+
+fn on_lobby_state(
+    In(state): In<PresenceState>,
+    mut lobby: ResMut<CurrentLobby>,
+    mut evw: EventWriter<LobbyEvent>,
+    mut exits: EventWriter<LobbyExit>,
+    player: Res<LobbyPlayer>,
+    mut directory_channel: Query<&mut PrescenceTrack, With<LobbyDirectoryChannel>>,
+) {
+    let Some(lobby) = lobby.0.as_mut() else {
+        return;
+    };
+
+    let members: BTreeMap<String, bool> = state
+        .0
+        .iter()
+        .map(|(key, metas)| {
+            let ready = metas
+                .values()
+                .any(|meta| meta.get("ready") == Some(&Value::Bool(true)));
+
+            (key.clone(), ready)
+        })
+        .collect();
+
+    if members.contains_key(&player.0) && !members.contains_key(&lobby.info.host) {
+        exits.send(LobbyExit(Some(LeaveReason::Closed)));
+        return;
+    }
+
+    let was_ready = lobby.all_ready();
+
+    for key in lobby.members.keys() {
+        if !members.contains_key(key) {
+            evw.send(LobbyEvent::MemberLeft(key.clone()));
+        }
+    }
+
+    for (key, ready) in members.iter() {
+        match lobby.members.get(key) {
+            None => {
+                evw.send(LobbyEvent::MemberJoined(key.clone()));
+            }
+            Some(was) if was != ready => {
+                evw.send(LobbyEvent::ReadyChanged {
+                    player: key.clone(),
+                    ready: *ready,
+                });
+            }
+            Some(_) => {}
+        }
+    }
+
+    lobby.members = members;
+    lobby.info.players = lobby.members.len();
+
+    if !was_ready && lobby.all_ready() {
+        evw.send(LobbyEvent::AllReady);
+    }
+
+    if lobby.is_host(&player) {
+        for mut track in directory_channel.iter_mut() {
+            track.payload = to_payload(&lobby.info);
+        }
+    }
+}
+
+/// True if `payload` was sent in the current lobby, rather than one left since
+fn is_current_lobby(payload: &HashMap<String, Value>, lobby: &CurrentLobby) -> bool {
+    lobby.0.as_ref().is_some_and(|lobby| {
+        payload.get("lobby").and_then(Value::as_str) == Some(lobby.info.id.as_str())
+    })
+}
+
+fn on_lobby_kick(
+    In(payload): In<HashMap<String, Value>>,
+    player: Res<LobbyPlayer>,
+    lobby: Res<CurrentLobby>,
+    mut exits: EventWriter<LobbyExit>,
+) {
+    if !is_current_lobby(&payload, &lobby)
+        || payload.get("player").and_then(Value::as_str) != Some(player.0.as_str())
+    {
+        return;
+    }
+
+    let reason = match payload.get("reason").and_then(Value::as_str) {
+        Some("banned") => LeaveReason::Banned,
+        Some("full") => LeaveReason::Full,
+        _ => LeaveReason::Kicked,
+    };
+
+    exits.send(LobbyExit(Some(reason)));
+}
+
+fn on_lobby_start(
+    In(payload): In<HashMap<String, Value>>,
+    lobby: Res<CurrentLobby>,
+    mut evw: EventWriter<LobbyEvent>,
+    mut exits: EventWriter<LobbyExit>,
+) {
+    if !is_current_lobby(&payload, &lobby) {
+        return;
+    }
+
+    let Some(topic) = payload.get("topic").and_then(Value::as_str) else {
+        return;
+    };
+
+    evw.send(LobbyEvent::MatchStarted {
+        topic: topic.into(),
+    });
+    exits.send(LobbyExit(None));
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::ecs::system::RunSystemOnce;
+    use serde_json::json;
+
+    use super::*;
+
+    fn app() -> App {
+        let mut app = App::new();
+        app.insert_resource(LobbyPlayer("me".into()))
+            .insert_resource(CurrentLobby(Some(Lobby {
+                info: LobbyInfo {
+                    id: "current".into(),
+                    name: "lobby".into(),
+                    host: "host".into(),
+                    players: 2,
+                    max_players: 4,
+                },
+                members: BTreeMap::new(),
+                banned: HashSet::new(),
+            })))
+            .add_event::<LobbyEvent>()
+            .add_event::<LobbyExit>();
+        app
+    }
+
+    fn payload(value: Value) -> HashMap<String, Value> {
+        let Value::Object(fields) = value else {
+            unreachable!();
+        };
+
+        fields.into_iter().collect()
+    }
+
+    fn exits(app: &App) -> Vec<Option<LeaveReason>> {
+        let events = app.world().resource::<Events<LobbyExit>>();
+        events
+            .iter_current_update_events()
+            .map(|exit| exit.0)
+            .collect()
+    }
+
+    #[test]
+    fn kicks_only_apply_to_this_player_in_the_current_lobby() {
+        let mut app = app();
+
+        for kick in [
+            json!({ "lobby": "current", "player": "other", "reason": "kicked" }),
+            json!({ "lobby": "previous", "player": "me", "reason": "kicked" }),
+            json!({ "player": "me", "reason": "kicked" }),
+        ] {
+            app.world_mut()
+                .run_system_once_with(payload(kick), on_lobby_kick)
+                .unwrap();
+        }
+
+        assert!(exits(&app).is_empty());
+
+        let kick = json!({ "lobby": "current", "player": "me", "reason": "banned" });
+        app.world_mut()
+            .run_system_once_with(payload(kick), on_lobby_kick)
+            .unwrap();
+
+        assert_eq!(exits(&app), vec![Some(LeaveReason::Banned)]);
+    }
+
+    #[test]
+    fn match_starts_only_from_the_current_lobby() {
+        let mut app = app();
+
+        let start = json!({ "lobby": "previous", "topic": "game:match:1" });
+        app.world_mut()
+            .run_system_once_with(payload(start), on_lobby_start)
+            .unwrap();
+
+        assert!(exits(&app).is_empty());
+
+        let start = json!({ "lobby": "current", "topic": "game:match:2" });
+        app.world_mut()
+            .run_system_once_with(payload(start), on_lobby_start)
+            .unwrap();
+
+        assert_eq!(exits(&app), vec![None]);
+        assert_eq!(
+            app.world()
+                .resource::<Events<LobbyEvent>>()
+                .iter_current_update_events()
+                .cloned()
+                .collect::<Vec<_>>(),
+            vec![LobbyEvent::MatchStarted {
+                topic: "game:match:2".into()
+            }]
+        );
+    }
+}
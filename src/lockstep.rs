@@ -0,0 +1,783 @@
+use std::{
+    any::Any,
+    collections::{BTreeMap, HashMap, VecDeque},
+    marker::PhantomData,
+    sync::Arc,
+};
+
+use bevy::{ecs::schedule::ScheduleLabel, prelude::*};
+use serde::{de::DeserializeOwned, Serialize};
+use serde_json::Value;
+use uuid::Uuid;
+
+use crate::{
+    channel::ChannelBuilder,
+    client_ready,
+    message::payload::{BroadcastConfig, BroadcastPayload, PresenceConfig},
+    presence::{PrescenceTrack, PresenceEvent, PresenceState},
+    BevyChannelBuilder, BuildChannel, Channel, Client,
+};
+
+const INPUT_EVENT: &str = "lockstep_input";
+
+/// Per frame input broadcast by each peer
+pub trait LockstepInput:
+    Serialize + DeserializeOwned + Clone + PartialEq + Default + Send + Sync + 'static
+{
+}
+
+impl<T> LockstepInput for T where
+    T: Serialize + DeserializeOwned + Clone + PartialEq + Default + Send + Sync + 'static
+{
+}
+
+/// Schedule run once per lockstep frame, read the frame's inputs from [LockstepSession]
+#[derive(ScheduleLabel, Debug, Clone, PartialEq, Eq, Hash)]
+pub struct LockstepUpdate;
+
+type SaveFn = dyn Fn(&mut World) -> Box<dyn Any + Send + Sync> + Send + Sync;
+type LoadFn = dyn Fn(&mut World, &(dyn Any + Send + Sync)) + Send + Sync;
+
+#[derive(Clone)]
+struct RollbackHooks {
+    max_frames: u32,
+    save: Arc<SaveFn>,
+    load: Arc<LoadFn>,
+}
+
+/// Deterministic lockstep over broadcasts on a dedicated channel.
+///
+/// Every [FixedUpdate] the local input set with [LockstepSession::set_input] is broadcast for
+/// the frame `input_delay` ahead, along with the previous few so a lost broadcast is covered by
+/// the next, and [LockstepUpdate] is run for the next frame once inputs for it have arrived
+/// from every peer. Peers are the channel's presence members. A member is expected every frame
+/// from the first it sent input for until it leaves, and no frame is confirmed while a member
+/// hasn't sent any input yet.
+///
+/// A session that gets input for a later frame before it simulated anything joined late, and
+/// skips ahead to the frame after it. The game state at that frame isn't transferred, late
+/// joiners need to get it some other way.
+///
+/// One broadcast is sent per [FixedUpdate], 64 a second by default, well above the client's
+/// default limit of 10 events a second. Raise it with
+/// [crate::RealtimePlugin::max_events_per_second] and the project's realtime quota, or lower the
+/// [Time<Fixed>] rate.
+///
+/// With [Self::rollback], missing remote inputs are predicted by repeating each peer's last
+/// one, so the simulation can run up to `max_frames` ahead of the last complete frame. When
+/// an input arrives that differs from its prediction, the state saved before that frame is
+/// loaded and the frames since are simulated again. Requires an unnamed [crate::RealtimePlugin].
+pub struct LockstepPlugin<I: LockstepInput> {
+    topic: String,
+    input_delay: u32,
+    redundancy: usize,
+    rollback: Option<RollbackHooks>,
+    _input: PhantomData<fn() -> I>,
+}
+
+impl<I: LockstepInput> LockstepPlugin<I> {
+    pub fn new(topic: impl Into<String>) -> Self {
+        Self {
+            topic: topic.into(),
+            input_delay: 2,
+            redundancy: 3,
+            rollback: None,
+            _input: PhantomData,
+        }
+    }
+
+    /// Set how many frames ahead local input is scheduled, hiding that much latency.
+    /// Default: 2
+    pub fn input_delay(mut self, input_delay: u32) -> Self {
+        self.input_delay = input_delay;
+        self
+    }
+
+    /// Set how many of the latest local inputs each broadcast carries.
+    /// Default: 3
+    /// Minimum: 1
+    pub fn redundancy(mut self, redundancy: usize) -> Self {
+        self.redundancy = redundancy.max(1);
+        self
+    }
+
+    /// Enable rollback, predicting up to `max_frames` ahead. `save` captures the simulation
+    /// state before a frame and `load` restores it.
+    /// Default: disabled
+    pub fn rollback<S: Send + Sync + 'static>(
+        mut self,
+        max_frames: u32,
+        save: impl Fn(&mut World) -> S + Send + Sync + 'static,
+        load: impl Fn(&mut World, &S) + Send + Sync + 'static,
+    ) -> Self {
+        self.rollback = Some(RollbackHooks {
+            max_frames,
+            save: Arc::new(move |world| Box::new(save(world))),
+            load: Arc::new(move |world, state| {
+                if let Some(state) = state.downcast_ref::<S>() {
+                    load(world, state);
+                }
+            }),
+        });
+        self
+    }
+}
+
+impl<I: LockstepInput> Plugin for LockstepPlugin<I> {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(LockstepSession::<I>::new(
+            Uuid::new_v4().to_string(),
+            self.input_delay,
+            self.redundancy,
+            self.rollback.clone(),
+        ))
+        .insert_resource(LockstepTopic(self.topic.clone()))
+        .init_schedule(LockstepUpdate)
+        .add_systems(Startup, setup_lockstep_channel::<I>)
+        .add_systems(
+            FixedUpdate,
+            (
+                send_lockstep_input::<I>.run_if(client_ready),
+                advance_lockstep::<I>,
+            )
+                .chain(),
+        );
+    }
+}
+
+/// State of a [LockstepPlugin] session
+#[derive(Resource)]
+pub struct LockstepSession<I: LockstepInput> {
+    peer_id: String,
+    /// Next frame to simulate
+    frame: u32,
+    /// First frame without inputs from every peer
+    confirmed: u32,
+    input_delay: u32,
+    redundancy: usize,
+    next_local_frame: u32,
+    local_input: Option<I>,
+    last_local_input: I,
+    /// Set once this peer shows up in presence, nothing is sent before
+    synced: bool,
+    /// Presence members, with the first frame each sent input for once known
+    peers: BTreeMap<String, Option<u32>>,
+    /// Latest local inputs, resent with every broadcast
+    sent: VecDeque<(u32, I)>,
+    inputs: BTreeMap<u32, BTreeMap<String, I>>,
+    last_inputs: HashMap<String, I>,
+    frame_inputs: BTreeMap<String, I>,
+    predicted: BTreeMap<u32, BTreeMap<String, I>>,
+    rollback: Option<RollbackHooks>,
+    snapshots: BTreeMap<u32, Box<dyn Any + Send + Sync>>,
+    rollback_to: Option<u32>,
+}
+
+impl<I: LockstepInput> LockstepSession<I> {
+    /// Presence key identifying this peer
+    pub fn peer_id(&self) -> &str {
+        &self.peer_id
+    }
+
+    /// Frame being simulated while [LockstepUpdate] runs, otherwise the next one
+    pub fn frame(&self) -> u32 {
+        self.frame
+    }
+
+    /// First frame that doesn't have inputs from every peer yet
+    pub fn confirmed_frame(&self) -> u32 {
+        self.confirmed
+    }
+
+    /// Inputs of the frame being simulated by peer, in a deterministic order
+    pub fn inputs(&self) -> &BTreeMap<String, I> {
+        &self.frame_inputs
+    }
+
+    /// True if the frame being simulated uses predicted inputs
+    pub fn is_predicting(&self) -> bool {
+        self.frame >= self.confirmed
+    }
+
+    /// Peers taking part in the session, including this one
+    pub fn peers(&self) -> impl Iterator<Item = &str> {
+        self.peers.keys().map(String::as_str)
+    }
+
+    /// Set the input sent for the next frame. Until this is called again the last input is
+    /// repeated.
+    pub fn set_input(&mut self, input: I) {
+        self.local_input = Some(input);
+    }
+
+    fn new(
+        peer_id: String,
+        input_delay: u32,
+        redundancy: usize,
+        rollback: Option<RollbackHooks>,
+    ) -> Self {
+        let mut peers = BTreeMap::new();
+        peers.insert(peer_id.clone(), Some(0));
+
+        Self {
+            peer_id,
+            frame: 0,
+            confirmed: 0,
+            input_delay,
+            redundancy,
+            next_local_frame: 0,
+            local_input: None,
+            last_local_input: I::default(),
+            synced: false,
+            peers,
+            sent: VecDeque::new(),
+            inputs: BTreeMap::new(),
+            last_inputs: HashMap::new(),
+            frame_inputs: BTreeMap::new(),
+            predicted: BTreeMap::new(),
+            rollback,
+            snapshots: BTreeMap::new(),
+            rollback_to: None,
+        }
+    }
+
+    fn add_input(&mut self, peer: String, frame: u32, input: I) {
+        let remote = peer != self.peer_id;
+
+        // Nothing simulated yet and the session is already past our first frame, we're late
+        if remote && self.frame == 0 && frame >= self.next_local_frame + self.input_delay {
+            self.skip_to(frame + 1);
+        }
+
+        // A late starter's inputs only count from the first unconfirmed frame
+        if let Some(first @ None) = self.peers.get_mut(&peer) {
+            *first = Some(frame.max(self.confirmed));
+        }
+
+        // Confirmed frames can't change, and resent inputs are already known
+        if frame < self.confirmed
+            || self
+                .inputs
+                .get(&frame)
+                .is_some_and(|inputs| inputs.contains_key(&peer))
+        {
+            return;
+        }
+
+        if frame < self.frame
+            && self
+                .predicted
+                .get(&frame)
+                .and_then(|predicted| predicted.get(&peer))
+                != Some(&input)
+        {
+            // Predicted wrong, or simulated before this peer started
+            self.rollback_to = Some(self.rollback_to.map_or(frame, |to| to.min(frame)));
+        }
+
+        let newest = self
+            .inputs
+            .range(frame + 1..)
+            .all(|(_, inputs)| !inputs.contains_key(&peer));
+
+        if newest {
+            self.last_inputs.insert(peer.clone(), input.clone());
+        }
+
+        self.inputs.entry(frame).or_default().insert(peer, input);
+        self.update_confirmed();
+    }
+
+    /// Schedules local input up to `input_delay` frames ahead, returning true if any was
+    fn schedule_local_inputs(&mut self) -> bool {
+        if !self.synced {
+            return false;
+        }
+
+        let start = self.next_local_frame;
+
+        while self.next_local_frame <= self.frame + self.input_delay {
+            let frame = self.next_local_frame;
+
+            // The first frames are played before anyone could have input, keep them identical
+            let input = if frame < self.input_delay {
+                I::default()
+            } else if let Some(input) = self.local_input.take() {
+                self.last_local_input = input.clone();
+                input
+            } else {
+                self.last_local_input.clone()
+            };
+
+            self.sent.push_back((frame, input.clone()));
+            self.next_local_frame += 1;
+            self.add_input(self.peer_id.clone(), frame, input);
+        }
+
+        while self.sent.len() > self.redundancy {
+            self.sent.pop_front();
+        }
+
+        self.next_local_frame != start
+    }
+
+    /// Starts the session at `frame`, for a peer joining a session in progress
+    fn skip_to(&mut self, frame: u32) {
+        debug!("Joined lockstep session late, skipping to frame {}", frame);
+
+        self.frame = frame;
+        self.confirmed = frame;
+        self.next_local_frame = frame;
+        self.peers.insert(self.peer_id.clone(), Some(frame));
+        self.sent.clear();
+        self.prune();
+    }
+
+    fn add_peer(&mut self, peer: String) {
+        if peer == self.peer_id {
+            self.synced = true;
+            return;
+        }
+
+        if self.peers.contains_key(&peer) {
+            return;
+        }
+
+        // Input may have arrived before the presence join
+        let first = self
+            .inputs
+            .iter()
+            .find(|(_, inputs)| inputs.contains_key(&peer))
+            .map(|(frame, _)| (*frame).max(self.confirmed));
+
+        self.peers.insert(peer, first);
+    }
+
+    fn remove_peer(&mut self, peer: &str) {
+        if peer == self.peer_id {
+            return;
+        }
+
+        self.peers.remove(peer);
+        self.last_inputs.remove(peer);
+        self.update_confirmed();
+    }
+
+    /// Replaces the peers with the current presence members
+    fn sync_peers(&mut self, members: Vec<String>) {
+        let left: Vec<String> = self
+            .peers
+            .keys()
+            .filter(|peer| !members.contains(peer))
+            .cloned()
+            .collect();
+
+        for peer in left {
+            self.remove_peer(&peer);
+        }
+
+        for peer in members {
+            self.add_peer(peer);
+        }
+    }
+
+    fn is_complete(&self, frame: u32) -> bool {
+        let Some(inputs) = self.inputs.get(&frame) else {
+            return false;
+        };
+
+        self.peers.iter().all(|(peer, first)| match first {
+            Some(first) => *first > frame || inputs.contains_key(peer),
+            // Whichever frame it starts at has to wait for it
+            None => false,
+        })
+    }
+
+    fn update_confirmed(&mut self) {
+        while self.confirmed < self.next_local_frame && self.is_complete(self.confirmed) {
+            self.confirmed += 1;
+        }
+    }
+
+    fn can_advance(&self) -> bool {
+        if self.frame >= self.next_local_frame {
+            return false;
+        }
+
+        match &self.rollback {
+            Some(rollback) => self.frame < self.confirmed + rollback.max_frames.max(1),
+            None => self.frame < self.confirmed,
+        }
+    }
+
+    /// Fills [Self::frame_inputs] for the current frame, predicting missing inputs
+    fn prepare_frame(&mut self) {
+        let frame = self.frame;
+        let confirmed = self.inputs.get(&frame);
+        let mut predicted = BTreeMap::new();
+
+        self.frame_inputs = self
+            .peers
+            .iter()
+            .filter(|(_, first)| first.is_some_and(|first| first <= frame))
+            .map(|(peer, _)| {
+                let input = match confirmed.and_then(|inputs| inputs.get(peer)) {
+                    Some(input) => input.clone(),
+                    None => {
+                        let input = self.last_inputs.get(peer).cloned().unwrap_or_default();
+                        predicted.insert(peer.clone(), input.clone());
+                        input
+                    }
+                };
+
+                (peer.clone(), input)
+            })
+            .collect();
+
+        if predicted.is_empty() {
+            self.predicted.remove(&frame);
+        } else {
+            self.predicted.insert(frame, predicted);
+        }
+    }
+
+    /// Drops inputs and snapshots that can no longer be rolled back to
+    fn prune(&mut self) {
+        let horizon = self.confirmed.min(self.frame);
+
+        self.inputs = self.inputs.split_off(&horizon);
+        self.predicted = self.predicted.split_off(&horizon);
+        self.snapshots = self.snapshots.split_off(&horizon);
+    }
+}
+
+#[derive(Resource, Deref)]
+struct LockstepTopic(String);
+
+/// Marks the channel used by [LockstepPlugin]
+#[derive(Component)]
+pub struct LockstepChannel;
+
+fn setup_lockstep_channel<I: LockstepInput>(world: &mut World) {
+    let build_channel = world.register_system(build_lockstep_channel::<I>);
+    let client = world.resource::<Client>();
+
+    if client.channel(build_channel).is_err() {
+        error!("Failed to create lockstep channel");
+    }
+}
+
+fn build_lockstep_channel<I: LockstepInput>(
+    mut channel_builder: In<ChannelBuilder>,
+    mut commands: Commands,
+    topic: Res<LockstepTopic>,
+    session: Res<LockstepSession<I>>,
+) {
+    let on_input = commands.register_system(on_lockstep_input::<I>);
+    let on_sync = commands.register_system(on_lockstep_sync::<I>);
+    let on_join = commands.register_system(on_lockstep_join::<I>);
+    let on_leave = commands.register_system(on_lockstep_leave::<I>);
+
+    channel_builder
+        .topic(topic.as_str())
+        .set_broadcast_config(BroadcastConfig {
+            broadcast_self: false,
+            ack: false,
+        })
+        .set_presence_config(PresenceConfig {
+            key: Some(session.peer_id.clone()),
+        })
+        .on_broadcast(INPUT_EVENT, on_input)
+        .on_presence(PresenceEvent::Sync, on_sync)
+        .on_presence(PresenceEvent::Join, on_join)
+        .on_presence(PresenceEvent::Leave, on_leave);
+
+    commands.spawn((
+        BevyChannelBuilder(channel_builder.0),
+        BuildChannel,
+        LockstepChannel,
+        PrescenceTrack {
+            payload: HashMap::new(),
+        },
+    ));
+}
+
+fn send_lockstep_input<I: LockstepInput>(
+    mut session: ResMut<LockstepSession<I>>,
+    q: Query<&Channel, With<LockstepChannel>>,
+) {
+    if !session.schedule_local_inputs() {
+        return;
+    }
+
+    let inputs: Vec<Value> = session
+        .sent
+        .iter()
+        .filter_map(|(frame, input)| {
+            let Ok(input) = serde_json::to_value(input) else {
+                error!("Failed to serialize lockstep input");
+                return None;
+            };
+
+            let mut value = serde_json::Map::new();
+            value.insert("frame".into(), (*frame).into());
+            value.insert("input".into(), input);
+            Some(value.into())
+        })
+        .collect();
+
+    let mut payload = HashMap::new();
+    payload.insert("peer".into(), session.peer_id.clone().into());
+    payload.insert("inputs".into(), inputs.into());
+
+    for channel in q.iter() {
+        let _ = channel.broadcast(BroadcastPayload {
+            event: INPUT_EVENT.into(),
+            payload: payload.clone(),
+            ..Default::default()
+        });
+    }
+}
+
+fn advance_lockstep<I: LockstepInput>(world: &mut World) {
+    let mut session = world.resource_mut::<LockstepSession<I>>();
+    let hooks = session.rollback.clone();
+    let rollback_to = session.rollback_to.take();
+    let end = session.frame + 1;
+
+    if let (Some(hooks), Some(to)) = (&hooks, rollback_to) {
+        let snapshot = session.snapshots.split_off(&to).remove(&to);
+
+        match snapshot {
+            Some(snapshot) => {
+                session.frame = to;
+                (hooks.load)(world, snapshot.as_ref());
+            }
+            None => warn!(
+                "Missing lockstep snapshot for frame {}, can't roll back",
+                to
+            ),
+        }
+    }
+
+    loop {
+        let mut session = world.resource_mut::<LockstepSession<I>>();
+
+        if session.frame >= end || !session.can_advance() {
+            break;
+        }
+
+        session.prepare_frame();
+
+        if let Some(hooks) = &hooks {
+            let snapshot = (hooks.save)(world);
+            let mut session = world.resource_mut::<LockstepSession<I>>();
+            let frame = session.frame;
+            session.snapshots.insert(frame, snapshot);
+        }
+
+        world.run_schedule(LockstepUpdate);
+        world.resource_mut::<LockstepSession<I>>().frame += 1;
+    }
+
+    world.resource_mut::<LockstepSession<I>>().prune();
+}
+
+fn on_lockstep_input<I: LockstepInput>(
+    In(payload): In<HashMap<String, Value>>,
+    mut session: ResMut<LockstepSession<I>>,
+) {
+    let (Some(peer), Some(Value::Array(inputs))) = (
+        payload.get("peer").and_then(Value::as_str),
+        payload.get("inputs"),
+    ) else {
+        return;
+    };
+
+    for value in inputs {
+        let (Some(frame), Some(input)) = (
+            value.get("frame").and_then(Value::as_u64),
+            value.get("input"),
+        ) else {
+            continue;
+        };
+
+        match serde_json::from_value::<I>(input.clone()) {
+            Ok(input) => session.add_input(peer.into(), frame as u32, input),
+            Err(e) => debug!("Malformed lockstep input: {}", e),
+        }
+    }
+}
+
+fn on_lockstep_sync<I: LockstepInput>(
+    In((_, _, state)): In<(String, PresenceState, PresenceState)>,
+    mut session: ResMut<LockstepSession<I>>,
+) {
+    session.sync_peers(state.0.into_keys().collect());
+}
+
+fn on_lockstep_join<I: LockstepInput>(
+    In((key, _, _)): In<(String, PresenceState, PresenceState)>,
+    mut session: ResMut<LockstepSession<I>>,
+) {
+    session.add_peer(key);
+}
+
+fn on_lockstep_leave<I: LockstepInput>(
+    In((key, _, _)): In<(String, PresenceState, PresenceState)>,
+    mut session: ResMut<LockstepSession<I>>,
+) {
+    session.remove_peer(&key);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn session(peer: &str, members: &[&str]) -> LockstepSession<u8> {
+        let mut session = LockstepSession::new(peer.into(), 2, 3, None);
+        session.sync_peers(members.iter().map(|member| member.to_string()).collect());
+        session
+    }
+
+    /// Simulates every frame that can be
+    fn advance(session: &mut LockstepSession<u8>) {
+        while session.can_advance() {
+            session.prepare_frame();
+            session.frame += 1;
+        }
+
+        session.prune();
+    }
+
+    #[test]
+    fn nothing_is_sent_before_presence_sync() {
+        let mut session = LockstepSession::<u8>::new("a".into(), 2, 3, None);
+
+        assert!(!session.schedule_local_inputs());
+
+        session.sync_peers(vec!["a".into()]);
+
+        assert!(session.schedule_local_inputs());
+        assert_eq!(session.next_local_frame, 3);
+    }
+
+    #[test]
+    fn frames_wait_for_every_presence_member() {
+        let mut a = session("a", &["a", "b"]);
+
+        a.schedule_local_inputs();
+        advance(&mut a);
+
+        // b hasn't sent anything, so no frame can be confirmed
+        assert_eq!(a.confirmed_frame(), 0);
+        assert_eq!(a.frame(), 0);
+
+        a.add_input("b".into(), 0, 0);
+        a.add_input("b".into(), 1, 0);
+        advance(&mut a);
+
+        assert_eq!(a.confirmed_frame(), 2);
+        assert_eq!(a.frame(), 2);
+    }
+
+    #[test]
+    fn leaving_members_stop_blocking() {
+        let mut a = session("a", &["a", "b"]);
+
+        a.schedule_local_inputs();
+        a.remove_peer("b");
+        advance(&mut a);
+
+        assert_eq!(a.frame(), 3);
+        assert_eq!(a.peers().collect::<Vec<_>>(), vec!["a"]);
+    }
+
+    #[test]
+    fn late_starters_count_from_the_first_unconfirmed_frame() {
+        let mut a = session("a", &["a"]);
+
+        for _ in 0..5 {
+            a.schedule_local_inputs();
+            advance(&mut a);
+        }
+
+        let confirmed = a.confirmed_frame();
+        assert!(confirmed > 0);
+
+        a.add_peer("b".into());
+        a.schedule_local_inputs();
+
+        // Too old to use, but b is now expected from the first unconfirmed frame
+        a.add_input("b".into(), 0, 1);
+        assert_eq!(a.peers.get("b"), Some(&Some(confirmed)));
+
+        advance(&mut a);
+        assert_eq!(a.frame(), confirmed);
+
+        a.add_input("b".into(), confirmed, 1);
+        advance(&mut a);
+
+        assert_eq!(a.frame(), confirmed + 1);
+        assert_eq!(a.inputs().get("b"), Some(&1));
+    }
+
+    #[test]
+    fn resent_inputs_are_ignored() {
+        let mut a = session("a", &["a", "b"]);
+        a.schedule_local_inputs();
+
+        // The broadcast with frame 0 was lost, the next one carries it again
+        for frame in [1, 2, 0, 1, 2, 3] {
+            a.add_input("b".into(), frame, 0);
+        }
+
+        advance(&mut a);
+
+        assert_eq!(a.frame(), 3);
+        assert_eq!(a.rollback_to, None);
+    }
+
+    #[test]
+    fn late_sessions_skip_ahead() {
+        let mut b = session("b", &["a", "b"]);
+        b.schedule_local_inputs();
+
+        b.add_input("a".into(), 100, 0);
+
+        assert_eq!(b.frame(), 101);
+        assert_eq!(b.confirmed_frame(), 101);
+        assert_eq!(b.next_local_frame, 101);
+        assert!(b.inputs.is_empty());
+
+        b.schedule_local_inputs();
+        b.add_input("a".into(), 101, 0);
+        advance(&mut b);
+
+        assert_eq!(b.frame(), 102);
+    }
+
+    #[test]
+    fn inputs_for_predicted_frames_roll_back() {
+        let mut a = LockstepPlugin::<u8>::new("game")
+            .rollback(8, |_| (), |_, _| {})
+            .rollback
+            .map(|hooks| LockstepSession::<u8>::new("a".into(), 2, 3, Some(hooks)))
+            .unwrap();
+        a.sync_peers(vec!["a".into(), "b".into()]);
+        a.add_input("b".into(), 0, 0);
+
+        a.schedule_local_inputs();
+        advance(&mut a);
+
+        assert_eq!(a.frame(), 3);
+        assert!(a.confirmed_frame() < 3);
+
+        // Matches the prediction
+        a.add_input("b".into(), 1, 0);
+        assert_eq!(a.rollback_to, None);
+
+        a.add_input("b".into(), 2, 5);
+        assert_eq!(a.rollback_to, Some(2));
+    }
+}
@@ -5,47 +5,43 @@ use super::{payload::Payload, realtime_message::RealtimeMessage};
 
 /// Incoming message filter for local callbacks
 ///```
-/// # use realtime_rs::message::*;  
-/// # use realtime_rs::sync::*;    
-/// # use realtime_rs::*;          
-/// # use realtime_rs::message::payload::*;  
-/// # use std::{collections::HashMap, env};
+/// # use bevy::{ecs::system::SystemId, prelude::*};
+/// # use bevy_realtime::{
+/// #     channel::ChannelBuilder,
+/// #     message::{payload::*, postgres_change_filter::PostgresChangeFilter},
+/// #     BevyChannelBuilder, BuildChannel,
+/// # };
 /// #
-/// # fn main() -> Result<(), ()> {
-/// #     let url = "http://127.0.0.1:54321";
-/// #     let anon_key = env::var("LOCAL_ANON_KEY").expect("No anon key!");
-/// #     let mut client = RealtimeClient::builder(url, anon_key).build();
-/// #     let _ = client.connect();
-///     let my_cdc_callback = move |msg: &_| {
-///         println!("Got message: {:?}", msg);
-///     };
+/// # #[derive(Resource, Deref)]
+/// # struct MyCdcCallback(SystemId<In<PostgresChangesPayload>>);
+/// #
+/// fn my_cdc_callback(msg: In<PostgresChangesPayload>) {
+///     println!("Got message: {:?}", *msg);
+/// }
+///
+/// fn build_channel(
+///     mut channel_builder: In<ChannelBuilder>,
+///     mut commands: Commands,
+///     callback: Res<MyCdcCallback>,
+/// ) {
+///     channel_builder.topic("topic").on_postgres_change(
+///         PostgresChangesEvent::All,
+///         PostgresChangeFilter {
+///             schema: "public".into(),
+///             table: Some("todos".into()),
+///             ..Default::default()
+///         },
+///         **callback,
+///     );
 ///
-///     let channel_id = client
-///         .channel("topic")
-///         .on_postgres_change(
-///             PostgresChangesEvent::All,
-///             PostgresChangeFilter {
-///                 schema: "public".into(),
-///                 table: Some("todos".into()),
-///                 ..Default::default()
-///             },
-///             my_cdc_callback,
-///         )
-///         .build(&mut client);
+///     commands.spawn((BevyChannelBuilder(channel_builder.0), BuildChannel));
+/// }
 /// #
-/// #     client.get_channel_mut(channel_id).unwrap().subscribe();
-/// #     loop {
-/// #         if client.get_status() == ConnectionState::Closed {
-/// #             break;
-/// #         }
-/// #         match client.next_message() {
-/// #             Ok(_topic) => return Ok(()),
-/// #             Err(NextMessageError::WouldBlock) => return Ok(()),
-/// #             Err(_e) => return Err(()),
-/// #         }
-/// #     }
-/// #     Err(())
-/// # }
+/// # let mut world = World::new();
+/// # let callback = world.register_system(my_cdc_callback);
+/// # world.insert_resource(MyCdcCallback(callback));
+/// # let _build_channel = world.register_system(build_channel);
+///```
 #[derive(Serialize, Deserialize, Debug, Default, Clone)]
 pub struct PostgresChangeFilter {
     pub schema: String,
@@ -0,0 +1,158 @@
+//! In-process stand-in for a Realtime server, for driving a [crate::client::Client] in tests.
+
+use std::{
+    io,
+    net::{SocketAddr, TcpListener, TcpStream},
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Arc,
+    },
+    thread::{self, sleep},
+    time::Duration,
+};
+
+use crossbeam::channel::{unbounded, Receiver, Sender, TryRecvError};
+use tungstenite::{accept, Error as TungsteniteError, Message, WebSocket};
+
+use crate::message::realtime_message::RealtimeMessage;
+
+/// Accepts websocket connections one at a time, handing every message the client sends to the
+/// test and writing whatever the test sends back. Shuts down once dropped.
+pub(crate) struct MockServer {
+    addr: SocketAddr,
+    received: Receiver<RealtimeMessage>,
+    outgoing: Sender<Message>,
+    stopped: Arc<AtomicBool>,
+    connections: Arc<AtomicUsize>,
+}
+
+impl MockServer {
+    pub(crate) fn start() -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        listener.set_nonblocking(true).unwrap();
+
+        let addr = listener.local_addr().unwrap();
+        let (received_tx, received) = unbounded();
+        let (outgoing, outgoing_rx) = unbounded();
+        let stopped = Arc::new(AtomicBool::new(false));
+        let thread_stopped = stopped.clone();
+        let connections = Arc::new(AtomicUsize::new(0));
+        let thread_connections = connections.clone();
+
+        thread::spawn(move || loop {
+            match listener.accept() {
+                Ok((stream, _)) => {
+                    thread_connections.fetch_add(1, Ordering::Relaxed);
+
+                    if !serve(stream, &received_tx, &outgoing_rx) {
+                        return;
+                    }
+                }
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => {
+                    if thread_stopped.load(Ordering::Relaxed) {
+                        return;
+                    }
+
+                    sleep(Duration::from_millis(1));
+                }
+                Err(_) => return,
+            }
+        });
+
+        Self {
+            addr,
+            received,
+            outgoing,
+            stopped,
+            connections,
+        }
+    }
+
+    /// Number of connections accepted so far, including the current one
+    pub(crate) fn connections(&self) -> usize {
+        self.connections.load(Ordering::Relaxed)
+    }
+
+    /// Address the server is listening on
+    pub(crate) fn addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    /// Endpoint to build the client with
+    pub(crate) fn endpoint(&self) -> String {
+        format!("http://{}", self.addr)
+    }
+
+    /// Sends `message` to the connected client
+    pub(crate) fn send(&self, message: RealtimeMessage) {
+        self.outgoing.send(message.into()).unwrap();
+    }
+
+    /// Returns the next message the client sent, if any
+    pub(crate) fn try_recv(&self) -> Option<RealtimeMessage> {
+        self.received.try_recv().ok()
+    }
+}
+
+impl Drop for MockServer {
+    fn drop(&mut self) {
+        self.stopped.store(true, Ordering::Relaxed);
+    }
+}
+
+/// Runs one connection until the client goes away. Returns false once the [MockServer] is
+/// dropped.
+fn serve(
+    stream: TcpStream,
+    received: &Sender<RealtimeMessage>,
+    outgoing: &Receiver<Message>,
+) -> bool {
+    stream.set_nonblocking(false).unwrap();
+
+    let Ok(mut socket) = accept(stream) else {
+        return true;
+    };
+
+    socket
+        .get_ref()
+        .set_read_timeout(Some(Duration::from_millis(1)))
+        .unwrap();
+
+    loop {
+        match socket.read() {
+            Ok(Message::Text(text)) => {
+                let Ok(message) = serde_json::from_str(&text) else {
+                    continue;
+                };
+
+                if received.send(message).is_err() {
+                    return false;
+                }
+            }
+            Ok(Message::Close(_)) => return true,
+            Ok(_) => {}
+            Err(TungsteniteError::Io(e))
+                if matches!(
+                    e.kind(),
+                    io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+                ) => {}
+            Err(_) => return true,
+        }
+
+        if !write_outgoing(&mut socket, outgoing) {
+            return false;
+        }
+    }
+}
+
+fn write_outgoing(socket: &mut WebSocket<TcpStream>, outgoing: &Receiver<Message>) -> bool {
+    loop {
+        match outgoing.try_recv() {
+            Ok(message) => {
+                let _ = socket.send(message);
+            }
+            Err(TryRecvError::Empty) => return true,
+            Err(TryRecvError::Disconnected) => return false,
+        }
+    }
+}
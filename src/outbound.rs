@@ -0,0 +1,789 @@
+use std::{
+    collections::VecDeque,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+
+use bevy::{ecs::event::Event, log::debug};
+use crossbeam::channel::{unbounded, Receiver, SendError, Sender, TryRecvError};
+
+use crate::message::{
+    payload::Payload,
+    realtime_message::{MessageEvent, RealtimeMessage},
+};
+
+/// How an outbound message is treated when the client is throttled
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum SendPolicy {
+    /// Queued until it can be sent
+    #[default]
+    Reliable,
+    /// Discarded if it can't be sent straight away
+    Droppable,
+    /// Only the newest queued message per topic and event is kept, older ones are replaced.
+    /// Useful for state such as positions where stale updates are worthless.
+    LatestOnly,
+}
+
+/// Outbound lanes, drained highest priority first
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum MessagePriority {
+    /// Heartbeats, joins, leaves and access token updates
+    Control,
+    /// Presence track and untrack
+    Presence,
+    /// Broadcasts sent with [SendPolicy::Reliable]
+    Reliable,
+    /// Broadcasts sent with [SendPolicy::Droppable] or [SendPolicy::LatestOnly]
+    Droppable,
+}
+
+/// What happens when a message is sent to a full queue
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum OverflowPolicy {
+    /// Discard the oldest queued message to make room
+    #[default]
+    DropOldest,
+    /// Discard the message being sent
+    DropNewest,
+    /// Return the message being sent to the caller as an error
+    Reject,
+}
+
+/// Capacity and [OverflowPolicy] of a bounded queue
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct QueueBound {
+    /// Max queued messages. Minimum: 1
+    pub capacity: usize,
+    pub overflow: OverflowPolicy,
+}
+
+impl QueueBound {
+    pub fn new(capacity: usize, overflow: OverflowPolicy) -> Self {
+        Self {
+            capacity: capacity.max(1),
+            overflow,
+        }
+    }
+}
+
+/// Sent when the outbound queue fills up or messages are dropped from it, and again once it
+/// has drained to half capacity.
+///
+/// Only sent when the outbound queue is bounded, see
+/// [crate::client::ClientBuilder::outbound_queue_bound].
+#[derive(Event, Debug, Clone, PartialEq, Eq)]
+pub struct OutboundBackpressure {
+    /// Name of the client, see [crate::client::ClientBuilder::name]
+    pub client: Option<String>,
+    /// Messages waiting to be sent
+    pub depth: usize,
+    pub capacity: usize,
+    /// Messages discarded by the [OverflowPolicy] since the last event
+    pub dropped: usize,
+    /// True while the queue is full. Producers should hold off until this is false again.
+    pub saturated: bool,
+}
+
+/// Messages sent through a [BoundedSender]
+pub(crate) trait BoundedMessage {
+    /// Whether the message counts against the [QueueBound]. Control messages such as joins,
+    /// leaves and presence updates don't, so they're never discarded or rejected.
+    fn bounded(&self) -> bool;
+}
+
+#[derive(Default)]
+struct BoundState {
+    // Bounded messages in the channel, including ones still to be discarded
+    queued: AtomicUsize,
+    // Oldest bounded messages the receiver discards under [OverflowPolicy::DropOldest]
+    evict: AtomicUsize,
+}
+
+impl BoundState {
+    fn live(&self) -> usize {
+        self.queued
+            .load(Ordering::Acquire)
+            .saturating_sub(self.evict.load(Ordering::Acquire))
+    }
+}
+
+fn decrement(counter: &AtomicUsize) -> bool {
+    counter
+        .fetch_update(Ordering::AcqRel, Ordering::Acquire, |n| n.checked_sub(1))
+        .is_ok()
+}
+
+/// Sending half of a channel, optionally bounded by a [QueueBound].
+///
+/// The underlying channel is unbounded and the bound is only applied to
+/// [BoundedMessage::bounded] messages, so order is kept and control messages always get through.
+/// Concurrent senders may briefly overshoot the capacity.
+pub(crate) struct BoundedSender<T> {
+    tx: Sender<T>,
+    bound: Option<QueueBound>,
+    state: Arc<BoundState>,
+}
+
+impl<T> Clone for BoundedSender<T> {
+    fn clone(&self) -> Self {
+        Self {
+            tx: self.tx.clone(),
+            bound: self.bound,
+            state: self.state.clone(),
+        }
+    }
+}
+
+/// Receiving half of a [BoundedSender]'s channel
+pub(crate) struct BoundedReceiver<T> {
+    rx: Receiver<T>,
+    state: Arc<BoundState>,
+}
+
+/// Creates a channel, unbounded if `bound` is [None]
+pub(crate) fn bounded_channel<T>(
+    bound: Option<QueueBound>,
+) -> (BoundedSender<T>, BoundedReceiver<T>) {
+    let (tx, rx) = unbounded();
+    let state = Arc::new(BoundState::default());
+
+    (
+        BoundedSender {
+            tx,
+            bound,
+            state: state.clone(),
+        },
+        BoundedReceiver { rx, state },
+    )
+}
+
+impl<T: BoundedMessage> BoundedSender<T> {
+    /// Sends `item`, applying the [OverflowPolicy] if it's bounded and the channel is full.
+    ///
+    /// Messages discarded by [OverflowPolicy::DropNewest] are reported as sent.
+    pub(crate) fn send(&self, item: T) -> Result<(), SendError<T>> {
+        if let (Some(bound), true) = (self.bound, item.bounded()) {
+            if self.state.live() >= bound.capacity {
+                match bound.overflow {
+                    OverflowPolicy::DropOldest => {
+                        debug!("Queue full, dropping oldest message");
+                        self.state.evict.fetch_add(1, Ordering::AcqRel);
+                    }
+                    OverflowPolicy::DropNewest => {
+                        debug!("Queue full, dropping newest message");
+                        return Ok(());
+                    }
+                    OverflowPolicy::Reject => return Err(SendError(item)),
+                }
+            }
+
+            self.state.queued.fetch_add(1, Ordering::AcqRel);
+        }
+
+        self.tx.send(item)
+    }
+
+    /// Raw sender for the channel, skipping the bound
+    pub(crate) fn unbounded(&self) -> Sender<T> {
+        self.tx.clone()
+    }
+
+    /// Number of messages waiting in the channel
+    pub(crate) fn len(&self) -> usize {
+        self.tx
+            .len()
+            .saturating_sub(self.state.evict.load(Ordering::Acquire))
+    }
+}
+
+impl<T: BoundedMessage> BoundedReceiver<T> {
+    /// Receives the next message, skipping any discarded under [OverflowPolicy::DropOldest]
+    pub(crate) fn try_recv(&self) -> Result<T, TryRecvError> {
+        loop {
+            let item = self.rx.try_recv()?;
+
+            if item.bounded() {
+                decrement(&self.state.queued);
+
+                if decrement(&self.state.evict) {
+                    continue;
+                }
+            }
+
+            return Ok(item);
+        }
+    }
+}
+
+/// Shared count of messages waiting to be written to the socket
+#[derive(Debug, Clone, Default)]
+pub(crate) struct OutboundDepth(Arc<AtomicUsize>);
+
+impl OutboundDepth {
+    pub(crate) fn get(&self) -> usize {
+        self.0.load(Ordering::Relaxed)
+    }
+
+    pub(crate) fn set(&self, depth: usize) {
+        self.0.store(depth, Ordering::Relaxed)
+    }
+}
+
+/// A [RealtimeMessage] queued for sending along with its [SendPolicy]
+#[derive(Debug, Clone)]
+pub(crate) struct OutboundMessage {
+    pub message: RealtimeMessage,
+    pub policy: SendPolicy,
+    /// When the message was first queued, kept as it moves between queues
+    pub queued_at: Instant,
+}
+
+impl OutboundMessage {
+    pub(crate) fn new(message: RealtimeMessage, policy: SendPolicy) -> Self {
+        Self {
+            message,
+            policy,
+            queued_at: Instant::now(),
+        }
+    }
+
+    pub(crate) fn priority(&self) -> MessagePriority {
+        match self.message.event {
+            MessageEvent::Heartbeat
+            | MessageEvent::PhxJoin
+            | MessageEvent::PhxLeave
+            | MessageEvent::PhxClose
+            | MessageEvent::AccessToken => MessagePriority::Control,
+            MessageEvent::Presence | MessageEvent::Track | MessageEvent::Untrack => {
+                MessagePriority::Presence
+            }
+            _ => match self.policy {
+                SendPolicy::Reliable => MessagePriority::Reliable,
+                SendPolicy::Droppable | SendPolicy::LatestOnly => MessagePriority::Droppable,
+            },
+        }
+    }
+
+    /// Key used to coalesce [SendPolicy::LatestOnly] messages
+    fn coalesce_key(&self) -> (&str, Option<&str>) {
+        let event = match &self.message.payload {
+            Payload::Broadcast(payload) => Some(payload.event.as_str()),
+            _ => None,
+        };
+
+        (self.message.topic.as_str(), event)
+    }
+}
+
+impl From<RealtimeMessage> for OutboundMessage {
+    fn from(message: RealtimeMessage) -> Self {
+        Self::new(message, SendPolicy::Reliable)
+    }
+}
+
+/// Priority queue of messages waiting for the socket.
+///
+/// If bounded, the [QueueBound] applies to all lanes but [MessagePriority::Control], so
+/// heartbeats and joins are never discarded.
+#[derive(Default)]
+pub(crate) struct OutboundQueue {
+    lanes: [VecDeque<OutboundMessage>; 4],
+    bound: Option<QueueBound>,
+}
+
+impl OutboundQueue {
+    pub(crate) fn new(bound: Option<QueueBound>) -> Self {
+        Self {
+            lanes: Default::default(),
+            bound,
+        }
+    }
+
+    /// Queues a message, returning how many messages were discarded to respect the bound
+    pub(crate) fn push(&mut self, message: OutboundMessage) -> usize {
+        let priority = message.priority();
+
+        if message.policy == SendPolicy::LatestOnly {
+            let key = message.coalesce_key();
+
+            if let Some(index) = self.lanes[priority as usize]
+                .iter()
+                .position(|q| q.policy == SendPolicy::LatestOnly && q.coalesce_key() == key)
+            {
+                self.lanes[priority as usize][index] = message;
+                return 0;
+            }
+        }
+
+        let mut dropped = 0;
+
+        if let Some(bound) = self.bound {
+            if priority != MessagePriority::Control && self.bounded_len() >= bound.capacity {
+                // Evict the oldest message of the lowest priority lane, unless the new message
+                // is lower priority still. Reject has already been applied by the producer, so
+                // treat it as DropNewest.
+                let evict = match bound.overflow {
+                    OverflowPolicy::DropOldest => self.lanes[1..]
+                        .iter()
+                        .rposition(|lane| !lane.is_empty())
+                        .map(|index| index + 1)
+                        .filter(|&index| index >= priority as usize),
+                    OverflowPolicy::DropNewest | OverflowPolicy::Reject => None,
+                };
+
+                let Some(index) = evict else {
+                    return 1;
+                };
+
+                self.lanes[index].pop_front();
+                dropped = 1;
+            }
+        }
+
+        self.lanes[priority as usize].push_back(message);
+        dropped
+    }
+
+    /// Puts back a popped message that couldn't be written, ahead of the rest of its lane.
+    /// It was already counted against the bound, so nothing is discarded.
+    pub(crate) fn push_front(&mut self, message: OutboundMessage) {
+        self.lanes[message.priority() as usize].push_front(message);
+    }
+
+    /// Pops the oldest message from the highest priority non-empty lane
+    pub(crate) fn pop(&mut self) -> Option<OutboundMessage> {
+        self.lanes.iter_mut().find_map(|lane| lane.pop_front())
+    }
+
+    /// Discards queued [SendPolicy::Droppable] messages, returning how many were dropped
+    pub(crate) fn drop_droppable(&mut self) -> usize {
+        let lane = &mut self.lanes[MessagePriority::Droppable as usize];
+        let before = lane.len();
+        lane.retain(|m| m.policy != SendPolicy::Droppable);
+        before - lane.len()
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.lanes.iter().all(|lane| lane.is_empty())
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.lanes.iter().map(|lane| lane.len()).sum()
+    }
+
+    /// Removes and returns the queued messages for `topic`, leaving control messages in place
+    pub(crate) fn take_topic(&mut self, topic: &str) -> Vec<OutboundMessage> {
+        let mut taken = vec![];
+
+        for lane in &mut self.lanes[1..] {
+            let (matching, rest): (VecDeque<_>, VecDeque<_>) =
+                lane.drain(..).partition(|m| m.message.topic == topic);
+
+            *lane = rest;
+            taken.extend(matching);
+        }
+
+        taken
+    }
+
+    /// Queued messages counted against the bound
+    fn bounded_len(&self) -> usize {
+        self.lanes[1..].iter().map(|lane| lane.len()).sum()
+    }
+}
+
+/// Messages held by a channel while it isn't joined, replayed in order once it is.
+///
+/// [SendPolicy::Droppable] messages are discarded rather than buffered, and
+/// [SendPolicy::LatestOnly] messages replace older buffered ones with the same event. Age is
+/// measured from [OutboundMessage::queued_at].
+#[derive(Debug, Clone)]
+pub(crate) struct OfflineBuffer {
+    max_age: Duration,
+    messages: VecDeque<OutboundMessage>,
+}
+
+impl OfflineBuffer {
+    pub(crate) fn new(max_age: Duration) -> Self {
+        Self {
+            max_age,
+            messages: Default::default(),
+        }
+    }
+
+    pub(crate) fn push(&mut self, message: OutboundMessage) {
+        self.expire(Instant::now());
+
+        match message.policy {
+            SendPolicy::Droppable => {}
+            SendPolicy::LatestOnly => {
+                let key = message.coalesce_key();
+
+                match self
+                    .messages
+                    .iter()
+                    .position(|q| q.policy == SendPolicy::LatestOnly && q.coalesce_key() == key)
+                {
+                    Some(index) => self.messages[index] = message,
+                    None => self.messages.push_back(message),
+                }
+            }
+            SendPolicy::Reliable => self.messages.push_back(message),
+        }
+    }
+
+    /// Puts messages taken back from the outbound queue ahead of anything buffered since
+    pub(crate) fn requeue(&mut self, messages: Vec<OutboundMessage>) {
+        for message in messages.into_iter().rev() {
+            if message.policy != SendPolicy::Droppable {
+                self.messages.push_front(message);
+            }
+        }
+
+        self.expire(Instant::now());
+    }
+
+    /// Empties the buffer, returning the messages younger than the max age
+    pub(crate) fn drain(&mut self) -> Vec<OutboundMessage> {
+        self.expire(Instant::now());
+        self.messages.drain(..).collect()
+    }
+
+    fn expire(&mut self, now: Instant) {
+        let before = self.messages.len();
+
+        self.messages
+            .retain(|m| now.saturating_duration_since(m.queued_at) <= self.max_age);
+
+        let expired = before - self.messages.len();
+
+        if expired > 0 {
+            debug!("Discarded {} expired offline messages", expired);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+    use crate::message::payload::BroadcastPayload;
+
+    fn control(topic: &str) -> OutboundMessage {
+        OutboundMessage::new(
+            RealtimeMessage {
+                event: MessageEvent::PhxJoin,
+                topic: topic.into(),
+                payload: Payload::Empty {},
+                message_ref: None,
+            },
+            SendPolicy::Reliable,
+        )
+    }
+
+    fn track(topic: &str) -> OutboundMessage {
+        OutboundMessage::new(
+            RealtimeMessage {
+                event: MessageEvent::Track,
+                topic: topic.into(),
+                payload: Payload::Empty {},
+                message_ref: None,
+            },
+            SendPolicy::Reliable,
+        )
+    }
+
+    fn broadcast(topic: &str, event: &str, policy: SendPolicy) -> OutboundMessage {
+        OutboundMessage::new(
+            RealtimeMessage {
+                event: MessageEvent::Broadcast,
+                topic: topic.into(),
+                payload: Payload::Broadcast(BroadcastPayload::new(event, HashMap::new())),
+                message_ref: None,
+            },
+            policy,
+        )
+    }
+
+    fn broadcast_event(message: &OutboundMessage) -> &str {
+        match &message.message.payload {
+            Payload::Broadcast(payload) => &payload.event,
+            _ => "",
+        }
+    }
+
+    fn drain(queue: &mut OutboundQueue) -> Vec<(MessageEvent, String)> {
+        std::iter::from_fn(|| queue.pop())
+            .map(|m| {
+                let event = broadcast_event(&m).to_string();
+                (m.message.event, event)
+            })
+            .collect()
+    }
+
+    #[derive(Debug, PartialEq)]
+    enum Manager {
+        Data(u32),
+        Control(u32),
+    }
+
+    impl BoundedMessage for Manager {
+        fn bounded(&self) -> bool {
+            matches!(self, Manager::Data(_))
+        }
+    }
+
+    fn receive_all(rx: &BoundedReceiver<Manager>) -> Vec<Manager> {
+        std::iter::from_fn(|| rx.try_recv().ok()).collect()
+    }
+
+    #[test]
+    fn bounded_drop_oldest_keeps_control_and_order() {
+        let (tx, rx) = bounded_channel(Some(QueueBound::new(2, OverflowPolicy::DropOldest)));
+
+        tx.send(Manager::Control(0)).unwrap();
+        tx.send(Manager::Data(1)).unwrap();
+        tx.send(Manager::Data(2)).unwrap();
+        tx.send(Manager::Control(3)).unwrap();
+        tx.send(Manager::Data(4)).unwrap();
+
+        assert_eq!(tx.len(), 4);
+        assert_eq!(
+            receive_all(&rx),
+            vec![
+                Manager::Control(0),
+                Manager::Data(2),
+                Manager::Control(3),
+                Manager::Data(4),
+            ]
+        );
+
+        // Room again once drained
+        tx.send(Manager::Data(5)).unwrap();
+        tx.send(Manager::Data(6)).unwrap();
+        assert_eq!(receive_all(&rx), vec![Manager::Data(5), Manager::Data(6)]);
+    }
+
+    #[test]
+    fn bounded_drop_newest_and_reject_spare_control() {
+        let (tx, rx) = bounded_channel(Some(QueueBound::new(1, OverflowPolicy::DropNewest)));
+
+        tx.send(Manager::Data(1)).unwrap();
+        tx.send(Manager::Data(2)).unwrap();
+        tx.send(Manager::Control(3)).unwrap();
+
+        assert_eq!(
+            receive_all(&rx),
+            vec![Manager::Data(1), Manager::Control(3)]
+        );
+
+        let (tx, rx) = bounded_channel(Some(QueueBound::new(1, OverflowPolicy::Reject)));
+
+        tx.send(Manager::Data(1)).unwrap();
+        assert_eq!(
+            tx.send(Manager::Data(2)).unwrap_err().into_inner(),
+            Manager::Data(2)
+        );
+        tx.send(Manager::Control(3)).unwrap();
+
+        assert_eq!(
+            receive_all(&rx),
+            vec![Manager::Data(1), Manager::Control(3)]
+        );
+    }
+
+    #[test]
+    fn unbounded_channel_keeps_everything() {
+        let (tx, rx) = bounded_channel(None);
+
+        for i in 0..100 {
+            tx.send(Manager::Data(i)).unwrap();
+        }
+
+        assert_eq!(receive_all(&rx).len(), 100);
+    }
+
+    #[test]
+    fn pops_highest_priority_first_in_order() {
+        let mut queue = OutboundQueue::new(None);
+
+        queue.push(broadcast("a", "drop", SendPolicy::Droppable));
+        queue.push(broadcast("a", "first", SendPolicy::Reliable));
+        queue.push(track("a"));
+        queue.push(broadcast("a", "second", SendPolicy::Reliable));
+        queue.push(control("a"));
+
+        assert_eq!(
+            drain(&mut queue),
+            vec![
+                (MessageEvent::PhxJoin, "".into()),
+                (MessageEvent::Track, "".into()),
+                (MessageEvent::Broadcast, "first".into()),
+                (MessageEvent::Broadcast, "second".into()),
+                (MessageEvent::Broadcast, "drop".into()),
+            ]
+        );
+    }
+
+    #[test]
+    fn latest_only_replaces_queued_message_with_same_key() {
+        let mut queue = OutboundQueue::new(None);
+
+        let mut old = broadcast("a", "pos", SendPolicy::LatestOnly);
+        old.message.message_ref = Some("old".into());
+        queue.push(old);
+        queue.push(broadcast("a", "other", SendPolicy::LatestOnly));
+        queue.push(broadcast("b", "pos", SendPolicy::LatestOnly));
+
+        let mut new = broadcast("a", "pos", SendPolicy::LatestOnly);
+        new.message.message_ref = Some("new".into());
+        assert_eq!(queue.push(new), 0);
+
+        assert_eq!(queue.len(), 3);
+
+        let first = queue.pop().unwrap();
+        assert_eq!(first.message.message_ref.as_deref(), Some("new"));
+    }
+
+    #[test]
+    fn drop_oldest_evicts_lowest_priority_and_never_control() {
+        let mut queue = OutboundQueue::new(Some(QueueBound::new(2, OverflowPolicy::DropOldest)));
+
+        queue.push(broadcast("a", "reliable", SendPolicy::Reliable));
+        queue.push(broadcast("a", "drop", SendPolicy::Droppable));
+        assert_eq!(queue.push(control("a")), 0);
+
+        // Full, the droppable message makes room
+        assert_eq!(queue.push(track("a")), 1);
+
+        // A lower priority message than anything queued is discarded itself
+        assert_eq!(queue.push(broadcast("a", "late", SendPolicy::Droppable)), 1);
+
+        assert_eq!(
+            drain(&mut queue),
+            vec![
+                (MessageEvent::PhxJoin, "".into()),
+                (MessageEvent::Track, "".into()),
+                (MessageEvent::Broadcast, "reliable".into()),
+            ]
+        );
+    }
+
+    #[test]
+    fn drop_newest_discards_incoming_message() {
+        let mut queue = OutboundQueue::new(Some(QueueBound::new(1, OverflowPolicy::DropNewest)));
+
+        queue.push(broadcast("a", "first", SendPolicy::Reliable));
+        assert_eq!(
+            queue.push(broadcast("a", "second", SendPolicy::Reliable)),
+            1
+        );
+
+        assert_eq!(
+            drain(&mut queue),
+            vec![(MessageEvent::Broadcast, "first".into())]
+        );
+    }
+
+    #[test]
+    fn drop_droppable_keeps_latest_only() {
+        let mut queue = OutboundQueue::new(None);
+
+        queue.push(broadcast("a", "drop", SendPolicy::Droppable));
+        queue.push(broadcast("a", "pos", SendPolicy::LatestOnly));
+
+        assert_eq!(queue.drop_droppable(), 1);
+        assert_eq!(
+            drain(&mut queue),
+            vec![(MessageEvent::Broadcast, "pos".into())]
+        );
+    }
+
+    #[test]
+    fn take_topic_leaves_control_and_other_topics() {
+        let mut queue = OutboundQueue::new(None);
+
+        queue.push(control("a"));
+        queue.push(track("a"));
+        queue.push(broadcast("a", "x", SendPolicy::Reliable));
+        queue.push(broadcast("b", "y", SendPolicy::Reliable));
+
+        let taken = queue.take_topic("a");
+
+        assert_eq!(taken.len(), 2);
+        assert_eq!(
+            drain(&mut queue),
+            vec![
+                (MessageEvent::PhxJoin, "".into()),
+                (MessageEvent::Broadcast, "y".into()),
+            ]
+        );
+    }
+
+    #[test]
+    fn offline_buffer_replays_in_order_and_coalesces() {
+        let mut buffer = OfflineBuffer::new(Duration::from_secs(10));
+
+        buffer.requeue(vec![
+            broadcast("a", "first", SendPolicy::Reliable),
+            broadcast("a", "skip", SendPolicy::Droppable),
+        ]);
+        buffer.push(broadcast("a", "pos", SendPolicy::LatestOnly));
+        buffer.push(broadcast("a", "skip", SendPolicy::Droppable));
+        buffer.push(broadcast("a", "second", SendPolicy::Reliable));
+        buffer.push(broadcast("a", "pos", SendPolicy::LatestOnly));
+
+        let events: Vec<String> = buffer
+            .drain()
+            .iter()
+            .map(|m| broadcast_event(m).to_string())
+            .collect();
+
+        assert_eq!(events, vec!["first", "pos", "second"]);
+        assert!(buffer.drain().is_empty());
+    }
+
+    #[test]
+    fn offline_buffer_ages_requeued_messages_from_when_they_were_queued() {
+        let mut buffer = OfflineBuffer::new(Duration::from_secs(5));
+
+        let mut stale = broadcast("a", "stale", SendPolicy::Reliable);
+        stale.queued_at = Instant::now() - Duration::from_secs(6);
+        let mut fresh = broadcast("a", "fresh", SendPolicy::Reliable);
+        fresh.queued_at = Instant::now() - Duration::from_secs(4);
+
+        buffer.requeue(vec![stale, fresh]);
+
+        let events: Vec<String> = buffer
+            .drain()
+            .iter()
+            .map(|m| broadcast_event(m).to_string())
+            .collect();
+
+        assert_eq!(events, vec!["fresh"]);
+    }
+
+    #[test]
+    fn push_front_puts_message_back_first() {
+        let mut queue = OutboundQueue::new(None);
+
+        queue.push(broadcast("a", "first", SendPolicy::Reliable));
+        queue.push(broadcast("a", "second", SendPolicy::Reliable));
+
+        let first = queue.pop().unwrap();
+        queue.push_front(first);
+
+        assert_eq!(
+            drain(&mut queue),
+            vec![
+                (MessageEvent::Broadcast, "first".into()),
+                (MessageEvent::Broadcast, "second".into()),
+            ]
+        );
+    }
+}
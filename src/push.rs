@@ -0,0 +1,212 @@
+use std::time::{Duration, Instant};
+
+use bevy::ecs::event::Event;
+use serde_json::Value;
+use uuid::Uuid;
+
+use crate::{
+    channel::RealtimeChannel,
+    message::payload::{Payload, PayloadStatus, ReplyPayload},
+};
+
+/// Channel operation a push was sent for
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum PushKind {
+    Join,
+    Leave,
+    Broadcast,
+    Track,
+    Untrack,
+    AccessToken,
+    /// Push sent through [crate::channel::ChannelManager::push], labelled by the caller
+    Custom(String),
+}
+
+/// Status a push resolves with, used to select [Push::receive] hooks
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ReplyStatus {
+    Ok,
+    Error,
+    Timeout,
+}
+
+/// Outcome of a push, with the server's response where there was one
+#[derive(Debug, Clone, PartialEq)]
+pub enum PushStatus {
+    Ok(Value),
+    Error(Value),
+    Timeout,
+}
+
+impl PushStatus {
+    pub fn reply_status(&self) -> ReplyStatus {
+        match self {
+            PushStatus::Ok(_) => ReplyStatus::Ok,
+            PushStatus::Error(_) => ReplyStatus::Error,
+            PushStatus::Timeout => ReplyStatus::Timeout,
+        }
+    }
+
+    /// Reads the status from a `phx_reply` payload
+    pub(crate) fn from_reply(payload: &Payload) -> Self {
+        match payload {
+            Payload::Reply(ReplyPayload { status, response }) => match status.as_str() {
+                "ok" => PushStatus::Ok(response.clone()),
+                _ => PushStatus::Error(response.clone()),
+            },
+            Payload::Response(join_response) => {
+                let response = serde_json::to_value(&join_response.response).unwrap_or_default();
+
+                match join_response.status {
+                    PayloadStatus::Ok => PushStatus::Ok(response),
+                    PayloadStatus::Error => PushStatus::Error(response),
+                }
+            }
+            _ => PushStatus::Ok(Value::Null),
+        }
+    }
+}
+
+/// Sent for every tracked push on a channel once it gets a reply or times out
+#[derive(Event, Debug, Clone)]
+pub struct PushReply {
+    /// Name of the client, see [crate::client::ClientBuilder::name]
+    pub client: Option<String>,
+    pub topic: String,
+    pub kind: PushKind,
+    /// Ref of the push, see [Push::message_ref]. For broadcasts this is the
+    /// [crate::channel::BroadcastHandle]'s id.
+    pub message_ref: String,
+    pub status: PushStatus,
+}
+
+type PushHook = Box<dyn FnOnce(&mut RealtimeChannel, &PushStatus) + Send>;
+
+/// An outbound channel message awaiting its `phx_reply`, matched by a unique ref.
+///
+/// The timeout starts when the push is created, including while it waits in an offline
+/// buffer. Send it with [crate::channel::ChannelManager::push].
+///
+/// ```
+/// # use std::time::Duration;
+/// # use bevy::log::{info, warn};
+/// # use bevy_realtime::push::*;
+/// let push = Push::new(PushKind::Custom("ping".into()), Duration::from_secs(10))
+///     .receive(ReplyStatus::Ok, |_, _| info!("pinged"))
+///     .receive(ReplyStatus::Timeout, |_, _| warn!("ping timed out"));
+/// ```
+pub struct Push {
+    kind: PushKind,
+    message_ref: String,
+    created_at: Instant,
+    timeout: Duration,
+    hooks: Vec<(ReplyStatus, PushHook)>,
+}
+
+impl Push {
+    pub fn new(kind: PushKind, timeout: Duration) -> Self {
+        Self::with_ref(kind, Uuid::new_v4().to_string(), timeout)
+    }
+
+    pub(crate) fn with_ref(kind: PushKind, message_ref: String, timeout: Duration) -> Self {
+        Self {
+            kind,
+            message_ref,
+            created_at: Instant::now(),
+            timeout,
+            hooks: vec![],
+        }
+    }
+
+    /// Adds a hook run when the push resolves with `status`. Hooks run on the client's thread,
+    /// after the [PushReply] is sent.
+    pub fn receive(
+        mut self,
+        status: ReplyStatus,
+        hook: impl FnOnce(&mut RealtimeChannel, &PushStatus) + Send + 'static,
+    ) -> Self {
+        self.hooks.push((status, Box::new(hook)));
+        self
+    }
+
+    pub fn kind(&self) -> &PushKind {
+        &self.kind
+    }
+
+    /// Unique ref the reply is matched by, also set on the [PushReply]
+    pub fn message_ref(&self) -> &str {
+        &self.message_ref
+    }
+
+    pub(crate) fn is_timed_out(&self, now: Instant) -> bool {
+        now.saturating_duration_since(self.created_at) >= self.timeout
+    }
+
+    /// Runs the hooks registered for `status`
+    pub(crate) fn resolve(self, channel: &mut RealtimeChannel, status: &PushStatus) {
+        let reply_status = status.reply_status();
+
+        for (hook_status, hook) in self.hooks {
+            if hook_status == reply_status {
+                hook(channel, status);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn reply_status_is_read_from_payload() {
+        let ok = Payload::Reply(ReplyPayload {
+            response: json!({ "a": 1 }),
+            status: "ok".into(),
+        });
+        let error = Payload::Reply(ReplyPayload {
+            response: json!("nope"),
+            status: "error".into(),
+        });
+
+        assert_eq!(
+            PushStatus::from_reply(&ok),
+            PushStatus::Ok(json!({ "a": 1 }))
+        );
+        assert_eq!(
+            PushStatus::from_reply(&error),
+            PushStatus::Error(json!("nope"))
+        );
+        assert_eq!(PushStatus::Timeout.reply_status(), ReplyStatus::Timeout);
+    }
+
+    #[test]
+    fn pushes_get_unique_refs_and_time_out() {
+        let a = Push::new(PushKind::Track, Duration::from_secs(5));
+        let b = Push::new(PushKind::Track, Duration::from_secs(5));
+
+        assert_ne!(a.message_ref(), b.message_ref());
+        assert_eq!(a.kind(), &PushKind::Track);
+
+        let now = Instant::now();
+        assert!(!a.is_timed_out(now));
+        assert!(a.is_timed_out(now + Duration::from_secs(5)));
+    }
+
+    #[test]
+    fn receive_chains_hooks() {
+        let push = Push::new(PushKind::Join, Duration::from_secs(5))
+            .receive(ReplyStatus::Ok, |_, _| {})
+            .receive(ReplyStatus::Error, |_, _| {})
+            .receive(ReplyStatus::Timeout, |_, _| {});
+
+        let statuses: Vec<ReplyStatus> = push.hooks.iter().map(|(status, _)| *status).collect();
+
+        assert_eq!(
+            statuses,
+            vec![ReplyStatus::Ok, ReplyStatus::Error, ReplyStatus::Timeout]
+        );
+    }
+}
@@ -0,0 +1,111 @@
+use std::time::Instant;
+
+/// Token bucket used to throttle outbound messages.
+///
+/// Holds up to `burst` tokens, refilled continuously at `rate` tokens per second. Each sent
+/// message consumes one token.
+#[derive(Debug, Clone)]
+pub(crate) struct TokenBucket {
+    rate: f64,
+    burst: f64,
+    tokens: f64,
+    last_refill: Instant,
+}
+
+impl TokenBucket {
+    /// Creates a full bucket
+    pub(crate) fn new(rate: usize, burst: usize) -> Self {
+        let burst = burst.max(1) as f64;
+
+        Self {
+            rate: rate as f64,
+            burst,
+            tokens: burst,
+            last_refill: Instant::now(),
+        }
+    }
+
+    fn refill(&mut self, now: Instant) {
+        let elapsed = now.saturating_duration_since(self.last_refill);
+        self.tokens = (self.tokens + elapsed.as_secs_f64() * self.rate).min(self.burst);
+        self.last_refill = now;
+    }
+
+    /// Returns true if a token is available, without consuming it
+    pub(crate) fn ready(&mut self, now: Instant) -> bool {
+        self.refill(now);
+        self.tokens >= 1.0
+    }
+
+    /// Consumes a token if one is available
+    pub(crate) fn try_take(&mut self, now: Instant) -> bool {
+        if !self.ready(now) {
+            return false;
+        }
+
+        self.tokens -= 1.0;
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    #[test]
+    fn starts_full_and_allows_a_burst() {
+        let mut bucket = TokenBucket::new(10, 3);
+        let now = Instant::now();
+
+        assert!(bucket.try_take(now));
+        assert!(bucket.try_take(now));
+        assert!(bucket.try_take(now));
+        assert!(!bucket.try_take(now));
+        assert!(!bucket.ready(now));
+    }
+
+    #[test]
+    fn refills_at_rate() {
+        let mut bucket = TokenBucket::new(10, 1);
+        let start = Instant::now();
+
+        assert!(bucket.try_take(start));
+        assert!(!bucket.try_take(start + Duration::from_millis(50)));
+        assert!(bucket.try_take(start + Duration::from_millis(110)));
+        assert!(!bucket.try_take(start + Duration::from_millis(160)));
+    }
+
+    #[test]
+    fn refill_is_capped_at_burst() {
+        let mut bucket = TokenBucket::new(10, 2);
+        let start = Instant::now();
+
+        assert!(bucket.try_take(start));
+        assert!(bucket.try_take(start));
+
+        let later = start + Duration::from_secs(60);
+        assert!(bucket.try_take(later));
+        assert!(bucket.try_take(later));
+        assert!(!bucket.try_take(later));
+    }
+
+    #[test]
+    fn ready_does_not_consume() {
+        let mut bucket = TokenBucket::new(1, 1);
+        let now = Instant::now();
+
+        assert!(bucket.ready(now));
+        assert!(bucket.ready(now));
+        assert!(bucket.try_take(now));
+        assert!(!bucket.ready(now));
+    }
+
+    #[test]
+    fn zero_burst_still_holds_one_token() {
+        let mut bucket = TokenBucket::new(1, 0);
+
+        assert!(bucket.try_take(Instant::now()));
+    }
+}
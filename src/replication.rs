@@ -0,0 +1,760 @@
+use std::{any::type_name, collections::HashMap, marker::PhantomData, time::Duration};
+
+use bevy::{ecs::system::EntityCommands, prelude::*, time::common_conditions::on_timer};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_json::{Map, Value};
+use uuid::Uuid;
+
+use crate::{
+    channel::ChannelBuilder,
+    client_ready,
+    interest::{InterestCell, InterestGrid, MirrorCells},
+    interpolation::{Interpolate, SnapshotBuffer},
+    message::payload::{BroadcastConfig, BroadcastPayload, PresenceConfig},
+    presence::{PrescenceTrack, PresenceEvent, PresenceState},
+    BevyChannelBuilder, BuildChannel, Channel, Client,
+};
+
+pub(crate) const REPLICATE_EVENT: &str = "replicate";
+
+/// Stable id of a replicated entity, shared by the owner and all of its mirrors
+#[derive(
+    Component, Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize,
+)]
+pub struct NetworkId(pub Uuid);
+
+impl Default for NetworkId {
+    fn default() -> Self {
+        Self(Uuid::new_v4())
+    }
+}
+
+/// Marks `T` on a locally owned entity for replication to other clients.
+///
+/// `T` must be registered with [ReplicationAppExt::replicate].
+#[derive(Component)]
+#[require(NetworkId)]
+pub struct Replicated<T: Component>(PhantomData<fn() -> T>);
+
+impl<T: Component> Default for Replicated<T> {
+    fn default() -> Self {
+        Self(PhantomData)
+    }
+}
+
+/// Presence key of the client owning a mirrored entity
+#[derive(Component, Debug, Clone, PartialEq, Eq, Hash)]
+pub struct RemoteOwner(pub String);
+
+/// Presence key of this client, used as the owner of its replicated entities
+#[derive(Resource, Debug, Clone, PartialEq, Eq, Deref)]
+pub struct ReplicationOwner(pub String);
+
+/// Maps [NetworkId]s to the local entities mirroring them
+#[derive(Resource, Debug, Default)]
+pub struct NetworkEntities(HashMap<NetworkId, Entity>);
+
+impl NetworkEntities {
+    pub fn get(&self, id: &NetworkId) -> Option<Entity> {
+        self.0.get(id).copied()
+    }
+
+    pub(crate) fn remove(&mut self, id: &NetworkId) -> Option<Entity> {
+        self.0.remove(id)
+    }
+}
+
+#[derive(SystemSet, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ReplicationSet {
+    /// Changed replicated components are gathered into the next batch
+    Collect,
+    /// The batch is broadcast
+    Send,
+}
+
+/// Replicates [Replicated] components over broadcasts on a dedicated channel.
+///
+/// Changed components are batched and broadcast every `send_interval`. Remote clients spawn a
+/// mirror of each entity keyed by its [NetworkId], tagged with the owner's [RemoteOwner]. Each
+/// client tracks presence with its owner key, so mirrors are despawned when their owner leaves
+/// and a full snapshot is sent when someone joins. Requires an unnamed [crate::RealtimePlugin].
+pub struct ReplicationPlugin {
+    topic: String,
+    owner_key: String,
+    send_interval: Duration,
+}
+
+impl ReplicationPlugin {
+    pub fn new(topic: impl Into<String>) -> Self {
+        Self {
+            topic: topic.into(),
+            owner_key: Uuid::new_v4().to_string(),
+            send_interval: Duration::from_millis(50),
+        }
+    }
+
+    /// Set the presence key identifying this client as an owner.
+    /// Default: random
+    pub fn owner_key(mut self, owner_key: impl Into<String>) -> Self {
+        self.owner_key = owner_key.into();
+        self
+    }
+
+    /// Set the time between batches.
+    /// Default: 50ms
+    pub fn send_interval(mut self, send_interval: Duration) -> Self {
+        self.send_interval = send_interval;
+        self
+    }
+}
+
+impl Plugin for ReplicationPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(ReplicationOwner(self.owner_key.clone()))
+            .insert_resource(ReplicationTopic(self.topic.clone()))
+            .init_resource::<NetworkEntities>()
+            .init_resource::<ReplicationBatch>()
+            .init_resource::<ReplicationRegistry>()
+            .init_resource::<OwnedEntities>()
+            .configure_sets(
+                PostUpdate,
+                (ReplicationSet::Collect, ReplicationSet::Send).chain(),
+            )
+            .add_systems(Startup, setup_replication_channel)
+            .add_systems(
+                PostUpdate,
+                (
+                    track_owned_entities.in_set(ReplicationSet::Collect),
+                    track_entity_cells.before(ReplicationSet::Collect),
+                    send_replication_batch
+                        .in_set(ReplicationSet::Send)
+                        .run_if(client_ready)
+                        .run_if(on_timer(self.send_interval)),
+                ),
+            );
+    }
+}
+
+pub trait ReplicationAppExt {
+    /// Registers `T` for replication under its type name
+    fn replicate<T>(&mut self) -> &mut Self
+    where
+        T: Component + Serialize + DeserializeOwned;
+
+    /// Registers `T` for replication under `name`, which must match on every client
+    fn replicate_as<T>(&mut self, name: impl Into<String>) -> &mut Self
+    where
+        T: Component + Serialize + DeserializeOwned;
+
+    /// Registers `T` for replication under its type name, buffering received values in a
+    /// [SnapshotBuffer<T>] on each mirror instead of writing `T` directly.
+    ///
+    /// Mirrors without a buffer get a clone of `buffer`. Add
+    /// [crate::interpolation::SnapshotInterpolationPlugin] for [Transform], or
+    /// [crate::interpolation::interpolate_snapshots::<T>] to `Update` for other components, to
+    /// render the buffered values.
+    fn replicate_interpolated<T>(&mut self, buffer: SnapshotBuffer<T>) -> &mut Self
+    where
+        T: Component + Interpolate + Serialize + DeserializeOwned;
+
+    /// [ReplicationAppExt::replicate_interpolated] under `name`, which must match on every
+    /// client
+    fn replicate_interpolated_as<T>(
+        &mut self,
+        name: impl Into<String>,
+        buffer: SnapshotBuffer<T>,
+    ) -> &mut Self
+    where
+        T: Component + Interpolate + Serialize + DeserializeOwned;
+}
+
+impl ReplicationAppExt for App {
+    fn replicate<T>(&mut self) -> &mut Self
+    where
+        T: Component + Serialize + DeserializeOwned,
+    {
+        self.replicate_as::<T>(type_name::<T>())
+    }
+
+    fn replicate_as<T>(&mut self, name: impl Into<String>) -> &mut Self
+    where
+        T: Component + Serialize + DeserializeOwned,
+    {
+        register_replicated::<T>(self, name.into(), Box::new(apply_component::<T>))
+    }
+
+    fn replicate_interpolated<T>(&mut self, buffer: SnapshotBuffer<T>) -> &mut Self
+    where
+        T: Component + Interpolate + Serialize + DeserializeOwned,
+    {
+        self.replicate_interpolated_as::<T>(type_name::<T>(), buffer)
+    }
+
+    fn replicate_interpolated_as<T>(
+        &mut self,
+        name: impl Into<String>,
+        buffer: SnapshotBuffer<T>,
+    ) -> &mut Self
+    where
+        T: Component + Interpolate + Serialize + DeserializeOwned,
+    {
+        register_replicated::<T>(
+            self,
+            name.into(),
+            Box::new(move |entity, value| apply_interpolated::<T>(entity, value, &buffer)),
+        )
+    }
+}
+
+fn register_replicated<T>(app: &mut App, name: String, apply: ApplyFn) -> &mut App
+where
+    T: Component + Serialize,
+{
+    app.world_mut()
+        .get_resource_or_insert_with(ReplicationRegistry::default)
+        .apply
+        .insert(name.clone(), apply);
+
+    app.add_systems(
+        PostUpdate,
+        (move |q: Query<(&NetworkId, Ref<T>), (With<Replicated<T>>, Without<RemoteOwner>)>,
+               batch: ResMut<ReplicationBatch>| { collect_component(&name, q, batch) })
+        .in_set(ReplicationSet::Collect),
+    )
+}
+
+#[derive(Resource, Deref)]
+struct ReplicationTopic(String);
+
+/// Marks the channel used by [ReplicationPlugin]
+#[derive(Component)]
+pub struct ReplicationChannel;
+
+#[derive(Resource, Default)]
+pub(crate) struct ReplicationBatch {
+    entities: HashMap<NetworkId, Map<String, Value>>,
+    despawned: Vec<NetworkId>,
+    /// Send every replicated component, not just changed ones
+    resync: bool,
+    /// Interest cell of each owned entity
+    cells: HashMap<NetworkId, IVec2>,
+    /// Cell each entity moved out of since the last batch. These entities are sent in full,
+    /// as receivers in the new cell haven't seen them.
+    left_cells: HashMap<NetworkId, IVec2>,
+}
+
+impl ReplicationBatch {
+    fn is_empty(&self) -> bool {
+        self.entities.is_empty() && self.despawned.is_empty()
+    }
+}
+
+type ApplyFn = Box<dyn Fn(&mut EntityCommands, Value) + Send + Sync>;
+
+#[derive(Resource, Default)]
+pub(crate) struct ReplicationRegistry {
+    apply: HashMap<String, ApplyFn>,
+}
+
+/// Locally owned entities, kept to report despawns
+#[derive(Resource, Default)]
+struct OwnedEntities(HashMap<Entity, NetworkId>);
+
+#[derive(Serialize, Deserialize)]
+struct ReplicationMessage {
+    owner: String,
+    /// Interest cell of the channel the message was sent on
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    cell: Option<[i32; 2]>,
+    #[serde(default)]
+    entities: HashMap<NetworkId, Map<String, Value>>,
+    #[serde(default)]
+    despawned: Vec<NetworkId>,
+}
+
+fn apply_component<T: Component + DeserializeOwned>(entity: &mut EntityCommands, value: Value) {
+    match serde_json::from_value::<T>(value) {
+        Ok(component) => {
+            entity.insert(component);
+        }
+        Err(e) => debug!("Failed to deserialize {}: {}", type_name::<T>(), e),
+    }
+}
+
+/// Pushes a received `T` into the mirror's [SnapshotBuffer<T>], stamped with the current [Time]
+fn apply_interpolated<T>(entity: &mut EntityCommands, value: Value, buffer: &SnapshotBuffer<T>)
+where
+    T: Component + Interpolate + DeserializeOwned,
+{
+    let component = match serde_json::from_value::<T>(value) {
+        Ok(component) => component,
+        Err(e) => {
+            debug!("Failed to deserialize {}: {}", type_name::<T>(), e);
+            return;
+        }
+    };
+
+    let buffer = buffer.clone();
+
+    entity.queue(move |mut entity: EntityWorldMut| {
+        let now = entity.world().resource::<Time>().elapsed_secs_f64();
+
+        match entity.get_mut::<SnapshotBuffer<T>>() {
+            Some(mut buffer) => buffer.push(now, component),
+            None => {
+                let mut buffer = buffer;
+                buffer.push(now, component.clone());
+
+                // The first sample is shown as is until the next one arrives
+                entity.insert((component, buffer));
+            }
+        }
+    });
+}
+
+fn collect_component<T: Component + Serialize>(
+    name: &str,
+    q: Query<(&NetworkId, Ref<T>), (With<Replicated<T>>, Without<RemoteOwner>)>,
+    mut batch: ResMut<ReplicationBatch>,
+) {
+    let resync = batch.resync;
+
+    for (id, component) in q.iter() {
+        if !resync && !component.is_changed() && !batch.left_cells.contains_key(id) {
+            continue;
+        }
+
+        match serde_json::to_value(&*component) {
+            Ok(value) => {
+                batch
+                    .entities
+                    .entry(*id)
+                    .or_default()
+                    .insert(name.into(), value);
+            }
+            Err(e) => debug!("Failed to serialize {}: {}", name, e),
+        }
+    }
+}
+
+fn track_owned_entities(
+    added: Query<(Entity, &NetworkId), (Added<NetworkId>, Without<RemoteOwner>)>,
+    mut removed: RemovedComponents<NetworkId>,
+    mut owned: ResMut<OwnedEntities>,
+    mut batch: ResMut<ReplicationBatch>,
+) {
+    for (entity, id) in added.iter() {
+        owned.0.insert(entity, *id);
+    }
+
+    for entity in removed.read() {
+        if let Some(id) = owned.0.remove(&entity) {
+            batch.entities.remove(&id);
+            batch.despawned.push(id);
+        }
+    }
+}
+
+/// Records the interest cell of each owned entity, and the cell it left if it moved
+fn track_entity_cells(
+    mut batch: ResMut<ReplicationBatch>,
+    grid: Option<Res<InterestGrid>>,
+    positions: Query<(&NetworkId, &GlobalTransform), Without<RemoteOwner>>,
+) {
+    let Some(grid) = grid else {
+        return;
+    };
+
+    for (id, transform) in positions.iter() {
+        let cell = grid.cell_of(transform.translation());
+
+        match batch.cells.insert(*id, cell) {
+            Some(last) if last != cell => {
+                batch.left_cells.entry(*id).or_insert(last);
+            }
+            _ => {}
+        }
+    }
+}
+
+fn send_replication_batch(
+    mut batch: ResMut<ReplicationBatch>,
+    owner: Res<ReplicationOwner>,
+    q: Query<(&Channel, Option<&InterestCell>), With<ReplicationChannel>>,
+    grid: Option<Res<InterestGrid>>,
+) {
+    batch.resync = false;
+    let left_cells = std::mem::take(&mut batch.left_cells);
+
+    if batch.is_empty() {
+        return;
+    }
+
+    let entities = std::mem::take(&mut batch.entities);
+    let despawned = std::mem::take(&mut batch.despawned);
+
+    // With interest management an entity is only sent to its cell, and to the cell it just
+    // left so mirrors there see it move out of interest
+    let cells: HashMap<NetworkId, Vec<IVec2>> = batch
+        .cells
+        .iter()
+        .map(|(id, cell)| {
+            let cells = match left_cells.get(id) {
+                Some(left) if left != cell => vec![*cell, *left],
+                _ => vec![*cell],
+            };
+
+            (*id, cells)
+        })
+        .collect();
+
+    for id in despawned.iter() {
+        batch.cells.remove(id);
+    }
+
+    for (channel, interest_cell) in q.iter() {
+        let entities = match (&grid, interest_cell) {
+            (Some(_), Some(interest_cell)) => entities
+                .iter()
+                .filter(|(id, _)| {
+                    cells
+                        .get(id)
+                        .is_none_or(|cells| cells.contains(&interest_cell.0))
+                })
+                .map(|(id, components)| (*id, components.clone()))
+                .collect(),
+            // Updates go through the cell channels, this one only reports despawns
+            (Some(_), None) => HashMap::new(),
+            (None, _) => entities.clone(),
+        };
+
+        let message = ReplicationMessage {
+            owner: owner.0.clone(),
+            cell: interest_cell.map(|cell| cell.0.to_array()),
+            entities,
+            despawned: despawned.clone(),
+        };
+
+        if message.entities.is_empty() && message.despawned.is_empty() {
+            continue;
+        }
+
+        let Ok(Value::Object(payload)) = serde_json::to_value(message) else {
+            continue;
+        };
+
+        let _ = channel.broadcast(BroadcastPayload {
+            event: REPLICATE_EVENT.into(),
+            payload: payload.into_iter().collect(),
+            ..Default::default()
+        });
+    }
+}
+
+fn setup_replication_channel(world: &mut World) {
+    let build_channel = world.register_system(build_replication_channel);
+    let client = world.resource::<Client>();
+
+    if client.channel(build_channel).is_err() {
+        error!("Failed to create replication channel");
+    }
+}
+
+fn build_replication_channel(
+    mut channel_builder: In<ChannelBuilder>,
+    mut commands: Commands,
+    topic: Res<ReplicationTopic>,
+    owner: Res<ReplicationOwner>,
+) {
+    let on_replicate = commands.register_system(on_replicate);
+    let on_join = commands.register_system(on_owner_join);
+    let on_leave = commands.register_system(on_owner_leave);
+
+    channel_builder
+        .topic(topic.as_str())
+        .set_broadcast_config(BroadcastConfig {
+            broadcast_self: false,
+            ack: false,
+        })
+        .set_presence_config(PresenceConfig {
+            key: Some(owner.0.clone()),
+        })
+        .on_broadcast(REPLICATE_EVENT, on_replicate)
+        .on_presence(PresenceEvent::Join, on_join)
+        .on_presence(PresenceEvent::Leave, on_leave);
+
+    let mut track = HashMap::new();
+    track.insert("owner".into(), owner.0.clone().into());
+
+    commands.spawn((
+        BevyChannelBuilder(channel_builder.0),
+        BuildChannel,
+        ReplicationChannel,
+        PrescenceTrack { payload: track },
+    ));
+}
+
+pub(crate) fn on_replicate(
+    In(payload): In<HashMap<String, Value>>,
+    mut commands: Commands,
+    registry: Res<ReplicationRegistry>,
+    owner: Res<ReplicationOwner>,
+    mut entities: ResMut<NetworkEntities>,
+) {
+    let message = match serde_json::from_value::<ReplicationMessage>(Value::Object(
+        payload.into_iter().collect(),
+    )) {
+        Ok(message) => message,
+        Err(e) => {
+            debug!("Malformed replication message: {}", e);
+            return;
+        }
+    };
+
+    if message.owner == owner.0 {
+        return;
+    }
+
+    for id in message.despawned {
+        if let Some(entity) = entities.0.remove(&id) {
+            commands.entity(entity).despawn_recursive();
+        }
+    }
+
+    for (id, components) in message.entities {
+        let entity = *entities.0.entry(id).or_insert_with(|| {
+            commands
+                .spawn((id, RemoteOwner(message.owner.clone())))
+                .id()
+        });
+
+        let mut entity = commands.entity(entity);
+
+        if let Some(cell) = message.cell {
+            let cell = IVec2::from_array(cell);
+
+            entity.queue(
+                move |mut entity: EntityWorldMut| match entity.get_mut::<MirrorCells>() {
+                    Some(mut cells) => {
+                        cells.0.insert(cell);
+                    }
+                    None => {
+                        entity.insert(MirrorCells([cell].into()));
+                    }
+                },
+            );
+        }
+
+        for (name, value) in components {
+            match registry.apply.get(&name) {
+                Some(apply) => apply(&mut entity, value),
+                None => debug!("Unregistered replicated component {}", name),
+            }
+        }
+    }
+}
+
+pub(crate) fn on_owner_join(
+    In((key, _, _)): In<(String, PresenceState, PresenceState)>,
+    owner: Res<ReplicationOwner>,
+    mut batch: ResMut<ReplicationBatch>,
+) {
+    if key != owner.0 {
+        batch.resync = true;
+    }
+}
+
+fn on_owner_leave(
+    In((key, _, _)): In<(String, PresenceState, PresenceState)>,
+    mut commands: Commands,
+    q: Query<(Entity, &NetworkId, &RemoteOwner)>,
+    mut entities: ResMut<NetworkEntities>,
+) {
+    for (entity, id, remote_owner) in q.iter() {
+        if remote_owner.0 == key {
+            entities.0.remove(id);
+            commands.entity(entity).despawn_recursive();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::{
+        ecs::system::RunSystemOnce,
+        time::{TimePlugin, TimeUpdateStrategy},
+    };
+
+    use super::*;
+    use crate::interest::InterestPlane;
+
+    #[derive(Component, Serialize, Deserialize, Clone, Debug, PartialEq)]
+    struct Position(f32);
+
+    impl Interpolate for Position {
+        fn interpolate(&self, other: &Self, t: f32) -> Self {
+            Position(self.0.interpolate(&other.0, t))
+        }
+    }
+
+    fn app() -> App {
+        let mut app = App::new();
+        app.add_plugins(TimePlugin)
+            .insert_resource(TimeUpdateStrategy::ManualDuration(Duration::from_millis(
+                25,
+            )))
+            .insert_resource(ReplicationOwner("me".into()))
+            .init_resource::<NetworkEntities>()
+            .init_resource::<ReplicationBatch>()
+            .add_systems(
+                Update,
+                crate::interpolation::interpolate_snapshots::<Position>,
+            );
+        app
+    }
+
+    fn replicate(app: &mut App, id: NetworkId, position: f32) {
+        let message = ReplicationMessage {
+            owner: "them".into(),
+            cell: None,
+            entities: HashMap::from([(
+                id,
+                Map::from_iter([(
+                    "position".into(),
+                    serde_json::to_value(Position(position)).unwrap(),
+                )]),
+            )]),
+            despawned: vec![],
+        };
+
+        let Ok(Value::Object(payload)) = serde_json::to_value(message) else {
+            unreachable!();
+        };
+
+        app.world_mut()
+            .run_system_once_with(payload.into_iter().collect(), on_replicate)
+            .unwrap();
+    }
+
+    fn owned(app: &mut App, x: f32) -> Entity {
+        app.world_mut()
+            .spawn((
+                Position(0.0),
+                Replicated::<Position>::default(),
+                GlobalTransform::from_xyz(x, 0.0, 0.0),
+            ))
+            .id()
+    }
+
+    fn now(app: &App) -> f64 {
+        app.world().resource::<Time>().elapsed_secs_f64()
+    }
+
+    #[test]
+    fn plain_components_are_written_to_mirrors() {
+        let mut app = app();
+        app.replicate_as::<Position>("position");
+
+        let id = NetworkId::default();
+
+        replicate(&mut app, id, 1.0);
+        replicate(&mut app, id, 2.0);
+
+        let entity = app.world().resource::<NetworkEntities>().get(&id).unwrap();
+
+        assert_eq!(app.world().get::<Position>(entity), Some(&Position(2.0)));
+        assert_eq!(
+            app.world().get::<RemoteOwner>(entity),
+            Some(&RemoteOwner("them".into()))
+        );
+        assert!(app
+            .world()
+            .get::<SnapshotBuffer<Position>>(entity)
+            .is_none());
+    }
+
+    #[test]
+    fn interpolated_components_are_buffered() {
+        let mut app = app();
+        app.replicate_interpolated_as::<Position>(
+            "position",
+            SnapshotBuffer::new().delay(Duration::from_millis(100)),
+        );
+
+        let id = NetworkId::default();
+
+        app.update();
+        let first = now(&app);
+        replicate(&mut app, id, 0.0);
+
+        let entity = app.world().resource::<NetworkEntities>().get(&id).unwrap();
+
+        for _ in 0..4 {
+            app.update();
+        }
+
+        let second = now(&app);
+        replicate(&mut app, id, 10.0);
+
+        // The new value only lands in the buffer
+        assert_eq!(app.world().get::<Position>(entity), Some(&Position(0.0)));
+        assert_eq!(
+            app.world()
+                .get::<SnapshotBuffer<Position>>(entity)
+                .unwrap()
+                .len(),
+            2
+        );
+
+        for _ in 0..4 {
+            app.update();
+
+            let target = now(&app) - 0.1;
+            let expected = (10.0 * (target - first) / (second - first)).clamp(0.0, 10.0) as f32;
+            let position = app.world().get::<Position>(entity).unwrap();
+
+            assert!(
+                (position.0 - expected).abs() < 1e-3,
+                "{} != {}",
+                position.0,
+                expected
+            );
+        }
+    }
+
+    #[test]
+    fn cell_changes_resync_only_the_moved_entity() {
+        let mut app = app();
+        app.insert_resource(InterestGrid::new(10.0, InterestPlane::XY))
+            .replicate_as::<Position>("position")
+            .add_systems(
+                PostUpdate,
+                track_entity_cells.before(ReplicationSet::Collect),
+            );
+
+        let moved = owned(&mut app, 1.0);
+        let still = owned(&mut app, 2.0);
+
+        app.update();
+
+        let [moved_id, still_id] =
+            [moved, still].map(|entity| *app.world().get::<NetworkId>(entity).unwrap());
+
+        app.world_mut()
+            .resource_mut::<ReplicationBatch>()
+            .entities
+            .clear();
+
+        *app.world_mut().get_mut::<GlobalTransform>(moved).unwrap() =
+            GlobalTransform::from_xyz(15.0, 0.0, 0.0);
+        app.update();
+
+        let batch = app.world().resource::<ReplicationBatch>();
+
+        assert!(!batch.resync);
+        assert!(batch.entities[&moved_id].contains_key("position"));
+        assert!(!batch.entities.contains_key(&still_id));
+        assert_eq!(batch.left_cells.get(&moved_id), Some(&IVec2::ZERO));
+        assert_eq!(batch.cells.get(&moved_id), Some(&IVec2::new(1, 0)));
+    }
+}
@@ -0,0 +1,683 @@
+use std::{
+    collections::HashMap,
+    fmt::Display,
+    marker::PhantomData,
+    time::{Duration, Instant},
+};
+
+use bevy::{
+    ecs::system::{SystemId, SystemParam},
+    prelude::*,
+};
+use serde::{de::DeserializeOwned, Serialize};
+use serde_json::Value;
+use uuid::Uuid;
+
+pub use bevy_realtime_derive::RpcRequest;
+
+use crate::{
+    channel::ChannelBuilder,
+    message::payload::{BroadcastConfig, BroadcastPayload, PresenceConfig},
+    presence::PrescenceTrack,
+    BevyChannelBuilder, BuildChannel, Channel, Client,
+};
+
+/// A request that can be called on another peer with [RpcCaller]. Derive it, implement it by
+/// hand or use [crate::rpc_request].
+///
+/// The derive takes the response type and optionally the name, which defaults to the type's
+/// name.
+///
+/// ```
+/// # use bevy_realtime::rpc::RpcRequest;
+/// # use serde::{Deserialize, Serialize};
+/// #[derive(Serialize, Deserialize, RpcRequest)]
+/// #[rpc(response = u32, name = "spawn_enemy")]
+/// struct SpawnEnemy {
+///     kind: String,
+/// }
+///
+/// #[derive(Serialize, Deserialize)]
+/// struct Ping;
+///
+/// impl RpcRequest for Ping {
+///     const NAME: &'static str = "ping";
+///     type Response = ();
+/// }
+/// ```
+pub trait RpcRequest: Serialize + DeserializeOwned + Send + Sync + 'static {
+    /// Name the request is sent under, must match on every peer
+    const NAME: &'static str;
+    type Response: Serialize + DeserializeOwned + Send + Sync + 'static;
+}
+
+/// Implements [RpcRequest], associating a request type with its response type. The name
+/// defaults to the request type as written.
+///
+/// ```
+/// # use bevy_realtime::rpc_request;
+/// # use serde::{Deserialize, Serialize};
+/// # #[derive(Serialize, Deserialize)]
+/// # struct SpawnEnemy { kind: String }
+/// # #[derive(Serialize, Deserialize)]
+/// # struct Ping;
+/// rpc_request!(SpawnEnemy => u32, "spawn_enemy");
+/// rpc_request!(Ping => ());
+/// ```
+#[macro_export]
+macro_rules! rpc_request {
+    ($request:ty => $response:ty, $name:expr) => {
+        impl $crate::rpc::RpcRequest for $request {
+            const NAME: &'static str = $name;
+            type Response = $response;
+        }
+    };
+    ($request:ty => $response:ty) => {
+        $crate::rpc_request!($request => $response, stringify!($request));
+    };
+}
+
+/// Correlates a call made with [RpcCaller::call] with its [RpcResponse]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct RpcCallId(pub Uuid);
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum RpcError {
+    /// No response arrived within the timeout
+    Timeout,
+    /// The target couldn't handle the request
+    Remote(String),
+    /// The request couldn't be serialized or sent
+    Send,
+}
+
+impl Display for RpcError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RpcError::Timeout => write!(f, "RPC timed out"),
+            RpcError::Remote(e) => write!(f, "RPC failed on the remote peer: {}", e),
+            RpcError::Send => write!(f, "RPC couldn't be sent"),
+        }
+    }
+}
+
+impl std::error::Error for RpcError {}
+
+/// Result of a call to `R`, sent once per [RpcCallId]
+#[derive(Event, Debug)]
+pub struct RpcResponse<R: RpcRequest> {
+    pub id: RpcCallId,
+    /// Presence key of the peer the request was sent to
+    pub target: String,
+    pub result: Result<R::Response, RpcError>,
+}
+
+/// Calls [RpcRequest]s on other peers over broadcasts on a dedicated channel.
+///
+/// Requests are addressed by presence key, see [RpcPeer]. Register request types with
+/// [RpcAppExt::add_rpc] to call them and [RpcAppExt::add_rpc_handler] to answer them. Both
+/// must be registered before the app starts. Requires an unnamed [crate::RealtimePlugin].
+pub struct RpcPlugin {
+    topic: String,
+    peer_key: String,
+    timeout: Duration,
+}
+
+impl RpcPlugin {
+    pub fn new(topic: impl Into<String>) -> Self {
+        Self {
+            topic: topic.into(),
+            peer_key: Uuid::new_v4().to_string(),
+            timeout: Duration::from_secs(5),
+        }
+    }
+
+    /// Set the presence key other peers address requests to.
+    /// Default: random
+    pub fn peer_key(mut self, peer_key: impl Into<String>) -> Self {
+        self.peer_key = peer_key.into();
+        self
+    }
+
+    /// Set how long to wait for a response before failing with [RpcError::Timeout].
+    /// Default: 5 seconds
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+}
+
+impl Plugin for RpcPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(RpcPeer(self.peer_key.clone()))
+            .insert_resource(RpcConfig {
+                topic: self.topic.clone(),
+                timeout: self.timeout,
+            })
+            .init_resource::<RpcRegistry>()
+            .add_systems(Startup, setup_rpc_channel);
+    }
+}
+
+/// Presence key of this peer, the target to use when calling it
+#[derive(Resource, Debug, Clone, PartialEq, Eq, Deref)]
+pub struct RpcPeer(pub String);
+
+pub trait RpcAppExt {
+    /// Allows calling `R` with [RpcCaller<R>] and receiving [RpcResponse<R>]
+    fn add_rpc<R: RpcRequest>(&mut self) -> &mut Self;
+
+    /// Answers `R` with `handler`, which gets the caller's presence key and the request
+    fn add_rpc_handler<R: RpcRequest, M>(
+        &mut self,
+        handler: impl IntoSystem<In<(String, R)>, R::Response, M> + 'static,
+    ) -> &mut Self;
+}
+
+impl RpcAppExt for App {
+    fn add_rpc<R: RpcRequest>(&mut self) -> &mut Self {
+        self.world_mut()
+            .get_resource_or_insert_with(RpcRegistry::default)
+            .callbacks
+            .push((reply_event(R::NAME), |commands| {
+                commands.register_system(on_rpc_reply::<R>)
+            }));
+
+        self.add_event::<RpcResponse<R>>()
+            .insert_resource(RpcPending::<R> {
+                calls: HashMap::new(),
+                _request: PhantomData,
+            })
+            .add_systems(Update, expire_rpc_calls::<R>)
+    }
+
+    fn add_rpc_handler<R: RpcRequest, M>(
+        &mut self,
+        handler: impl IntoSystem<In<(String, R)>, R::Response, M> + 'static,
+    ) -> &mut Self {
+        let handler = self.world_mut().register_system(handler);
+
+        self.world_mut()
+            .get_resource_or_insert_with(RpcRegistry::default)
+            .callbacks
+            .push((request_event(R::NAME), |commands| {
+                commands.register_system(on_rpc_request::<R>)
+            }));
+
+        self.insert_resource(RpcHandler::<R>(handler))
+    }
+}
+
+/// Calls `R` on other peers
+#[derive(SystemParam)]
+pub struct RpcCaller<'w, 's, R: RpcRequest> {
+    pending: ResMut<'w, RpcPending<R>>,
+    config: Res<'w, RpcConfig>,
+    peer: Res<'w, RpcPeer>,
+    channels: Query<'w, 's, &'static Channel, With<RpcChannel>>,
+    responses: EventWriter<'w, RpcResponse<R>>,
+}
+
+impl<R: RpcRequest> RpcCaller<'_, '_, R> {
+    /// Sends `request` to the peer with presence key `target`. The result arrives as an
+    /// [RpcResponse<R>] with the returned id, failing with [RpcError::Send] straight away if the
+    /// request couldn't be sent.
+    pub fn call(&mut self, target: impl Into<String>, request: &R) -> RpcCallId {
+        let id = RpcCallId(Uuid::new_v4());
+        let target = target.into();
+
+        let sent = serde_json::to_value(request).is_ok_and(|request| {
+            let mut payload = HashMap::new();
+            payload.insert("id".into(), id.0.to_string().into());
+            payload.insert("from".into(), self.peer.0.clone().into());
+            payload.insert("to".into(), target.clone().into());
+            payload.insert("request".into(), request);
+
+            let mut sent = false;
+
+            for channel in self.channels.iter() {
+                sent |= channel
+                    .broadcast(BroadcastPayload {
+                        event: request_event(R::NAME),
+                        payload: payload.clone(),
+                        ..Default::default()
+                    })
+                    .is_ok();
+            }
+
+            sent
+        });
+
+        if sent {
+            self.pending
+                .calls
+                .insert(id, (target, Instant::now() + self.config.timeout));
+        } else {
+            self.responses.send(RpcResponse {
+                id,
+                target,
+                result: Err(RpcError::Send),
+            });
+        }
+
+        id
+    }
+
+    /// Number of calls awaiting a response
+    pub fn pending(&self) -> usize {
+        self.pending.calls.len()
+    }
+}
+
+type RpcCallbackFn = fn(&mut Commands) -> SystemId<In<HashMap<String, Value>>>;
+
+#[derive(Resource, Default)]
+struct RpcRegistry {
+    callbacks: Vec<(String, RpcCallbackFn)>,
+}
+
+#[derive(Resource)]
+struct RpcConfig {
+    topic: String,
+    timeout: Duration,
+}
+
+#[derive(Resource)]
+struct RpcPending<R: RpcRequest> {
+    calls: HashMap<RpcCallId, (String, Instant)>,
+    _request: PhantomData<fn() -> R>,
+}
+
+#[derive(Resource)]
+struct RpcHandler<R: RpcRequest>(SystemId<In<(String, R)>, R::Response>);
+
+/// Marks the channel used by [RpcPlugin]
+#[derive(Component)]
+pub struct RpcChannel;
+
+fn request_event(name: &str) -> String {
+    format!("rpc:{}", name)
+}
+
+fn reply_event(name: &str) -> String {
+    format!("rpc_reply:{}", name)
+}
+
+fn setup_rpc_channel(world: &mut World) {
+    let build_channel = world.register_system(build_rpc_channel);
+    let client = world.resource::<Client>();
+
+    if client.channel(build_channel).is_err() {
+        error!("Failed to create RPC channel");
+    }
+}
+
+fn build_rpc_channel(
+    mut channel_builder: In<ChannelBuilder>,
+    mut commands: Commands,
+    config: Res<RpcConfig>,
+    registry: Res<RpcRegistry>,
+    peer: Res<RpcPeer>,
+) {
+    channel_builder
+        .topic(&config.topic)
+        .set_broadcast_config(BroadcastConfig {
+            broadcast_self: true,
+            ack: false,
+        })
+        .set_presence_config(PresenceConfig {
+            key: Some(peer.0.clone()),
+        });
+
+    for (event, register) in registry.callbacks.iter() {
+        let callback = register(&mut commands);
+        channel_builder.on_broadcast(event, callback);
+    }
+
+    commands.spawn((
+        BevyChannelBuilder(channel_builder.0),
+        BuildChannel,
+        RpcChannel,
+        PrescenceTrack {
+            payload: HashMap::new(),
+        },
+    ));
+}
+
+fn on_rpc_request<R: RpcRequest>(In(payload): In<HashMap<String, Value>>, world: &mut World) {
+    let (Some(id), Some(from), Some(to), Some(request)) = (
+        payload.get("id").cloned(),
+        payload.get("from").and_then(Value::as_str),
+        payload.get("to").and_then(Value::as_str),
+        payload.get("request"),
+    ) else {
+        return;
+    };
+
+    if to != world.resource::<RpcPeer>().0 {
+        return;
+    }
+
+    let result = match serde_json::from_value::<R>(request.clone()) {
+        Ok(request) => {
+            let handler = world.resource::<RpcHandler<R>>().0;
+
+            world
+                .run_system_with_input(handler, (from.to_string(), request))
+                .map_err(|e| e.to_string())
+                .and_then(|response| serde_json::to_value(response).map_err(|e| e.to_string()))
+        }
+        Err(e) => Err(e.to_string()),
+    };
+
+    let mut reply = HashMap::new();
+    reply.insert("id".into(), id);
+    reply.insert("to".into(), from.into());
+
+    match result {
+        Ok(response) => reply.insert("response".into(), response),
+        Err(e) => reply.insert("error".into(), e.into()),
+    };
+
+    let mut q = world.query_filtered::<&Channel, With<RpcChannel>>();
+
+    for channel in q.iter(world) {
+        let _ = channel.broadcast(BroadcastPayload {
+            event: reply_event(R::NAME),
+            payload: reply.clone(),
+            ..Default::default()
+        });
+    }
+}
+
+fn on_rpc_reply<R: RpcRequest>(
+    In(payload): In<HashMap<String, Value>>,
+    peer: Res<RpcPeer>,
+    mut pending: ResMut<RpcPending<R>>,
+    mut responses: EventWriter<RpcResponse<R>>,
+) {
+    if payload.get("to").and_then(Value::as_str) != Some(peer.0.as_str()) {
+        return;
+    }
+
+    let Some(id) = payload
+        .get("id")
+        .and_then(Value::as_str)
+        .and_then(|id| Uuid::parse_str(id).ok())
+        .map(RpcCallId)
+    else {
+        return;
+    };
+
+    let Some((target, _)) = pending.calls.remove(&id) else {
+        return;
+    };
+
+    let result = match (payload.get("response"), payload.get("error")) {
+        (Some(response), _) => serde_json::from_value::<R::Response>(response.clone())
+            .map_err(|e| RpcError::Remote(e.to_string())),
+        (None, Some(error)) => Err(RpcError::Remote(
+            error.as_str().unwrap_or_default().to_string(),
+        )),
+        (None, None) => Err(RpcError::Remote("Empty response".into())),
+    };
+
+    responses.send(RpcResponse { id, target, result });
+}
+
+fn expire_rpc_calls<R: RpcRequest>(
+    mut pending: ResMut<RpcPending<R>>,
+    mut responses: EventWriter<RpcResponse<R>>,
+) {
+    let now = Instant::now();
+
+    pending.calls.retain(|id, (target, deadline)| {
+        if now < *deadline {
+            return true;
+        }
+
+        responses.send(RpcResponse {
+            id: *id,
+            target: target.clone(),
+            result: Err(RpcError::Timeout),
+        });
+
+        false
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::ecs::system::RunSystemOnce;
+    use serde::Deserialize;
+    use serde_json::json;
+
+    use super::*;
+
+    #[derive(Serialize, Deserialize)]
+    struct SpawnEnemy {
+        kind: String,
+    }
+
+    #[derive(Serialize, Deserialize)]
+    struct Ping;
+
+    rpc_request!(SpawnEnemy => u32, "spawn_enemy");
+    rpc_request!(Ping => ());
+
+    #[derive(Serialize, Deserialize, RpcRequest)]
+    #[rpc(response = String, name = "greet")]
+    struct Greet;
+
+    #[derive(Serialize, Deserialize, RpcRequest)]
+    #[rpc(response = Vec<u8>)]
+    struct Echo(Vec<u8>);
+
+    fn response<R: RpcRequest>(response: R::Response) -> R::Response {
+        response
+    }
+
+    #[test]
+    fn macro_implements_rpc_request() {
+        assert_eq!(SpawnEnemy::NAME, "spawn_enemy");
+        assert_eq!(Ping::NAME, "Ping");
+
+        assert_eq!(response::<SpawnEnemy>(3), 3);
+        response::<Ping>(());
+    }
+
+    #[test]
+    fn derive_implements_rpc_request() {
+        assert_eq!(Greet::NAME, "greet");
+        assert_eq!(Echo::NAME, "Echo");
+
+        assert_eq!(response::<Greet>("hi".into()), "hi");
+        assert_eq!(response::<Echo>(vec![1]), vec![1]);
+    }
+
+    #[derive(Resource, Default)]
+    struct Handled(Vec<(String, String)>);
+
+    fn spawn_enemy(
+        In((from, request)): In<(String, SpawnEnemy)>,
+        mut handled: ResMut<Handled>,
+    ) -> u32 {
+        handled.0.push((from, request.kind));
+        7
+    }
+
+    fn app() -> App {
+        let mut app = App::new();
+        app.insert_resource(RpcPeer("me".into()))
+            .insert_resource(RpcConfig {
+                topic: "rpc".into(),
+                timeout: Duration::from_secs(5),
+            })
+            .init_resource::<Handled>()
+            .add_rpc::<SpawnEnemy>()
+            .add_rpc_handler::<SpawnEnemy, _>(spawn_enemy);
+
+        app
+    }
+
+    /// Registers the broadcast callback the channel would get for `event`
+    fn callback(app: &mut App, event: &str) -> SystemId<In<HashMap<String, Value>>> {
+        let register = app
+            .world()
+            .resource::<RpcRegistry>()
+            .callbacks
+            .iter()
+            .find(|(callback_event, _)| callback_event == event)
+            .map(|(_, register)| *register)
+            .unwrap();
+
+        let world = app.world_mut();
+        let id = register(&mut world.commands());
+        world.flush();
+
+        id
+    }
+
+    fn payload(value: Value) -> HashMap<String, Value> {
+        serde_json::from_value(value).unwrap()
+    }
+
+    fn responses(app: &mut App) -> Vec<RpcResponse<SpawnEnemy>> {
+        app.world_mut()
+            .resource_mut::<Events<RpcResponse<SpawnEnemy>>>()
+            .drain()
+            .collect()
+    }
+
+    fn add_pending(app: &mut App, target: &str, deadline: Instant) -> RpcCallId {
+        let id = RpcCallId(Uuid::new_v4());
+
+        app.world_mut()
+            .resource_mut::<RpcPending<SpawnEnemy>>()
+            .calls
+            .insert(id, (target.into(), deadline));
+
+        id
+    }
+
+    #[test]
+    fn requests_dispatch_to_handler_only_when_addressed_here() {
+        let mut app = app();
+        let on_request = callback(&mut app, "rpc:spawn_enemy");
+
+        for to in ["someone else", "me"] {
+            app.world_mut()
+                .run_system_with_input(
+                    on_request,
+                    payload(json!({
+                        "id": Uuid::new_v4().to_string(),
+                        "from": "caller",
+                        "to": to,
+                        "request": { "kind": "orc" },
+                    })),
+                )
+                .unwrap();
+        }
+
+        assert_eq!(
+            app.world().resource::<Handled>().0,
+            vec![("caller".to_string(), "orc".to_string())]
+        );
+    }
+
+    #[test]
+    fn replies_resolve_the_matching_call() {
+        let mut app = app();
+        let on_reply = callback(&mut app, "rpc_reply:spawn_enemy");
+        let id = add_pending(&mut app, "target", Instant::now() + Duration::from_secs(5));
+
+        let replies = [
+            // For another peer
+            json!({ "id": id.0.to_string(), "to": "someone else", "response": 1 }),
+            // Unknown call
+            json!({ "id": Uuid::new_v4().to_string(), "to": "me", "response": 2 }),
+            json!({ "id": id.0.to_string(), "to": "me", "response": 3 }),
+            // Already resolved
+            json!({ "id": id.0.to_string(), "to": "me", "response": 4 }),
+        ];
+
+        for reply in replies {
+            app.world_mut()
+                .run_system_with_input(on_reply, payload(reply))
+                .unwrap();
+        }
+
+        let responses = responses(&mut app);
+
+        assert_eq!(responses.len(), 1);
+        assert_eq!(responses[0].id, id);
+        assert_eq!(responses[0].target, "target");
+        assert_eq!(responses[0].result, Ok(3));
+        assert!(app
+            .world()
+            .resource::<RpcPending<SpawnEnemy>>()
+            .calls
+            .is_empty());
+    }
+
+    #[test]
+    fn error_replies_are_remote_errors() {
+        let mut app = app();
+        let on_reply = callback(&mut app, "rpc_reply:spawn_enemy");
+        let id = add_pending(&mut app, "target", Instant::now() + Duration::from_secs(5));
+
+        app.world_mut()
+            .run_system_with_input(
+                on_reply,
+                payload(json!({ "id": id.0.to_string(), "to": "me", "error": "no orcs" })),
+            )
+            .unwrap();
+
+        assert_eq!(
+            responses(&mut app)[0].result,
+            Err(RpcError::Remote("no orcs".into()))
+        );
+    }
+
+    #[test]
+    fn calls_past_their_deadline_time_out() {
+        let mut app = app();
+        let expired = add_pending(&mut app, "slow", Instant::now());
+        let waiting = add_pending(&mut app, "fast", Instant::now() + Duration::from_secs(5));
+
+        app.update();
+
+        let responses = responses(&mut app);
+
+        assert_eq!(responses.len(), 1);
+        assert_eq!(responses[0].id, expired);
+        assert_eq!(responses[0].target, "slow");
+        assert_eq!(responses[0].result, Err(RpcError::Timeout));
+
+        let pending = &app.world().resource::<RpcPending<SpawnEnemy>>().calls;
+        assert_eq!(pending.keys().collect::<Vec<_>>(), vec![&waiting]);
+    }
+
+    #[test]
+    fn calls_without_a_channel_fail_to_send() {
+        let mut app = app();
+
+        let id = app
+            .world_mut()
+            .run_system_once(|mut caller: RpcCaller<SpawnEnemy>| {
+                caller.call("target", &SpawnEnemy { kind: "orc".into() })
+            })
+            .unwrap();
+
+        let responses = responses(&mut app);
+
+        assert_eq!(responses.len(), 1);
+        assert_eq!(responses[0].id, id);
+        assert_eq!(responses[0].result, Err(RpcError::Send));
+        assert!(app
+            .world()
+            .resource::<RpcPending<SpawnEnemy>>()
+            .calls
+            .is_empty());
+    }
+}
@@ -0,0 +1,247 @@
+use std::net::TcpStream;
+#[cfg(feature = "rustls")]
+use std::sync::Arc;
+
+use bevy::log::debug;
+use tungstenite::stream::{MaybeTlsStream, Mode};
+
+use crate::client::ConnectError;
+
+/// TLS configuration used when connecting to `wss://` endpoints.
+///
+/// Set with [crate::client::ClientBuilder::tls_config] to trust a private CA or present a client
+/// certificate. When no config is set the default connector of the enabled backend is used.
+#[derive(Clone)]
+pub enum TlsConfig {
+    /// Preconfigured `native-tls` connector
+    #[cfg(feature = "native-tls")]
+    NativeTls(native_tls::TlsConnector),
+    /// `rustls` client config, e.g. built with a custom root store or client auth cert
+    #[cfg(feature = "rustls")]
+    Rustls(Arc<rustls::ClientConfig>),
+}
+
+#[cfg(feature = "native-tls")]
+impl From<native_tls::TlsConnector> for TlsConfig {
+    fn from(value: native_tls::TlsConnector) -> Self {
+        TlsConfig::NativeTls(value)
+    }
+}
+
+#[cfg(feature = "rustls")]
+impl From<Arc<rustls::ClientConfig>> for TlsConfig {
+    fn from(value: Arc<rustls::ClientConfig>) -> Self {
+        TlsConfig::Rustls(value)
+    }
+}
+
+#[cfg(feature = "rustls")]
+impl From<rustls::ClientConfig> for TlsConfig {
+    fn from(value: rustls::ClientConfig) -> Self {
+        TlsConfig::Rustls(Arc::new(value))
+    }
+}
+
+/// Performs a blocking TLS handshake over `stream` with the configured backend, or the default
+/// backend if `config` is [None]. Plain `ws://` streams are returned as is.
+pub(crate) fn wrap_stream(
+    stream: TcpStream,
+    mode: Mode,
+    host: &str,
+    config: Option<&TlsConfig>,
+) -> Result<MaybeTlsStream<TcpStream>, ConnectError> {
+    if let Mode::Plain = mode {
+        return Ok(MaybeTlsStream::Plain(stream));
+    }
+
+    let Some(config) = config else {
+        return wrap_stream_default(stream, host);
+    };
+
+    match *config {
+        #[cfg(feature = "native-tls")]
+        TlsConfig::NativeTls(ref connector) => wrap_native_tls(stream, host, connector),
+        #[cfg(feature = "rustls")]
+        TlsConfig::Rustls(ref client_config) => wrap_rustls(stream, host, client_config.clone()),
+    }
+}
+
+#[cfg(feature = "native-tls")]
+fn wrap_stream_default(
+    stream: TcpStream,
+    host: &str,
+) -> Result<MaybeTlsStream<TcpStream>, ConnectError> {
+    let connector = native_tls::TlsConnector::new().map_err(|e| {
+        debug!("Failed to create TLS connector: {:?}", e);
+        ConnectError::TlsError
+    })?;
+
+    wrap_native_tls(stream, host, &connector)
+}
+
+#[cfg(all(feature = "rustls", not(feature = "native-tls")))]
+fn wrap_stream_default(
+    stream: TcpStream,
+    host: &str,
+) -> Result<MaybeTlsStream<TcpStream>, ConnectError> {
+    wrap_rustls(stream, host, default_rustls_config()?)
+}
+
+#[cfg(not(any(feature = "native-tls", feature = "rustls")))]
+fn wrap_stream_default(
+    _stream: TcpStream,
+    _host: &str,
+) -> Result<MaybeTlsStream<TcpStream>, ConnectError> {
+    debug!("TLS endpoint requested but no TLS backend feature is enabled");
+    Err(ConnectError::TlsError)
+}
+
+#[cfg(feature = "native-tls")]
+fn wrap_native_tls(
+    stream: TcpStream,
+    host: &str,
+    connector: &native_tls::TlsConnector,
+) -> Result<MaybeTlsStream<TcpStream>, ConnectError> {
+    match connector.connect(host, stream) {
+        Ok(stream) => Ok(MaybeTlsStream::NativeTls(stream)),
+        Err(e) => {
+            debug!("TLS handshake failed: {:?}", e);
+            Err(ConnectError::TlsError)
+        }
+    }
+}
+
+#[cfg(feature = "rustls")]
+fn wrap_rustls(
+    mut stream: TcpStream,
+    host: &str,
+    client_config: Arc<rustls::ClientConfig>,
+) -> Result<MaybeTlsStream<TcpStream>, ConnectError> {
+    let Ok(server_name) = rustls::pki_types::ServerName::try_from(host.to_string()) else {
+        return Err(ConnectError::BadHost);
+    };
+
+    let mut connection =
+        rustls::ClientConnection::new(client_config, server_name).map_err(|e| {
+            debug!("Failed to create TLS connection: {:?}", e);
+            ConnectError::TlsError
+        })?;
+
+    while connection.is_handshaking() {
+        if let Err(e) = connection.complete_io(&mut stream) {
+            debug!("TLS handshake failed: {:?}", e);
+            return Err(ConnectError::TlsError);
+        }
+    }
+
+    Ok(MaybeTlsStream::Rustls(rustls::StreamOwned::new(
+        connection, stream,
+    )))
+}
+
+/// Builds a `rustls` config trusting the roots provided by the enabled root features.
+#[cfg(feature = "rustls")]
+pub fn default_rustls_config() -> Result<Arc<rustls::ClientConfig>, ConnectError> {
+    let roots = default_root_store();
+
+    if roots.is_empty() {
+        debug!("No TLS root certificates loaded, enable `rustls-webpki-roots` or `rustls-native-roots`");
+    }
+
+    let config = rustls::ClientConfig::builder_with_provider(Arc::new(
+        rustls::crypto::ring::default_provider(),
+    ))
+    .with_safe_default_protocol_versions()
+    .map_err(|e| {
+        debug!("Invalid TLS protocol configuration: {:?}", e);
+        ConnectError::TlsError
+    })?
+    .with_root_certificates(roots)
+    .with_no_client_auth();
+
+    Ok(Arc::new(config))
+}
+
+#[cfg(feature = "rustls")]
+fn default_root_store() -> rustls::RootCertStore {
+    #[allow(unused_mut)]
+    let mut roots = rustls::RootCertStore::empty();
+
+    #[cfg(feature = "rustls-native-roots")]
+    {
+        let native = rustls_native_certs::load_native_certs();
+
+        for e in native.errors {
+            debug!("Error loading native root certificate: {:?}", e);
+        }
+
+        let (added, ignored) = roots.add_parsable_certificates(native.certs);
+        debug!(
+            "Loaded {} native root certificates, {} ignored",
+            added, ignored
+        );
+    }
+
+    #[cfg(feature = "rustls-webpki-roots")]
+    roots.extend(webpki_roots::TLS_SERVER_ROOTS.iter().cloned());
+
+    roots
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::TcpListener;
+
+    use super::*;
+
+    fn stream_pair() -> (TcpStream, TcpStream) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let (server, _) = listener.accept().unwrap();
+
+        (client, server)
+    }
+
+    #[test]
+    fn wrap_stream_leaves_plain_streams_plain() {
+        let (client, _server) = stream_pair();
+
+        let stream = wrap_stream(client, Mode::Plain, "localhost", None).unwrap();
+
+        assert!(matches!(stream, MaybeTlsStream::Plain(_)));
+    }
+
+    #[cfg(feature = "native-tls")]
+    #[test]
+    fn wrap_stream_ignores_native_tls_config_for_plain_streams() {
+        let (client, _server) = stream_pair();
+        let config = TlsConfig::from(native_tls::TlsConnector::new().unwrap());
+
+        let stream = wrap_stream(client, Mode::Plain, "localhost", Some(&config)).unwrap();
+
+        assert!(matches!(stream, MaybeTlsStream::Plain(_)));
+    }
+
+    #[cfg(feature = "rustls")]
+    #[test]
+    fn wrap_stream_ignores_rustls_config_for_plain_streams() {
+        let (client, _server) = stream_pair();
+        let config = TlsConfig::from(default_rustls_config().unwrap());
+
+        let stream = wrap_stream(client, Mode::Plain, "localhost", Some(&config)).unwrap();
+
+        assert!(matches!(stream, MaybeTlsStream::Plain(_)));
+    }
+
+    #[cfg(feature = "rustls-webpki-roots")]
+    #[test]
+    fn default_root_store_loads_webpki_roots() {
+        assert!(default_root_store().len() >= webpki_roots::TLS_SERVER_ROOTS.len());
+    }
+
+    #[cfg(feature = "rustls-native-roots")]
+    #[test]
+    fn default_root_store_loads_native_roots() {
+        assert!(!default_root_store().is_empty());
+    }
+}